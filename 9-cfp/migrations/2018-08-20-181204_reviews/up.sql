@@ -0,0 +1,8 @@
+CREATE TABLE reviews (
+	id INTEGER PRIMARY KEY NOT NULL,
+	reviewer INTEGER REFERENCES users (id) NOT NULL,
+	talk INTEGER REFERENCES talks (id) NOT NULL,
+	score INTEGER NOT NULL,
+	comments TEXT NOT NULL,
+	UNIQUE (reviewer, talk)
+);
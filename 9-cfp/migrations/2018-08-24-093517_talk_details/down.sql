@@ -0,0 +1,16 @@
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE old_talks (
+	id INTEGER PRIMARY KEY NOT NULL,
+	presenter INTEGER REFERENCES users (id) NOT NULL,
+	title TEXT NOT NULL,
+	status INTEGER NOT NULL,
+	description TEXT NOT NULL
+);
+
+INSERT INTO old_talks (id, presenter, title, status, description)
+	SELECT id, presenter, title, status, description
+	FROM talks;
+
+DROP TABLE talks;
+ALTER TABLE old_talks RENAME TO talks;
@@ -0,0 +1,6 @@
+ALTER TABLE talks ADD COLUMN format INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE talks ADD COLUMN duration INTEGER NOT NULL DEFAULT 30;
+ALTER TABLE talks ADD COLUMN level INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE talks ADD COLUMN outline TEXT NOT NULL DEFAULT '';
+ALTER TABLE talks ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+ALTER TABLE talks ADD COLUMN tags TEXT NOT NULL DEFAULT '';
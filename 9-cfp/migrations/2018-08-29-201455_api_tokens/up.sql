@@ -0,0 +1,8 @@
+CREATE TABLE api_tokens (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER REFERENCES users (id) NOT NULL,
+	name TEXT NOT NULL,
+	token_hash TEXT NOT NULL UNIQUE,
+	created_at TIMESTAMP NOT NULL,
+	last_used_at TIMESTAMP
+);
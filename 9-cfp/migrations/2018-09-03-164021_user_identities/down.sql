@@ -0,0 +1,18 @@
+PRAGMA defer_foreign_keys = ON;
+
+DROP TABLE user_identities;
+
+CREATE TABLE old_users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	github_id TEXT NOT NULL,
+	email TEXT NOT NULL,
+	name TEXT NOT NULL,
+	is_admin BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO old_users (id, github_id, email, name, is_admin)
+	SELECT id, login, email, name, is_admin
+	FROM users;
+
+DROP TABLE users;
+ALTER TABLE old_users RENAME TO users;
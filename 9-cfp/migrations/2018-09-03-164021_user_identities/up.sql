@@ -0,0 +1,28 @@
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE new_users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	login TEXT NOT NULL UNIQUE,
+	email TEXT NOT NULL,
+	name TEXT NOT NULL,
+	is_admin BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO new_users (id, login, email, name, is_admin)
+	SELECT id, github_id, email, name, is_admin
+	FROM users;
+
+DROP TABLE users;
+ALTER TABLE new_users RENAME TO users;
+
+CREATE TABLE user_identities (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER REFERENCES users (id) NOT NULL,
+	provider TEXT NOT NULL,
+	subject TEXT NOT NULL,
+	UNIQUE (provider, subject)
+);
+
+INSERT INTO user_identities (user, provider, subject)
+	SELECT id, 'github', login
+	FROM users;
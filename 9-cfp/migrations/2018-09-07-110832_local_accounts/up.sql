@@ -0,0 +1,13 @@
+CREATE TABLE local_credentials (
+	user INTEGER PRIMARY KEY NOT NULL REFERENCES users (id),
+	password_hash TEXT NOT NULL,
+	failed_attempts INTEGER NOT NULL DEFAULT 0,
+	locked_until TIMESTAMP
+);
+
+CREATE TABLE password_resets (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER REFERENCES users (id) NOT NULL,
+	token_hash TEXT NOT NULL UNIQUE,
+	expires_at TIMESTAMP NOT NULL
+);
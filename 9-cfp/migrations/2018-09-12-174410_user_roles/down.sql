@@ -0,0 +1,18 @@
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE old_users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	login TEXT NOT NULL UNIQUE,
+	email TEXT NOT NULL,
+	name TEXT NOT NULL,
+	is_admin BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO old_users (id, login, email, name, is_admin)
+	SELECT id, login, email, name, id IN (SELECT user FROM user_roles WHERE role = 3)
+	FROM users;
+
+DROP TABLE users;
+ALTER TABLE old_users RENAME TO users;
+
+DROP TABLE user_roles;
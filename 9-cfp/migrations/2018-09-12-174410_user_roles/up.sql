@@ -0,0 +1,31 @@
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE user_roles (
+	user INTEGER REFERENCES users (id) NOT NULL,
+	role INTEGER NOT NULL,
+	PRIMARY KEY (user, role)
+);
+
+-- Every existing user is a speaker, and administrators become organizers.
+INSERT INTO user_roles (user, role)
+	SELECT id, 0
+	FROM users;
+
+INSERT INTO user_roles (user, role)
+	SELECT id, 3
+	FROM users
+	WHERE is_admin;
+
+CREATE TABLE new_users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	login TEXT NOT NULL UNIQUE,
+	email TEXT NOT NULL,
+	name TEXT NOT NULL
+);
+
+INSERT INTO new_users (id, login, email, name)
+	SELECT id, login, email, name
+	FROM users;
+
+DROP TABLE users;
+ALTER TABLE new_users RENAME TO users;
@@ -0,0 +1,12 @@
+CREATE TABLE talk_events (
+	id INTEGER PRIMARY KEY NOT NULL,
+	talk INTEGER REFERENCES talks (id) NOT NULL,
+	actor INTEGER REFERENCES users (id) NOT NULL,
+	old_status INTEGER,
+	new_status INTEGER NOT NULL,
+	created_at TIMESTAMP NOT NULL,
+	note TEXT
+);
+
+CREATE INDEX talk_events_talk ON talk_events (talk);
+CREATE INDEX talk_events_created_at ON talk_events (created_at);
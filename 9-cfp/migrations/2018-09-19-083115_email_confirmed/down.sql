@@ -0,0 +1,15 @@
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE old_users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	login TEXT NOT NULL UNIQUE,
+	email TEXT NOT NULL,
+	name TEXT NOT NULL
+);
+
+INSERT INTO old_users (id, login, email, name)
+	SELECT id, login, email, name
+	FROM users;
+
+DROP TABLE users;
+ALTER TABLE old_users RENAME TO users;
@@ -0,0 +1,5 @@
+ALTER TABLE users ADD COLUMN email_confirmed BOOLEAN NOT NULL DEFAULT 0;
+
+-- Users with local accounts entered their email address themselves.
+UPDATE users SET email_confirmed = 1
+	WHERE email != '' AND id IN (SELECT user FROM local_credentials);
@@ -0,0 +1,27 @@
+DROP TRIGGER talks_fts_update;
+DROP TRIGGER talks_fts_delete;
+DROP TRIGGER talks_fts_insert;
+DROP TABLE talks_fts;
+
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE old_talks (
+	id INTEGER PRIMARY KEY NOT NULL,
+	presenter INTEGER REFERENCES users (id) NOT NULL,
+	title TEXT NOT NULL,
+	status INTEGER NOT NULL,
+	description TEXT NOT NULL,
+	format INTEGER NOT NULL DEFAULT 0,
+	duration INTEGER NOT NULL DEFAULT 30,
+	level INTEGER NOT NULL DEFAULT 0,
+	outline TEXT NOT NULL DEFAULT '',
+	notes TEXT NOT NULL DEFAULT '',
+	tags TEXT NOT NULL DEFAULT ''
+);
+
+INSERT INTO old_talks (id, presenter, title, status, description, format, duration, level, outline, notes, tags)
+	SELECT id, presenter, title, status, description, format, duration, level, outline, notes, tags
+	FROM talks;
+
+DROP TABLE talks;
+ALTER TABLE old_talks RENAME TO talks;
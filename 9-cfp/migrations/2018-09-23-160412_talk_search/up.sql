@@ -0,0 +1,38 @@
+ALTER TABLE talks ADD COLUMN submitted_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+
+-- Talks submitted before their history was recorded keep the placeholder.
+UPDATE talks SET submitted_at = (
+	SELECT MIN(created_at) FROM talk_events
+	WHERE talk_events.talk = talks.id AND talk_events.old_status IS NULL
+) WHERE EXISTS (
+	SELECT 1 FROM talk_events
+	WHERE talk_events.talk = talks.id AND talk_events.old_status IS NULL
+);
+
+CREATE INDEX talks_submitted_at ON talks (submitted_at);
+
+CREATE VIRTUAL TABLE talks_fts USING fts5 (
+	title,
+	description,
+	content = 'talks',
+	content_rowid = 'id'
+);
+
+INSERT INTO talks_fts (talks_fts) VALUES ('rebuild');
+
+CREATE TRIGGER talks_fts_insert AFTER INSERT ON talks BEGIN
+	INSERT INTO talks_fts (rowid, title, description)
+		VALUES (new.id, new.title, new.description);
+END;
+
+CREATE TRIGGER talks_fts_delete AFTER DELETE ON talks BEGIN
+	INSERT INTO talks_fts (talks_fts, rowid, title, description)
+		VALUES ('delete', old.id, old.title, old.description);
+END;
+
+CREATE TRIGGER talks_fts_update AFTER UPDATE OF title, description ON talks BEGIN
+	INSERT INTO talks_fts (talks_fts, rowid, title, description)
+		VALUES ('delete', old.id, old.title, old.description);
+	INSERT INTO talks_fts (rowid, title, description)
+		VALUES (new.id, new.title, new.description);
+END;
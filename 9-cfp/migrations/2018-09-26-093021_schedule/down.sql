@@ -0,0 +1,3 @@
+DROP TABLE schedule_entries;
+DROP TABLE slots;
+DROP TABLE rooms;
@@ -0,0 +1,21 @@
+CREATE TABLE rooms (
+	id INTEGER PRIMARY KEY NOT NULL,
+	name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE slots (
+	id INTEGER PRIMARY KEY NOT NULL,
+	starts_at TIMESTAMP NOT NULL,
+	ends_at TIMESTAMP NOT NULL,
+	CHECK (starts_at < ends_at)
+);
+
+CREATE INDEX slots_starts_at ON slots (starts_at);
+
+CREATE TABLE schedule_entries (
+	id INTEGER PRIMARY KEY NOT NULL,
+	talk INTEGER REFERENCES talks (id) NOT NULL UNIQUE,
+	room INTEGER REFERENCES rooms (id) NOT NULL,
+	slot INTEGER REFERENCES slots (id) NOT NULL,
+	UNIQUE (room, slot)
+);
@@ -0,0 +1,21 @@
+CREATE TABLE events (
+	id INTEGER PRIMARY KEY NOT NULL,
+	slug TEXT NOT NULL UNIQUE,
+	name TEXT NOT NULL,
+	cfp_opens TIMESTAMP,
+	cfp_closes TIMESTAMP
+);
+
+-- Existing talks belong to the first event, which also takes the talks that
+-- are submitted without naming an event.
+INSERT INTO events (id, slug, name) VALUES (1, 'cfp', 'Call for Papers');
+
+CREATE TABLE event_admins (
+	event INTEGER REFERENCES events (id) NOT NULL,
+	user INTEGER REFERENCES users (id) NOT NULL,
+	PRIMARY KEY (event, user)
+);
+
+ALTER TABLE talks ADD COLUMN event INTEGER NOT NULL DEFAULT 1 REFERENCES events (id);
+
+CREATE INDEX talks_event ON talks (event);
@@ -0,0 +1,10 @@
+-- Co-speakers of talks. `talks.presenter` stays the primary speaker, who
+-- invites others; invitations are confirmed once the invitee accepts.
+CREATE TABLE talk_speakers (
+	talk INTEGER REFERENCES talks (id) NOT NULL,
+	user INTEGER REFERENCES users (id) NOT NULL,
+	confirmed BOOLEAN NOT NULL DEFAULT 0,
+	PRIMARY KEY (talk, user)
+);
+
+CREATE INDEX talk_speakers_user ON talk_speakers (user);
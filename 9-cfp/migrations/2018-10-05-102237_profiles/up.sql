@@ -0,0 +1,12 @@
+-- Public speaker profiles. Users without a row have an empty profile.
+CREATE TABLE profiles (
+	user INTEGER PRIMARY KEY REFERENCES users (id) NOT NULL,
+	bio TEXT NOT NULL DEFAULT '',
+	pronouns TEXT NOT NULL DEFAULT '',
+	company TEXT NOT NULL DEFAULT '',
+	website TEXT NOT NULL DEFAULT '',
+	twitter TEXT NOT NULL DEFAULT '',
+	github TEXT NOT NULL DEFAULT '',
+	-- The file name of the headshot in the avatar directory
+	avatar TEXT
+);
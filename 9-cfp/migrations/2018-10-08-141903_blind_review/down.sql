@@ -0,0 +1,16 @@
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE old_events (
+	id INTEGER PRIMARY KEY NOT NULL,
+	slug TEXT NOT NULL UNIQUE,
+	name TEXT NOT NULL,
+	cfp_opens TIMESTAMP,
+	cfp_closes TIMESTAMP
+);
+
+INSERT INTO old_events (id, slug, name, cfp_opens, cfp_closes)
+	SELECT id, slug, name, cfp_opens, cfp_closes
+	FROM events;
+
+DROP TABLE events;
+ALTER TABLE old_events RENAME TO events;
@@ -0,0 +1,3 @@
+-- Under blind review, only organizers (and the speakers themselves) get to know
+-- who presents a talk.
+ALTER TABLE events ADD COLUMN blind_review BOOLEAN NOT NULL DEFAULT 0;
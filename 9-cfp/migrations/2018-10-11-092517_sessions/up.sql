@@ -0,0 +1,12 @@
+-- Logged-in browser sessions. The session cookie holds a random token, of
+-- which only the hash is stored.
+CREATE TABLE sessions (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER REFERENCES users (id) NOT NULL,
+	token_hash TEXT NOT NULL UNIQUE,
+	created_at TIMESTAMP NOT NULL,
+	last_seen_at TIMESTAMP NOT NULL,
+	user_agent TEXT NOT NULL DEFAULT '',
+	ip TEXT NOT NULL DEFAULT ''
+);
+CREATE INDEX sessions_user ON sessions (user);
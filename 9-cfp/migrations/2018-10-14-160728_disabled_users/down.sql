@@ -0,0 +1,16 @@
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE old_users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	login TEXT NOT NULL UNIQUE,
+	email TEXT NOT NULL,
+	name TEXT NOT NULL,
+	email_confirmed BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO old_users (id, login, email, name, email_confirmed)
+	SELECT id, login, email, name, email_confirmed
+	FROM users;
+
+DROP TABLE users;
+ALTER TABLE old_users RENAME TO users;
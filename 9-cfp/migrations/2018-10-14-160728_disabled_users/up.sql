@@ -0,0 +1,2 @@
+-- Disabled users can no longer log in, but keep their talks and history.
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+UPDATE user_identities SET subject = substr(subject, 7)
+	WHERE provider = 'github' AND subject LIKE 'login:%';
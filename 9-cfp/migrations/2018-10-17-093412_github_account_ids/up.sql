@@ -0,0 +1,5 @@
+-- GitHub identities were keyed on the login, which users can rename and
+-- others can then register. They are now keyed on the numeric account ID,
+-- which only GitHub knows: mark the existing ones as legacy, so that each one
+-- is moved to the account ID when its owner next logs in.
+UPDATE user_identities SET subject = 'login:' || subject WHERE provider = 'github';
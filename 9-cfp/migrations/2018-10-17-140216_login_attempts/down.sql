@@ -0,0 +1,17 @@
+PRAGMA defer_foreign_keys = ON;
+
+DROP TABLE login_attempts;
+
+CREATE TABLE old_local_credentials (
+	user INTEGER PRIMARY KEY NOT NULL REFERENCES users (id),
+	password_hash TEXT NOT NULL,
+	failed_attempts INTEGER NOT NULL DEFAULT 0,
+	locked_until TIMESTAMP
+);
+
+INSERT INTO old_local_credentials (user, password_hash)
+	SELECT user, password_hash
+	FROM local_credentials;
+
+DROP TABLE local_credentials;
+ALTER TABLE old_local_credentials RENAME TO local_credentials;
@@ -0,0 +1,23 @@
+-- Failed logins are throttled per login and client address, rather than by
+-- locking the account for everyone.
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE new_local_credentials (
+	user INTEGER PRIMARY KEY NOT NULL REFERENCES users (id),
+	password_hash TEXT NOT NULL
+);
+
+INSERT INTO new_local_credentials (user, password_hash)
+	SELECT user, password_hash
+	FROM local_credentials;
+
+DROP TABLE local_credentials;
+ALTER TABLE new_local_credentials RENAME TO local_credentials;
+
+CREATE TABLE login_attempts (
+	login TEXT NOT NULL,
+	ip TEXT NOT NULL,
+	failed_attempts INTEGER NOT NULL DEFAULT 0,
+	locked_until TIMESTAMP,
+	PRIMARY KEY (login, ip)
+);
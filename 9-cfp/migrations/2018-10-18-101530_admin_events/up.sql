@@ -0,0 +1,8 @@
+CREATE TABLE admin_events (
+	id INTEGER PRIMARY KEY NOT NULL,
+	actor INTEGER REFERENCES users (id) NOT NULL,
+	action TEXT NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX admin_events_created_at ON admin_events (created_at);
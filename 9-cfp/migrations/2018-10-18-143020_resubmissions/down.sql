@@ -0,0 +1,48 @@
+DROP TRIGGER talks_fts_update;
+DROP TRIGGER talks_fts_delete;
+DROP TRIGGER talks_fts_insert;
+
+PRAGMA defer_foreign_keys = ON;
+
+CREATE TABLE old_talks (
+	id INTEGER PRIMARY KEY NOT NULL,
+	presenter INTEGER REFERENCES users (id) NOT NULL,
+	title TEXT NOT NULL,
+	status INTEGER NOT NULL,
+	description TEXT NOT NULL,
+	format INTEGER NOT NULL DEFAULT 0,
+	duration INTEGER NOT NULL DEFAULT 30,
+	level INTEGER NOT NULL DEFAULT 0,
+	outline TEXT NOT NULL DEFAULT '',
+	notes TEXT NOT NULL DEFAULT '',
+	tags TEXT NOT NULL DEFAULT '',
+	submitted_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00',
+	event INTEGER NOT NULL DEFAULT 1 REFERENCES events (id)
+);
+
+INSERT INTO old_talks (id, presenter, title, status, description, format, duration, level, outline, notes, tags, submitted_at, event)
+	SELECT id, presenter, title, status, description, format, duration, level, outline, notes, tags, submitted_at, event
+	FROM talks;
+
+DROP TABLE talks;
+ALTER TABLE old_talks RENAME TO talks;
+
+CREATE INDEX talks_submitted_at ON talks (submitted_at);
+CREATE INDEX talks_event ON talks (event);
+
+CREATE TRIGGER talks_fts_insert AFTER INSERT ON talks BEGIN
+	INSERT INTO talks_fts (rowid, title, description)
+		VALUES (new.id, new.title, new.description);
+END;
+
+CREATE TRIGGER talks_fts_delete AFTER DELETE ON talks BEGIN
+	INSERT INTO talks_fts (talks_fts, rowid, title, description)
+		VALUES ('delete', old.id, old.title, old.description);
+END;
+
+CREATE TRIGGER talks_fts_update AFTER UPDATE OF title, description ON talks BEGIN
+	INSERT INTO talks_fts (talks_fts, rowid, title, description)
+		VALUES ('delete', old.id, old.title, old.description);
+	INSERT INTO talks_fts (rowid, title, description)
+		VALUES (new.id, new.title, new.description);
+END;
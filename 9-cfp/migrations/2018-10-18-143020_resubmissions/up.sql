@@ -0,0 +1,21 @@
+ALTER TABLE talks ADD COLUMN resubmitted_from INTEGER REFERENCES talks (id);
+
+-- Until now, resubmissions were only mentioned in the note of the submission.
+UPDATE talks SET resubmitted_from = (
+	SELECT CAST(substr(note, length('Resubmitted from talk ') + 1) AS INTEGER)
+	FROM talk_events
+	WHERE talk_events.talk = talks.id AND talk_events.old_status IS NULL
+		AND talk_events.note LIKE 'Resubmitted from talk %'
+);
+
+-- Talks that were resubmitted to the same event more than once keep their
+-- copies, but only the first one counts as the resubmission.
+UPDATE talks SET resubmitted_from = NULL
+	WHERE EXISTS (
+		SELECT 1 FROM talks AS earlier
+		WHERE earlier.resubmitted_from = talks.resubmitted_from
+			AND earlier.event = talks.event
+			AND earlier.id < talks.id
+	);
+
+CREATE UNIQUE INDEX talks_resubmitted_from ON talks (resubmitted_from, event);
@@ -0,0 +1,31 @@
+PRAGMA defer_foreign_keys = ON;
+
+DROP INDEX slots_event;
+
+CREATE TABLE old_slots (
+	id INTEGER PRIMARY KEY NOT NULL,
+	starts_at TIMESTAMP NOT NULL,
+	ends_at TIMESTAMP NOT NULL,
+	CHECK (starts_at < ends_at)
+);
+
+INSERT INTO old_slots (id, starts_at, ends_at) SELECT id, starts_at, ends_at FROM slots;
+
+DROP TABLE slots;
+ALTER TABLE old_slots RENAME TO slots;
+
+CREATE INDEX slots_starts_at ON slots (starts_at);
+
+-- Rooms of other events are told apart by the event's slug, since room names
+-- have to be unique again.
+CREATE TABLE old_rooms (
+	id INTEGER PRIMARY KEY NOT NULL,
+	name TEXT NOT NULL UNIQUE
+);
+
+INSERT INTO old_rooms (id, name)
+	SELECT rooms.id, CASE WHEN rooms.event = 1 THEN rooms.name ELSE rooms.name || ' (' || events.slug || ')' END
+	FROM rooms INNER JOIN events ON events.id = rooms.event;
+
+DROP TABLE rooms;
+ALTER TABLE old_rooms RENAME TO rooms;
@@ -0,0 +1,23 @@
+PRAGMA defer_foreign_keys = ON;
+
+-- Room names only have to be unique within an event. Existing rooms and slots
+-- belong to the first event, like the talks that predate events.
+CREATE TABLE new_rooms (
+	id INTEGER PRIMARY KEY NOT NULL,
+	name TEXT NOT NULL,
+	event INTEGER NOT NULL DEFAULT 1 REFERENCES events (id),
+	UNIQUE (event, name)
+);
+
+INSERT INTO new_rooms (id, name) SELECT id, name FROM rooms;
+
+DROP TABLE rooms;
+ALTER TABLE new_rooms RENAME TO rooms;
+
+ALTER TABLE slots ADD COLUMN event INTEGER NOT NULL DEFAULT 1 REFERENCES events (id);
+
+CREATE INDEX slots_event ON slots (event);
+
+-- Talks of other events can't stay in the first event's rooms.
+DELETE FROM schedule_entries
+	WHERE talk IN (SELECT id FROM talks WHERE event != 1);
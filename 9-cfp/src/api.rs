@@ -0,0 +1,206 @@
+use diesel;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request, State};
+use rocket::response::{self, status, Responder, Response};
+use rocket_contrib::json::Json;
+
+use error::CfpError;
+use event::Event;
+use mail::Mail;
+use role::{ProgramChair, Reviewer};
+use talk::{PresenterVisibility, StatusChange, Talk, TalkDetails, TalkStatus, TalkView};
+use user::User;
+use window::CfpWindow;
+use DbConn;
+
+/// An error returned by the JSON API. Rendered as a JSON object of the form
+/// `{ "error": { "code": 404, "reason": "Not Found", "message": "...", "errors": [] } }`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: Status,
+    pub message: String,
+    pub errors: Vec<String>,
+    /// The internal error this one was made from, logged like any other
+    /// `CfpError` when responding.
+    cause: Option<CfpError>,
+}
+
+impl ApiError {
+    pub fn new<S: Into<String>>(status: Status, message: S) -> ApiError {
+        ApiError { status, message: message.into(), errors: vec![], cause: None }
+    }
+
+    /// An error for a request with an invalid body, listing each problem.
+    pub fn invalid(errors: Vec<String>) -> ApiError {
+        ApiError { errors, ..ApiError::new(Status::UnprocessableEntity, "Invalid request") }
+    }
+
+    pub fn not_found() -> ApiError {
+        ApiError::new(Status::NotFound, "No such resource")
+    }
+}
+
+/// Database errors are logged and reported as a 500 without further detail.
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> ApiError {
+        ApiError {
+            cause: Some(e.into()),
+            ..ApiError::new(Status::InternalServerError, "Database error")
+        }
+    }
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        if let Some(ref cause) = self.cause {
+            cause.log(req);
+        }
+        let body = json!({
+            "error": {
+                "code": self.status.code,
+                "reason": self.status.reason,
+                "message": self.message,
+                "errors": self.errors,
+            }
+        });
+        Response::build_from(body.respond_to(req)?)
+            .status(self.status)
+            .ok()
+    }
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// Like `User`, but fails with a `401 Unauthorized` instead of forwarding
+/// when no user is logged in.
+pub struct ApiUser(pub User);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ApiUser {
+    type Error = ();
+
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        match req.guard::<User>() {
+            Outcome::Success(user) => Outcome::Success(ApiUser(user)),
+            Outcome::Failure(e) => Outcome::Failure(e),
+            Outcome::Forward(()) => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Like `ProgramChair`, but fails with a `401 Unauthorized` when no user is
+/// logged in, and with a `403 Forbidden` when the user isn't a program chair.
+pub struct ApiProgramChair(pub ProgramChair);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ApiProgramChair {
+    type Error = ();
+
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        req.guard::<ApiUser>()?;
+        match req.guard::<ProgramChair>() {
+            Outcome::Success(chair) => Outcome::Success(ApiProgramChair(chair)),
+            Outcome::Failure(e) => Outcome::Failure(e),
+            Outcome::Forward(()) => Outcome::Failure((Status::Forbidden, ())),
+        }
+    }
+}
+
+/// Returns the currently logged-in user.
+#[get("/me")]
+fn me(user: ApiUser) -> Json<User> {
+    Json(user.0)
+}
+
+/// Lists talks: all of them for reviewers, or the user's own otherwise. Talks
+/// only include their `presenter` if the user may know who that is; see
+/// `PresenterVisibility`.
+#[get("/talks")]
+fn list_talks(conn: DbConn, user: ApiUser, reviewer: Option<Reviewer>) -> ApiResult<Json<Vec<TalkView>>> {
+    let talks = match reviewer {
+        Some(reviewer) => Talk::get_all(&conn, &reviewer)?,
+        None => Talk::get_for_user(&conn, &user.0)?,
+    };
+    let visibility = PresenterVisibility::for_user(&conn, &user.0)?;
+    Ok(Json(visibility.view_all(talks)))
+}
+
+/// Returns a single talk, which the user must present or be a reviewer.
+#[get("/talks/<id>")]
+pub fn get_talk(id: i32, conn: DbConn, user: ApiUser) -> ApiResult<Json<TalkView>> {
+    let talk = Talk::get_one(&conn, &user.0, id)?.ok_or_else(ApiError::not_found)?;
+    let visibility = PresenterVisibility::for_user(&conn, &user.0)?;
+    Ok(Json(visibility.view(talk)))
+}
+
+/// Submits a new talk presented by the current user to the event with the
+/// slug `event`, or to the default event. The body is a JSON object with the
+/// fields of `TalkDetails`. Responds with `201 Created`.
+#[post("/talks?<event>", format = "json", data = "<details>")]
+fn new_talk(
+    event: Option<String>,
+    details: Json<TalkDetails>,
+    conn: DbConn,
+    user: ApiUser,
+    window: State<CfpWindow>,
+    mail: State<Mail>,
+) -> ApiResult<status::Created<Json<TalkView>>> {
+    if !user.0.roles.speaker {
+        return Err(ApiError::new(Status::Forbidden, "Only speakers can submit talks"));
+    }
+    let event = match event {
+        Some(slug) => Event::get_by_slug(&conn, &slug)?.ok_or_else(ApiError::not_found)?,
+        None => Event::get_default(&conn)?,
+    };
+    if !window.is_open_for(&event) {
+        return Err(ApiError::new(Status::Forbidden, "The call for papers is closed"));
+    }
+
+    let errors = details.validate();
+    if !errors.is_empty() {
+        return Err(ApiError::invalid(errors));
+    }
+
+    let id = Talk::new(&conn, &user.0, &event, &details)?;
+    let talk = Talk::get_one(&conn, &user.0, id)?.ok_or_else(ApiError::not_found)?;
+    mail.send(&user.0, "received", &json!({ "talk": talk }));
+    let location = format!("/api/v1{}", uri!(get_talk: id));
+    let view = PresenterVisibility::for_user(&conn, &user.0)?.view(talk);
+    Ok(status::Created(location, Some(Json(view))))
+}
+
+/// The body of a status change request, e.g. `{ "status": "Approved" }`,
+/// optionally with a `note` explaining the change.
+#[derive(Deserialize)]
+struct NewStatus {
+    status: TalkStatus,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+/// Sets the status of a talk. Returns the updated talk.
+#[put("/talks/<id>/status", format = "json", data = "<body>")]
+fn set_status(
+    id: i32,
+    body: Json<NewStatus>,
+    conn: DbConn,
+    chair: ApiProgramChair,
+    mail: State<Mail>,
+) -> ApiResult<Json<TalkView>> {
+    let chair = chair.0;
+    Talk::get_one(&conn, &chair.0, id)?.ok_or_else(ApiError::not_found)?;
+    let change = Talk::set_status(&conn, &chair, id, body.status, body.note.as_ref().map(String::as_str))?;
+    let talk = Talk::get_one(&conn, &chair.0, id)?.ok_or_else(ApiError::not_found)?;
+    if change == StatusChange::Changed {
+        mail.send_decision(&conn, &talk);
+    }
+    let visibility = PresenterVisibility::for_user(&conn, &chair.0)?;
+    Ok(Json(visibility.view(talk)))
+}
+
+/// Returns a fairing that mounts the JSON API under `/api/v1`.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("JSON API", |rocket| {
+        Ok(rocket.mount("/api/v1", routes![me, list_talks, get_talk, new_talk, set_status]))
+    })
+}
@@ -0,0 +1,281 @@
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use diesel;
+use diesel::prelude::*;
+use diesel::result::Error;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket_contrib::templates::Template;
+
+use error::CfpResult;
+use role::{Admin, ProgramChair};
+use schema::{admin_events, talk_events, talks, users};
+use talk::TalkStatus;
+use user::User;
+use DbConn;
+
+/// A change of a talk's status, retrieved from the database. Events are only
+/// ever appended, never changed or deleted.
+#[derive(Queryable, Serialize)]
+pub struct TalkEvent {
+    pub id: i32,
+    pub talk: i32,
+    #[serde(skip_serializing)]
+    pub actor: i32,
+    /// The status before the change, or `None` when the talk was submitted.
+    pub old_status: Option<TalkStatus>,
+    pub new_status: TalkStatus,
+    pub created_at: NaiveDateTime,
+    pub note: Option<String>,
+}
+
+/// Event data to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "talk_events"]
+struct NewTalkEvent<'a> {
+    pub talk: i32,
+    pub actor: i32,
+    pub old_status: Option<TalkStatus>,
+    pub new_status: TalkStatus,
+    pub created_at: NaiveDateTime,
+    pub note: Option<&'a str>,
+}
+
+/// An event together with the name of the user who caused it, if the viewer
+/// may know it
+#[derive(Serialize)]
+pub struct NamedTalkEvent {
+    #[serde(flatten)]
+    pub event: TalkEvent,
+    pub actor_name: Option<String>,
+}
+
+/// An event as shown in the audit log, with the actor and the talk spelled
+/// out
+#[derive(Serialize)]
+pub struct AuditEntry {
+    #[serde(flatten)]
+    pub event: TalkEvent,
+    pub actor_login: String,
+    pub actor_name: String,
+    pub talk_title: String,
+}
+
+/// An administrative action that doesn't concern a single talk, such as
+/// granting a role or moving the deadline. Like talk events, these are only
+/// ever appended.
+#[derive(Queryable, Serialize)]
+pub struct AdminEvent {
+    pub id: i32,
+    #[serde(skip_serializing)]
+    pub actor: i32,
+    /// What was done, in words, e.g. `granted Reviewer to carol`.
+    pub action: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Administrative event data to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "admin_events"]
+struct NewAdminEvent<'a> {
+    pub actor: i32,
+    pub action: &'a str,
+    pub created_at: NaiveDateTime,
+}
+
+/// An administrative action as shown in the audit log, with the actor
+/// spelled out
+#[derive(Serialize)]
+pub struct AdminEntry {
+    #[serde(flatten)]
+    pub event: AdminEvent,
+    pub actor_login: String,
+    pub actor_name: String,
+}
+
+/// Restricts the audit log to the events of one actor, and to a range of
+/// days. `until` is inclusive.
+#[derive(Default, Serialize)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl TalkEvent {
+    /// Records that `actor` changed the status of the talk with the given
+    /// `talk_id` from `old_status` to `new_status`. Call this in the same
+    /// transaction as the change itself.
+    pub fn record(
+        conn: &DbConn,
+        talk_id: i32,
+        actor: &User,
+        old_status: Option<TalkStatus>,
+        new_status: TalkStatus,
+        note: Option<&str>,
+    ) -> Result<(), Error> {
+        diesel::insert_into(talk_events::table)
+            .values(NewTalkEvent {
+                talk: talk_id,
+                actor: actor.id,
+                old_status,
+                new_status,
+                created_at: Utc::now().naive_utc(),
+                note,
+            })
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Retrieve the history of the talk with the given `talk_id`, oldest
+    /// first. Who caused each event is only revealed to program chairs.
+    pub fn get_for_talk(
+        conn: &DbConn,
+        chair: Option<&ProgramChair>,
+        talk_id: i32,
+    ) -> Result<Vec<NamedTalkEvent>, Error> {
+        let rows: Vec<(TalkEvent, String)> = talk_events::table
+            .inner_join(users::table)
+            .filter(talk_events::talk.eq(talk_id))
+            .select((talk_events::all_columns, users::name))
+            .order((talk_events::created_at, talk_events::id))
+            .load(&conn.0)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(event, name)| NamedTalkEvent {
+                event,
+                actor_name: chair.map(|_| name),
+            })
+            .collect())
+    }
+
+    /// Retrieve all events matching `filter`, newest first
+    pub fn get_all(
+        conn: &DbConn,
+        _admin: &Admin,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        let mut query = talk_events::table
+            .inner_join(users::table)
+            .inner_join(talks::table)
+            .select((talk_events::all_columns, users::login, users::name, talks::title))
+            .order((talk_events::created_at.desc(), talk_events::id.desc()))
+            .into_boxed();
+
+        if let Some(ref actor) = filter.actor {
+            query = query.filter(users::login.eq(actor));
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(talk_events::created_at.ge(since.and_hms(0, 0, 0)));
+        }
+        if let Some(until) = filter.until {
+            query = query.filter(talk_events::created_at.lt(until.succ().and_hms(0, 0, 0)));
+        }
+
+        let rows: Vec<(TalkEvent, String, String, String)> = query.load(&conn.0)?;
+        Ok(rows
+            .into_iter()
+            .map(|(event, actor_login, actor_name, talk_title)| AuditEntry {
+                event,
+                actor_login,
+                actor_name,
+                talk_title,
+            })
+            .collect())
+    }
+}
+
+impl AdminEvent {
+    /// Records that `actor`, an organizer or event administrator, did
+    /// `action`, which describes it for the audit log. Call this in the same
+    /// transaction as the action itself, if any.
+    pub fn record(conn: &DbConn, actor: &User, action: &str) -> Result<(), Error> {
+        diesel::insert_into(admin_events::table)
+            .values(NewAdminEvent {
+                actor: actor.id,
+                action,
+                created_at: Utc::now().naive_utc(),
+            })
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Retrieve all administrative events matching `filter`, newest first
+    pub fn get_all(
+        conn: &DbConn,
+        _admin: &Admin,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AdminEntry>, Error> {
+        let mut query = admin_events::table
+            .inner_join(users::table)
+            .select((admin_events::all_columns, users::login, users::name))
+            .order((admin_events::created_at.desc(), admin_events::id.desc()))
+            .into_boxed();
+
+        if let Some(ref actor) = filter.actor {
+            query = query.filter(users::login.eq(actor));
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(admin_events::created_at.ge(since.and_hms(0, 0, 0)));
+        }
+        if let Some(until) = filter.until {
+            query = query.filter(admin_events::created_at.lt(until.succ().and_hms(0, 0, 0)));
+        }
+
+        let rows: Vec<(AdminEvent, String, String)> = query.load(&conn.0)?;
+        Ok(rows
+            .into_iter()
+            .map(|(event, actor_login, actor_name)| AdminEntry {
+                event,
+                actor_login,
+                actor_name,
+            })
+            .collect())
+    }
+}
+
+/// Parses an optional date in the format submitted by an HTML `date` input
+/// (`2018-09-15`). Empty values count as missing.
+fn parse_date(value: Option<String>) -> CfpResult<Option<NaiveDate>> {
+    match value {
+        Some(ref value) if !value.trim().is_empty() => NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| Status::BadRequest.into()),
+        _ => Ok(None),
+    }
+}
+
+/// Shows the status changes of all talks and the administrative actions,
+/// optionally restricted to those made by the user with the login `actor`,
+/// and to the days from `since` until `until`.
+#[get("/admin/audit?<actor>&<since>&<until>")]
+fn audit_log(
+    actor: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    conn: DbConn,
+    admin: Admin,
+) -> CfpResult<Template> {
+    let filter = AuditFilter {
+        actor: actor.map(|actor| actor.trim().to_string()).filter(|actor| !actor.is_empty()),
+        since: parse_date(since)?,
+        until: parse_date(until)?,
+    };
+    let events = TalkEvent::get_all(&conn, &admin, &filter)?;
+    let admin_events = AdminEvent::get_all(&conn, &admin, &filter)?;
+    Ok(Template::render("audit", json!({
+        "user": admin.0,
+        "events": events,
+        "admin_events": admin_events,
+        "filter": filter,
+    })))
+}
+
+/// Returns a fairing that mounts the audit log.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Audit Log", |rocket| {
+        Ok(rocket.mount("/", routes![audit_log]))
+    })
+}
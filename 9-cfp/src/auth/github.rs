@@ -0,0 +1,122 @@
+use reqwest::{self, Url};
+use rocket::config::{self, Table};
+
+use super::{exchange_code, get_url, with_params, ClientCredentials, OAuthProvider};
+use user::Identity;
+
+const AUTHORIZE_URL: &str = "https://github.com/login/oauth/authorize";
+const TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const USER_URL: &str = "https://api.github.com/user";
+const EMAILS_URL: &str = "https://api.github.com/user/emails";
+
+/// User information to be retrieved from the GitHub API.
+#[derive(Deserialize)]
+struct UserInfo {
+    id: i64,
+    login: String,
+    name: Option<String>,
+    email: Option<String>,
+}
+
+/// An email address of a GitHub user, as returned by the emails API.
+#[derive(Deserialize)]
+struct EmailInfo {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+/// Logs users in with their GitHub account. Users are identified by the ID of
+/// their GitHub account, rather than by their login: logins can be renamed,
+/// and then registered by someone else.
+///
+/// The endpoints default to github.com, but can be pointed elsewhere, e.g. at
+/// a GitHub Enterprise instance or at a mock server in tests.
+pub struct GitHub {
+    credentials: ClientCredentials,
+    authorize_url: Url,
+    token_url: Url,
+    user_url: Url,
+    emails_url: Url,
+}
+
+impl GitHub {
+    /// Logs in with github.com, using `credentials`.
+    pub fn new(credentials: ClientCredentials) -> config::Result<GitHub> {
+        GitHub::with_endpoints("github", &Table::new(), credentials)
+    }
+
+    /// Reads the `client_id`, `client_secret` and optional `authorize_url`,
+    /// `token_url`, `user_url` and `emails_url` of the provider `name`.
+    pub fn from_table(name: &str, table: &Table) -> config::Result<GitHub> {
+        GitHub::with_endpoints(name, table, ClientCredentials::from_table(name, table)?)
+    }
+
+    /// Reads the endpoints of the provider `name`, falling back to github.com.
+    fn with_endpoints(name: &str, table: &Table, credentials: ClientCredentials) -> config::Result<GitHub> {
+        Ok(GitHub {
+            credentials,
+            authorize_url: get_url(name, table, "authorize_url", AUTHORIZE_URL)?,
+            token_url: get_url(name, table, "token_url", TOKEN_URL)?,
+            user_url: get_url(name, table, "user_url", USER_URL)?,
+            emails_url: get_url(name, table, "emails_url", EMAILS_URL)?,
+        })
+    }
+}
+
+impl OAuthProvider for GitHub {
+    fn display_name(&self) -> &str {
+        "GitHub"
+    }
+
+    fn authorize_url(&self, redirect_uri: &str, state: &str) -> String {
+        with_params(&self.authorize_url, &[
+            ("client_id", self.credentials.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("scope", "read:user user:email"),
+            ("state", state),
+        ])
+    }
+
+    fn identify(
+        &self,
+        client: &reqwest::Client,
+        redirect_uri: &str,
+        code: &str,
+    ) -> reqwest::Result<Identity> {
+        let access_token = exchange_code(client, &self.token_url, &self.credentials, redirect_uri, code)?;
+
+        // Use the token to retrieve the user's GitHub account information.
+        let get = |url: &Url| {
+            client
+                .get(url.clone())
+                .header(reqwest::header::Authorization(format!("token {}", access_token)))
+                .header(reqwest::header::Accept(vec![reqwest::header::qitem(
+                    "application/vnd.github.v3+json".parse().expect("mime type"),
+                )]))
+                .send()
+        };
+        let user_info: UserInfo = get(&self.user_url)?.error_for_status()?.json()?;
+
+        // Users can keep their email address private, in which case it has to
+        // be asked for separately.
+        let email = match user_info.email {
+            Some(email) => Some(email),
+            None => {
+                let emails: Vec<EmailInfo> = get(&self.emails_url)?.error_for_status()?.json()?;
+                emails
+                    .into_iter()
+                    .find(|email| email.primary && email.verified)
+                    .map(|email| email.email)
+            }
+        };
+
+        Ok(Identity {
+            subject: user_info.id.to_string(),
+            legacy_subject: Some(format!("login:{}", user_info.login)),
+            name: user_info.name.unwrap_or_else(|| user_info.login.clone()),
+            login: user_info.login,
+            email,
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use reqwest::{self, Url};
+use rocket::config::{self, Table};
+
+use super::{exchange_code, get_url, with_params, ClientCredentials, OAuthProvider};
+use user::Identity;
+
+const DEFAULT_BASE_URL: &str = "https://gitlab.com";
+
+/// User information to be retrieved from the GitLab API.
+#[derive(Deserialize)]
+struct UserInfo {
+    id: i64,
+    username: String,
+    name: String,
+    email: Option<String>,
+}
+
+/// Logs users in with their account on gitlab.com, or on a self-hosted GitLab
+/// instance given by `base_url`. Users are identified by their numeric ID.
+pub struct GitLab {
+    credentials: ClientCredentials,
+    authorize_url: Url,
+    token_url: Url,
+    user_url: Url,
+}
+
+impl GitLab {
+    /// Reads the `client_id`, `client_secret` and optional `base_url` of the
+    /// provider `name`. The individual endpoints are derived from `base_url`,
+    /// but can be overridden with `authorize_url`, `token_url` and `user_url`.
+    pub fn from_table(name: &str, table: &Table) -> config::Result<GitLab> {
+        let credentials = ClientCredentials::from_table(name, table)?;
+        let base_url = get_url(name, table, "base_url", DEFAULT_BASE_URL)?;
+        let endpoint = |key: &str, path: &str| {
+            get_url(name, table, key, &format!("{}{}", base_url.as_str().trim_right_matches('/'), path))
+        };
+        Ok(GitLab {
+            credentials,
+            authorize_url: endpoint("authorize_url", "/oauth/authorize")?,
+            token_url: endpoint("token_url", "/oauth/token")?,
+            user_url: endpoint("user_url", "/api/v4/user")?,
+        })
+    }
+}
+
+impl OAuthProvider for GitLab {
+    fn display_name(&self) -> &str {
+        "GitLab"
+    }
+
+    fn authorize_url(&self, redirect_uri: &str, state: &str) -> String {
+        with_params(&self.authorize_url, &[
+            ("client_id", self.credentials.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("response_type", "code"),
+            ("scope", "read_user"),
+            ("state", state),
+        ])
+    }
+
+    fn identify(
+        &self,
+        client: &reqwest::Client,
+        redirect_uri: &str,
+        code: &str,
+    ) -> reqwest::Result<Identity> {
+        let access_token = exchange_code(client, &self.token_url, &self.credentials, redirect_uri, code)?;
+
+        let user_info: UserInfo = client
+            .get(self.user_url.clone())
+            .header(reqwest::header::Authorization(format!("Bearer {}", access_token)))
+            .header(reqwest::header::Accept::json())
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        Ok(Identity {
+            subject: user_info.id.to_string(),
+            legacy_subject: None,
+            login: user_info.username,
+            name: user_info.name,
+            email: user_info.email,
+        })
+    }
+}
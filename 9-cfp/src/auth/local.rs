@@ -0,0 +1,447 @@
+use argon2;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel;
+use diesel::prelude::*;
+use diesel::result::Error;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use rocket::config::{self, Config, ConfigError};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::{Cookies, Status};
+use rocket::request::{LenientForm, State};
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+
+use super::{log_in, render_login, AutoAdmin, OAuthConfig};
+use csrf::CsrfToken;
+use error::CfpResult;
+use mail::Mail;
+use schema::{local_credentials, login_attempts, password_resets};
+use session::{ClientInfo, Session, SessionConfig};
+use token::hash_token;
+use user::{NewUser, User};
+use {DbConn, SubmitResponse};
+
+/// The shortest password a user can choose.
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// The number of failed logins from one client after which it is throttled.
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+/// How long a client is throttled after too many failed logins.
+const LOCKOUT_MINUTES: i64 = 15;
+/// How long a password reset link stays valid.
+pub const RESET_VALIDITY_HOURS: i64 = 2;
+
+/// Whether local username/password accounts are enabled, read from the
+/// `local_accounts` configuration parameter (enabled by default).
+pub struct LocalAccounts {
+    pub enabled: bool,
+}
+
+impl LocalAccounts {
+    pub fn from_config(config: &Config) -> config::Result<LocalAccounts> {
+        match config.get_bool("local_accounts") {
+            Ok(enabled) => Ok(LocalAccounts { enabled }),
+            Err(ConfigError::Missing(_)) => Ok(LocalAccounts { enabled: true }),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Hashes `password` with Argon2 and a random salt.
+fn hash_password(password: &str) -> String {
+    let salt: [u8; 16] = thread_rng().gen();
+    argon2::hash_encoded(password.as_bytes(), &salt, &argon2::Config::default())
+        .expect("argon2 hashing with default parameters")
+}
+
+/// Checks `password` against a hash created by `hash_password`.
+fn verify_password(hash: &str, password: &str) -> bool {
+    argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+}
+
+/// Checks a new password for problems, returning a description of each one.
+fn validate_password(password: &str, confirmation: &str) -> Vec<String> {
+    let mut errors = vec![];
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        errors.push(format!("The password must be at least {} characters long.", MIN_PASSWORD_LENGTH));
+    }
+    if password != confirmation {
+        errors.push("The passwords don't match.".to_string());
+    }
+    errors
+}
+
+/// The password of a local account
+#[derive(Queryable)]
+pub struct LocalCredentials {
+    pub user: i32,
+    pub password_hash: String,
+}
+
+/// Local credentials to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "local_credentials"]
+struct NewLocalCredentials<'a> {
+    pub user: i32,
+    pub password_hash: &'a str,
+}
+
+/// Why a local login failed.
+pub enum LoginError {
+    /// The login or the password is wrong.
+    Invalid,
+    /// There were too many failed attempts; try again after the given time.
+    Locked(NaiveDateTime),
+    /// The password is right, but an admin disabled the account.
+    Disabled,
+    Database(Error),
+}
+
+impl From<Error> for LoginError {
+    fn from(e: Error) -> LoginError {
+        LoginError::Database(e)
+    }
+}
+
+impl LocalCredentials {
+    /// Sets the password of `user`, replacing the previous one if any.
+    pub fn set_password(conn: &DbConn, user_id: i32, password: &str) -> Result<(), Error> {
+        diesel::replace_into(local_credentials::table)
+            .values(NewLocalCredentials { user: user_id, password_hash: &hash_password(password) })
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Returns the user with the given `login`, if `password` is theirs.
+    fn check(conn: &DbConn, login: &str, password: &str) -> Result<Option<User>, Error> {
+        let user = match User::get_by_login(conn, login)? {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        let credentials: Option<LocalCredentials> = local_credentials::table
+            .find(user.id)
+            .first(&conn.0)
+            .optional()?;
+        match credentials {
+            Some(ref credentials) if verify_password(&credentials.password_hash, password) => Ok(Some(user)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Verifies the `password` of the user with the given `login`, returning
+    /// their ID. Failed attempts are counted per login and client address
+    /// `ip`: after `MAX_FAILED_ATTEMPTS`, that client can't log in as `login`
+    /// for `LOCKOUT_MINUTES`, even with the right password. Other clients
+    /// aren't affected, so nobody can lock a user out of their account.
+    pub fn verify(conn: &DbConn, login: &str, password: &str, ip: &str) -> Result<i32, LoginError> {
+        // The outcome is returned inside the transaction's result, so that
+        // failed attempts are recorded rather than rolled back.
+        conn.0.transaction::<_, Error, _>(|| {
+            let attempts = login_attempts::table.find((login, ip));
+            let (failed_attempts, locked_until) = attempts
+                .select((login_attempts::failed_attempts, login_attempts::locked_until))
+                .first::<(i32, Option<NaiveDateTime>)>(&conn.0)
+                .optional()?
+                .unwrap_or((0, None));
+
+            let now = Utc::now().naive_utc();
+            if let Some(locked_until) = locked_until {
+                if now < locked_until {
+                    return Ok(Err(LoginError::Locked(locked_until)));
+                }
+            }
+
+            if let Some(user) = LocalCredentials::check(conn, login, password)? {
+                diesel::delete(attempts).execute(&conn.0)?;
+                if user.disabled {
+                    return Ok(Err(LoginError::Disabled));
+                }
+                return Ok(Ok(user.id));
+            }
+
+            let failed_attempts = failed_attempts + 1;
+            let (failed_attempts, locked_until) = if failed_attempts >= MAX_FAILED_ATTEMPTS {
+                warn!("Throttling logins as {} from {} after {} failed attempts", login, ip, failed_attempts);
+                (0, Some(now + Duration::minutes(LOCKOUT_MINUTES)))
+            } else {
+                (failed_attempts, None)
+            };
+            diesel::replace_into(login_attempts::table)
+                .values((
+                    login_attempts::login.eq(login),
+                    login_attempts::ip.eq(ip),
+                    login_attempts::failed_attempts.eq(failed_attempts),
+                    login_attempts::locked_until.eq(locked_until),
+                ))
+                .execute(&conn.0)?;
+            Ok(Err(LoginError::Invalid))
+        })?
+    }
+}
+
+/// A password reset token to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "password_resets"]
+struct NewPasswordReset<'a> {
+    pub user: i32,
+    pub token_hash: &'a str,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Creates a password reset token for the user with the given `id`. Returns
+/// the token in plain text; only its hash is stored.
+pub fn create_reset_token(conn: &DbConn, user_id: i32) -> Result<String, Error> {
+    let token: String = thread_rng().sample_iter(&Alphanumeric).take(40).collect();
+    diesel::insert_into(password_resets::table)
+        .values(NewPasswordReset {
+            user: user_id,
+            token_hash: &hash_token(&token),
+            expires_at: Utc::now().naive_utc() + Duration::hours(RESET_VALIDITY_HOURS),
+        })
+        .execute(&conn.0)?;
+
+    Ok(token)
+}
+
+/// Looks up the user a valid (unexpired) reset `token` was issued for.
+fn find_reset_token(conn: &DbConn, token: &str) -> Result<Option<i32>, Error> {
+    password_resets::table
+        .filter(password_resets::token_hash.eq(hash_token(token)))
+        .filter(password_resets::expires_at.gt(Utc::now().naive_utc()))
+        .select(password_resets::user)
+        .first(&conn.0)
+        .optional()
+}
+
+/// A struct containing the credentials submitted on the login page.
+#[derive(FromForm)]
+struct LoginCredentials {
+    login: String,
+    password: String,
+}
+
+/// Logs in with a local account. Re-renders the login page on failure.
+#[post("/login", data = "<credentials>")]
+fn login_submit(
+    credentials: LenientForm<LoginCredentials>,
+    conn: DbConn,
+    csrf: CsrfToken,
+    mut cookies: Cookies,
+    oauth_config: State<OAuthConfig>,
+    local: State<LocalAccounts>,
+    sessions: State<SessionConfig>,
+    client: ClientInfo,
+) -> SubmitResponse {
+    let error = match LocalCredentials::verify(&conn, credentials.login.trim(), &credentials.password, &client.ip) {
+        Ok(id) => match log_in(&conn, &sessions, &mut cookies, &client, id) {
+            Ok(()) => return SubmitResponse::Redirect(Redirect::to("/")),
+            Err(e) => {
+                error!("Failed to start a session: {}", e);
+                "Login failed, please try again.".to_string()
+            }
+        },
+        Err(LoginError::Invalid) => "Invalid login or password.".to_string(),
+        Err(LoginError::Locked(until)) => format!(
+            "Too many failed logins. Try again after {} UTC.",
+            until.format("%H:%M")
+        ),
+        Err(LoginError::Disabled) => "This account has been disabled.".to_string(),
+        Err(LoginError::Database(e)) => {
+            error!("Database error during login: {}", e);
+            "Login failed, please try again.".to_string()
+        }
+    };
+    SubmitResponse::Invalid(render_login(&oauth_config, &local, &csrf, None, Some(&error)))
+}
+
+/// Shows the registration form.
+#[get("/register")]
+fn register_page(csrf: CsrfToken) -> Template {
+    Template::render("register", json!({ "errors": [], "values": {}, "csrf_token": csrf }))
+}
+
+/// A struct containing the registration form fields.
+#[derive(FromForm, Serialize)]
+struct Registration {
+    login: String,
+    name: String,
+    email: String,
+    #[serde(skip_serializing)]
+    password: String,
+    #[serde(skip_serializing)]
+    password_confirm: String,
+}
+
+/// Returns whether `login` only contains letters, digits, `-` and `_`.
+pub fn is_valid_login(login: &str) -> bool {
+    !login.is_empty()
+        && login.len() <= 39
+        && login.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Creates a local account, logs it in, and asks the user to confirm their
+/// email address. Re-renders the registration form with the errors if the
+/// registration is invalid.
+#[post("/register", data = "<registration>")]
+fn register(
+    registration: LenientForm<Registration>,
+    conn: DbConn,
+    csrf: CsrfToken,
+    mut cookies: Cookies,
+    sessions: State<SessionConfig>,
+    auto_admin: State<AutoAdmin>,
+    client: ClientInfo,
+) -> CfpResult<SubmitResponse> {
+    let login = registration.login.trim();
+    let mut errors = vec![];
+    if !is_valid_login(login) {
+        errors.push("The login may only contain letters, digits, '-' and '_'.".to_string());
+    } else if User::get_by_login(&conn, login)?.is_some() {
+        errors.push(format!("The login '{}' is already taken.", login));
+    }
+    if registration.name.trim().is_empty() {
+        errors.push("The name must not be empty.".to_string());
+    }
+    errors.extend(validate_password(&registration.password, &registration.password_confirm));
+
+    if !errors.is_empty() {
+        let values = &*registration;
+        let page = Template::render("register", json!({
+            "errors": errors,
+            "values": values,
+            "csrf_token": csrf,
+        }));
+        return Ok(SubmitResponse::Invalid(page));
+    }
+
+    let id = conn.0.transaction::<_, Error, _>(|| {
+        let id = User::create(&conn, &NewUser {
+            login,
+            email: registration.email.trim(),
+            name: registration.name.trim(),
+            // Like the ones reported by providers, the address isn't trusted
+            // until the user confirms it.
+            email_confirmed: false,
+        }, false)?;
+        // If this is the first user to be created, make it an admin automatically.
+        auto_admin.apply(&conn, id)?;
+        LocalCredentials::set_password(&conn, id, &registration.password)?;
+        Ok(id)
+    })?;
+
+    log_in(&conn, &sessions, &mut cookies, &client, id)?;
+    Ok(SubmitResponse::Redirect(Redirect::to("/email")))
+}
+
+/// Shows the form to request a password reset.
+#[get("/password/reset")]
+fn reset_request_page(csrf: CsrfToken) -> Template {
+    Template::render("password_reset", json!({ "requested": false, "csrf_token": csrf }))
+}
+
+/// A struct containing the login to reset the password of.
+#[derive(FromForm)]
+struct ResetRequest {
+    login: String,
+}
+
+/// Creates a password reset link for the given login, and emails it to the
+/// user. Users without a confirmed email address have to ask the organizers,
+/// who can create a link with `cfp reset-password`. The response is the same
+/// whether or not the login exists.
+#[post("/password/reset", data = "<request>")]
+fn request_reset(request: LenientForm<ResetRequest>, conn: DbConn, mail: State<Mail>) -> CfpResult<Template> {
+    let user = User::get_by_login(&conn, request.login.trim())?;
+    if let Some(user) = user {
+        if user.email.is_empty() || !user.email_confirmed {
+            info!("Password reset requested for {}, who has no confirmed email address", user.login);
+        } else {
+            let token = create_reset_token(&conn, user.id)?;
+            mail.send(&user, "password_reset", &json!({ "token": token, "hours": RESET_VALIDITY_HOURS }));
+        }
+    }
+    Ok(Template::render("password_reset", json!({ "requested": true })))
+}
+
+/// Shows the form to choose a new password, if the reset `token` is valid.
+#[get("/password/reset/<token>")]
+fn reset_page(token: String, conn: DbConn, csrf: CsrfToken) -> CfpResult<Template> {
+    find_reset_token(&conn, &token)?.ok_or(Status::NotFound)?;
+    Ok(Template::render("password_new", json!({ "token": token, "errors": [], "csrf_token": csrf })))
+}
+
+/// A struct containing the new password.
+#[derive(FromForm)]
+struct NewPassword {
+    password: String,
+    password_confirm: String,
+}
+
+/// Sets a new password using a reset `token`, which is used up, and logs the
+/// user in. Every other session of the user ends.
+#[post("/password/reset/<token>", data = "<password>")]
+fn reset_password(
+    token: String,
+    password: LenientForm<NewPassword>,
+    conn: DbConn,
+    csrf: CsrfToken,
+    mut cookies: Cookies,
+    sessions: State<SessionConfig>,
+    client: ClientInfo,
+) -> CfpResult<SubmitResponse> {
+    let user_id = find_reset_token(&conn, &token)?.ok_or(Status::NotFound)?;
+
+    let errors = validate_password(&password.password, &password.password_confirm);
+    if !errors.is_empty() {
+        let page = Template::render("password_new", json!({
+            "token": token,
+            "errors": errors,
+            "csrf_token": csrf,
+        }));
+        return Ok(SubmitResponse::Invalid(page));
+    }
+
+    conn.0.transaction::<_, Error, _>(|| {
+        LocalCredentials::set_password(&conn, user_id, &password.password)?;
+        diesel::delete(password_resets::table.filter(password_resets::user.eq(user_id)))
+            .execute(&conn.0)?;
+        // Whoever knew the old password is logged out.
+        Session::revoke_all(&conn, user_id)?;
+        Ok(())
+    })?;
+
+    log_in(&conn, &sessions, &mut cookies, &client, user_id)?;
+    Ok(SubmitResponse::Redirect(Redirect::to("/")))
+}
+
+/// Returns a fairing that sets up local accounts:
+///
+/// * Reads whether local accounts are enabled.
+/// * If so, mounts the login, registration and password reset routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Local Accounts", |rocket| {
+        match LocalAccounts::from_config(rocket.config()) {
+            Ok(local) => {
+                let rocket = if local.enabled {
+                    rocket.mount("/", routes![
+                        login_submit,
+                        register_page,
+                        register,
+                        reset_request_page,
+                        request_reset,
+                        reset_page,
+                        reset_password
+                    ])
+                } else {
+                    rocket
+                };
+                Ok(rocket.manage(local))
+            }
+            Err(e) => {
+                error!("Invalid local accounts configuration: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
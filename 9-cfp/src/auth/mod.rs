@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use reqwest::{self, Url};
+use rocket::config::{self, Config, ConfigError, Table, Value};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::{Cookie, Cookies, SameSite, Status};
+use rocket::request::{State, Form};
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+
+use diesel::result::Error;
+
+use crate::DbConn;
+use csrf::{self, CsrfToken};
+use error::{CfpError, CfpResult};
+use role::Role;
+use session::{ClientInfo, Session, SessionConfig, SESSION_COOKIE};
+use user::{Identity, User};
+
+mod github;
+mod gitlab;
+pub mod local;
+mod oidc;
+
+use self::github::GitHub;
+use self::gitlab::GitLab;
+use self::local::LocalAccounts;
+use self::oidc::OpenIdConnect;
+
+/// Generates a new random state string.
+fn generate_state() -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(20).collect()
+}
+
+/// Logs in the user with the given `id` by starting a session for them, and
+/// setting a private cookie with the session token. The CSRF token is
+/// replaced along with the session, and expired sessions of any user are
+/// cleaned up along the way. Disabled users are refused with a `403 Forbidden`.
+pub fn log_in(
+    conn: &DbConn,
+    sessions: &SessionConfig,
+    cookies: &mut Cookies,
+    client: &ClientInfo,
+    id: i32,
+) -> CfpResult<()> {
+    if User::get(conn, id)?.disabled {
+        return Err(CfpError::new(Status::Forbidden, "This account has been disabled."));
+    }
+    Session::purge_expired(conn, sessions)?;
+    let token = Session::create(conn, id, client)?;
+    cookies.add_private(
+        Cookie::build(SESSION_COOKIE, token)
+            .same_site(SameSite::Lax)
+            .finish()
+    );
+    csrf::rotate(cookies);
+
+    Ok(())
+}
+
+/// A source of user identities using the OAuth 2 authorization code flow.
+pub trait OAuthProvider: Send + Sync {
+    /// A human-readable name of the provider, shown on the login page.
+    fn display_name(&self) -> &str;
+
+    /// The URL of the provider's authorization page, which the user is sent
+    /// to in order to log in.
+    fn authorize_url(&self, redirect_uri: &str, state: &str) -> String;
+
+    /// Exchanges the authorization `code` for an access token, and uses it to
+    /// retrieve the identity of the user who logged in.
+    fn identify(
+        &self,
+        client: &reqwest::Client,
+        redirect_uri: &str,
+        code: &str,
+    ) -> reqwest::Result<Identity>;
+}
+
+/// Whether the first user to sign up is made an admin, read from the
+/// `auto_admin` configuration parameter (enabled by default). Deployments
+/// that create their admin with `cfp create-admin` should disable it, so
+/// that nobody can take over a fresh instance by signing up first.
+pub struct AutoAdmin(pub bool);
+
+impl AutoAdmin {
+    pub fn from_config(config: &Config) -> config::Result<AutoAdmin> {
+        match config.get_bool("auto_admin") {
+            Ok(enabled) => Ok(AutoAdmin(enabled)),
+            Err(ConfigError::Missing(_)) => Ok(AutoAdmin(true)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Makes the user with the given `id`, who just signed up, an admin if
+    /// they are the first user. Call this in the transaction that inserted
+    /// them: the insert locks the database until the transaction ends, so two
+    /// users signing up at once can't both count as the first.
+    pub fn apply(&self, conn: &DbConn, id: i32) -> Result<(), Error> {
+        if self.0 && User::count(conn)? == 1 {
+            Role::grant_initial(conn, id, true)?;
+        }
+        Ok(())
+    }
+}
+
+/// The client ID and secret an application is registered with at a provider.
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl ClientCredentials {
+    fn from_table(name: &str, table: &Table) -> config::Result<ClientCredentials> {
+        let client_id = get_str(name, table, "client_id")?.to_string();
+        let client_secret = get_str(name, table, "client_secret")?.to_string();
+        Ok(ClientCredentials { client_id, client_secret })
+    }
+}
+
+/// Reads the string `key` from the configuration table of provider `name`.
+fn get_str<'a>(name: &str, table: &'a Table, key: &str) -> config::Result<&'a str> {
+    let full_key = format!("oauth.{}.{}", name, key);
+    match table.get(key) {
+        Some(Value::String(value)) => Ok(value.as_str()),
+        Some(value) => Err(ConfigError::BadType(full_key, "a string", value.type_str(), None)),
+        None => Err(ConfigError::Missing(full_key)),
+    }
+}
+
+/// Reads the URL `key` from the configuration table of provider `name`.
+/// Fails unless it is an absolute `http` or `https` URL. Trailing slashes are
+/// removed.
+fn get_required_url(name: &str, table: &Table, key: &str) -> config::Result<Url> {
+    parse_url(name, key, get_str(name, table, key)?)
+}
+
+/// Reads the optional URL `key` from the configuration table of provider
+/// `name`, like `get_required_url`, falling back to `default`.
+fn get_url(name: &str, table: &Table, key: &str, default: &str) -> config::Result<Url> {
+    match table.get(key) {
+        Some(_) => get_required_url(name, table, key),
+        None => parse_url(name, key, default),
+    }
+}
+
+/// Parses `url`, the value of `key` in the configuration table of provider
+/// `name`.
+fn parse_url(name: &str, key: &str, url: &str) -> config::Result<Url> {
+    Url::parse(url.trim_right_matches('/'))
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .ok_or_else(|| ConfigError::BadType(format!("oauth.{}.{}", name, key), "an http(s) URL", "string", None))
+}
+
+/// Returns `url` with the query parameters `params` appended.
+fn with_params(url: &Url, params: &[(&str, &str)]) -> String {
+    let mut url = url.clone();
+    url.query_pairs_mut().extend_pairs(params);
+    url.into_string()
+}
+
+/// The OAuth token exchange response, containing the API access token.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Exchanges an authorization `code` for an access token at `token_url`,
+/// using the standard OAuth 2 form-encoded token request.
+fn exchange_code(
+    client: &reqwest::Client,
+    token_url: &Url,
+    credentials: &ClientCredentials,
+    redirect_uri: &str,
+    code: &str,
+) -> reqwest::Result<String> {
+    let params = [
+        ("client_id", credentials.client_id.as_str()),
+        ("client_secret", credentials.client_secret.as_str()),
+        ("code", code),
+        ("grant_type", "authorization_code"),
+        ("redirect_uri", redirect_uri),
+    ];
+    let token_response: TokenResponse = client
+        .post(token_url.clone())
+        .header(reqwest::header::Accept::json())
+        .form(&params)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    Ok(token_response.access_token)
+}
+
+/// Reads the URL this application is reachable at from the `public_url`
+/// configuration parameter, which defaults to the address and port Rocket
+/// listens on.
+pub fn public_url(config: &Config) -> config::Result<String> {
+    match config.get_string("public_url") {
+        Ok(url) => Ok(url.trim_right_matches('/').to_string()),
+        Err(ConfigError::Missing(_)) => Ok(format!("http://{}:{}", config.address, config.port)),
+        Err(e) => Err(e),
+    }
+}
+
+/// All configured OAuth providers, keyed by the name used in their URLs.
+pub struct OAuthConfig {
+    pub providers: HashMap<String, Box<OAuthProvider>>,
+    /// The URL this application is reachable at, used to build redirect URIs.
+    pub public_url: String,
+}
+
+impl OAuthConfig {
+    /// Reads the provider configuration from the `oauth` table, which
+    /// contains one table per provider, e.g.:
+    ///
+    /// ```toml
+    /// [global.oauth.github]
+    /// client_id = "..."
+    /// client_secret = "..."
+    /// ```
+    ///
+    /// The kind of a provider is given by its `kind` key, which defaults to
+    /// the provider's name. For backwards compatibility, a GitHub provider is
+    /// also configured from `gh_client_id` and `gh_client_secret`.
+    pub fn from_config(config: &Config) -> config::Result<OAuthConfig> {
+        let mut providers: HashMap<String, Box<OAuthProvider>> = HashMap::new();
+
+        match config.get_table("oauth") {
+            Ok(table) => for (name, value) in table {
+                let provider = match value.as_table() {
+                    Some(provider) => provider,
+                    None => {
+                        let key = format!("oauth.{}", name);
+                        return Err(ConfigError::BadType(key, "a table", value.type_str(), None));
+                    }
+                };
+                let kind = match provider.get("kind") {
+                    Some(_) => get_str(name, provider, "kind")?,
+                    None => name.as_str(),
+                };
+                let provider: Box<OAuthProvider> = match kind {
+                    "github" => Box::new(GitHub::from_table(name, provider)?),
+                    "gitlab" => Box::new(GitLab::from_table(name, provider)?),
+                    "oidc" => Box::new(OpenIdConnect::from_table(name, provider)?),
+                    _ => {
+                        let key = format!("oauth.{}.kind", name);
+                        return Err(ConfigError::BadType(key, "github, gitlab or oidc", "string", None));
+                    }
+                };
+                providers.insert(name.clone(), provider);
+            },
+            Err(ConfigError::Missing(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        if !providers.contains_key("github") {
+            if let Ok(client_id) = config.get_string("gh_client_id") {
+                let client_secret = config.get_string("gh_client_secret")?;
+                let credentials = ClientCredentials { client_id, client_secret };
+                providers.insert("github".into(), Box::new(GitHub::new(credentials)?));
+            }
+        }
+
+        Ok(OAuthConfig { providers, public_url: public_url(config)? })
+    }
+
+    /// The URI the provider `name` redirects back to after logging in.
+    pub fn redirect_uri(&self, name: &str) -> String {
+        format!("{}/callback/{}", self.public_url, name)
+    }
+}
+
+/// A struct containing the authentication callback query parameters.
+#[derive(FromForm)]
+struct AuthParams {
+    code: String,
+    state: String,
+}
+
+/// The login page: either a redirect to the only provider, or a choice.
+#[derive(Responder)]
+enum LoginPage {
+    Redirect(Redirect),
+    Choose(Template),
+}
+
+/// Sends the user to the only configured provider, or lets them choose one if
+/// there are several (or if local accounts are enabled).
+#[get("/login")]
+fn login(
+    oauth_config: State<OAuthConfig>,
+    local: State<LocalAccounts>,
+    user: Option<User>,
+    csrf: CsrfToken,
+) -> LoginPage {
+    if oauth_config.providers.len() == 1 && !local.enabled {
+        let name = oauth_config.providers.keys().next().expect("one provider");
+        return LoginPage::Redirect(Redirect::to(format!("/login/{}", name)));
+    }
+
+    LoginPage::Choose(render_login(&oauth_config, &local, &csrf, user.as_ref(), None))
+}
+
+/// Renders the login page, offering every provider and, if enabled, the local
+/// login form. `error` explains why a previous local login failed.
+pub fn render_login(
+    oauth_config: &OAuthConfig,
+    local: &LocalAccounts,
+    csrf: &CsrfToken,
+    user: Option<&User>,
+    error: Option<&str>,
+) -> Template {
+    let mut providers: Vec<_> = oauth_config.providers
+        .iter()
+        .map(|(name, provider)| json!({ "name": name, "display_name": provider.display_name() }))
+        .collect();
+    providers.sort_by_key(|provider| provider["name"].as_str().map(String::from));
+    Template::render("login", json!({
+        "user": user,
+        "providers": providers,
+        "local": local.enabled,
+        "error": error,
+        "csrf_token": csrf,
+    }))
+}
+
+/// Generates a state string and redirects the user to the OAuth login flow of
+/// the given provider, which will later resume at the auth_callback.
+#[get("/login/<provider>")]
+fn login_with(
+    provider: String,
+    oauth_config: State<OAuthConfig>,
+    mut cookies: Cookies,
+) -> Option<Redirect> {
+    let oauth_provider = oauth_config.providers.get(&provider)?;
+    let state = generate_state();
+    cookies.add_private(
+        Cookie::build("oauth_state", state.clone())
+            .same_site(SameSite::Lax)
+            .finish()
+    );
+    let redirect_uri = oauth_config.redirect_uri(&provider);
+    Some(Redirect::to(oauth_provider.authorize_url(&redirect_uri, &state)))
+}
+
+/// The OAuth authentication callback. The provider's OAuth flow will redirect
+/// here after the user has logged in and granted the necessary permissions.
+///
+/// If a user is already logged in, the identity is linked to their account
+/// instead of logging in as whoever owns it.
+///
+/// Fails with a `400 Bad Request` if the state doesn't match the one issued
+/// when the login started, and with a `502 Bad Gateway` if the provider
+/// can't be reached or rejects the code.
+#[get("/callback/<provider>?<params..>")]
+fn auth_callback(
+    provider: String,
+    conn: DbConn,
+    params: Form<AuthParams>,
+    mut cookies: Cookies,
+    oauth_config: State<OAuthConfig>,
+    sessions: State<SessionConfig>,
+    auto_admin: State<AutoAdmin>,
+    client: ClientInfo,
+    current_user: Option<User>,
+) -> CfpResult<Redirect> {
+    let oauth_provider = oauth_config.providers.get(&provider).ok_or(Status::NotFound)?;
+
+    // Verify that the given state is the same one in the cookie.
+    match cookies.get_private("oauth_state") {
+        Some(ref cookie) if cookie.value() == params.state => {
+            cookies.remove(cookie.clone());
+        },
+        _ => {
+            return Err(CfpError::new(Status::BadRequest, "The login could not be verified. Please try logging in again."));
+        }
+    }
+
+    // Exchange the code for the user's identity at the provider.
+    let http = reqwest::Client::new();
+    let redirect_uri = oauth_config.redirect_uri(&provider);
+    let identity = oauth_provider.identify(&http, &redirect_uri, &params.code)?;
+
+    if let Some(user) = current_user {
+        if !User::link_identity(&conn, &user, &provider, &identity)? {
+            return Err(CfpError::new(Status::Conflict, "This account is already linked to another user."));
+        }
+        return Ok(Redirect::to("/accounts"));
+    }
+
+    // Get or create the user in the database. If this is the first user to be
+    // created, make it an admin automatically.
+    let id = User::get_or_create(&conn, &provider, &identity, &auto_admin)?;
+
+    // Start a session, and redirect to the dashboard.
+    log_in(&conn, &sessions, &mut cookies, &client, id)?;
+    Ok(Redirect::to("/"))
+}
+
+/// Lists the identities linked to the current user's account, and offers to
+/// link the remaining providers.
+#[get("/accounts")]
+fn accounts(conn: DbConn, user: User, oauth_config: State<OAuthConfig>) -> CfpResult<Template> {
+    let identities = User::get_identities(&conn, &user)?;
+    let mut unlinked: Vec<_> = oauth_config.providers
+        .iter()
+        .filter(|&(name, _)| !identities.iter().any(|identity| &identity.provider == name))
+        .map(|(name, provider)| json!({ "name": name, "display_name": provider.display_name() }))
+        .collect();
+    unlinked.sort_by_key(|provider| provider["name"].as_str().map(String::from));
+    Ok(Template::render("accounts", json!({
+        "user": user,
+        "identities": identities,
+        "providers": unlinked,
+    })))
+}
+
+/// Returns a fairing that sets up OAuth for the application:
+///
+/// * Reads the OAuth provider configuration, and whether the first user is
+///   made an admin.
+/// * Mounts the login routes and the authentication callback.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("OAuth", |rocket| {
+        let auto_admin = match AutoAdmin::from_config(rocket.config()) {
+            Ok(auto_admin) => auto_admin,
+            Err(e) => {
+                error!("Invalid auto_admin configuration: {}", e);
+                return Err(rocket);
+            }
+        };
+        match OAuthConfig::from_config(rocket.config()) {
+            Ok(config) => Ok(rocket
+                .manage(config)
+                .manage(auto_admin)
+                .mount("/", routes![login, login_with, auth_callback, accounts])
+            ),
+            Err(e) => {
+                error!("Invalid OAuth configuration: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
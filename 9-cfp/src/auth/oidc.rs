@@ -0,0 +1,97 @@
+use reqwest::{self, Url};
+use rocket::config::{self, Table};
+
+use super::{exchange_code, get_required_url, get_str, with_params, ClientCredentials, OAuthProvider};
+use user::Identity;
+
+const DEFAULT_SCOPE: &str = "openid profile email";
+
+/// The standard claims retrieved from an OpenID Connect userinfo endpoint.
+#[derive(Deserialize)]
+struct UserInfo {
+    sub: String,
+    preferred_username: Option<String>,
+    name: Option<String>,
+    email: Option<String>,
+}
+
+/// Logs users in with any OpenID Connect provider. The endpoints have to be
+/// configured explicitly; discovery is not supported. Users are identified by
+/// their `sub` claim.
+pub struct OpenIdConnect {
+    credentials: ClientCredentials,
+    display_name: String,
+    authorization_endpoint: Url,
+    token_endpoint: Url,
+    userinfo_endpoint: Url,
+    scope: String,
+}
+
+impl OpenIdConnect {
+    /// Reads the `client_id`, `client_secret`, `display_name`,
+    /// `authorization_endpoint`, `token_endpoint`, `userinfo_endpoint` and
+    /// optional `scope` of the provider `name`.
+    pub fn from_table(name: &str, table: &Table) -> config::Result<OpenIdConnect> {
+        let optional = |key: &str, default: &str| match table.get(key) {
+            Some(_) => get_str(name, table, key).map(String::from),
+            None => Ok(default.to_string()),
+        };
+
+        Ok(OpenIdConnect {
+            credentials: ClientCredentials::from_table(name, table)?,
+            display_name: optional("display_name", name)?,
+            authorization_endpoint: get_required_url(name, table, "authorization_endpoint")?,
+            token_endpoint: get_required_url(name, table, "token_endpoint")?,
+            userinfo_endpoint: get_required_url(name, table, "userinfo_endpoint")?,
+            scope: optional("scope", DEFAULT_SCOPE)?,
+        })
+    }
+}
+
+impl OAuthProvider for OpenIdConnect {
+    fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    fn authorize_url(&self, redirect_uri: &str, state: &str) -> String {
+        with_params(&self.authorization_endpoint, &[
+            ("client_id", self.credentials.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("response_type", "code"),
+            ("scope", self.scope.as_str()),
+            ("state", state),
+        ])
+    }
+
+    fn identify(
+        &self,
+        client: &reqwest::Client,
+        redirect_uri: &str,
+        code: &str,
+    ) -> reqwest::Result<Identity> {
+        let access_token = exchange_code(
+            client,
+            &self.token_endpoint,
+            &self.credentials,
+            redirect_uri,
+            code,
+        )?;
+
+        let user_info: UserInfo = client
+            .get(self.userinfo_endpoint.clone())
+            .header(reqwest::header::Authorization(format!("Bearer {}", access_token)))
+            .header(reqwest::header::Accept::json())
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        let login = user_info.preferred_username.unwrap_or_else(|| user_info.sub.clone());
+        Ok(Identity {
+            subject: user_info.sub,
+            legacy_subject: None,
+            name: user_info.name.unwrap_or_else(|| login.clone()),
+            login,
+            email: user_info.email,
+        })
+    }
+}
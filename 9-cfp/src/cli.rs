@@ -0,0 +1,190 @@
+use diesel::result::Error;
+use diesel::Connection;
+use rocket::http::RawStr;
+use rocket::request::FromFormValue;
+use rocket::Rocket;
+
+use audit::AdminEvent;
+use auth::local::{self, LocalAccounts};
+use auth::OAuthConfig;
+use import;
+use mail::Mail;
+use role::{Admin, ProgramChair, Role};
+use talk::{StatusChange, Talk, TalkStatus};
+use user::{NewUser, User};
+use {embedded_migrations, DbConn};
+
+const USAGE: &str = "Usage: cfp [command]
+
+Commands:
+  serve                                    Run the web server (the default)
+  migrate                                  Apply pending database migrations
+  create-admin <login>                     Make a user an admin, creating a local account if there is none
+  reset-password <login>                   Print a link for a user to choose a new password
+  list-users                               List all users and the roles they were granted
+  set-status <talk> <status> --as <login>  Decide on a talk as the given program chair
+  import [--dry-run] <file>                Import talks from a .csv or .json file";
+
+/// The outcome of a command: an error message if it failed.
+type CliResult = Result<(), String>;
+
+fn database_error(e: Error) -> String {
+    format!("Database error: {}", e)
+}
+
+/// Connects to the database `rocket` is configured with.
+fn connect(rocket: &Rocket) -> Result<DbConn, String> {
+    DbConn::get_one(rocket).ok_or_else(|| "Could not connect to the database. Check its configuration.".to_string())
+}
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+/// Runs `cfp <command> <args>`, using the database and configuration of
+/// `rocket`. Returns the exit code: `0` on success, `1` if the command
+/// failed, or `2` if it was used wrongly.
+pub fn run(rocket: Rocket, command: &str, args: &[String]) -> i32 {
+    let result = match (command, args) {
+        ("import", _) => return import::run_cli(rocket, args),
+        ("migrate", []) => migrate(&rocket),
+        ("create-admin", [login]) => create_admin(&rocket, login),
+        ("reset-password", [login]) => reset_password(&rocket, login),
+        ("list-users", []) => list_users(&rocket),
+        ("set-status", [talk, status, flag, login]) if flag == "--as" => set_status(&rocket, talk, status, login),
+        _ => return usage(),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Applies pending migrations without starting the server.
+fn migrate(rocket: &Rocket) -> CliResult {
+    let conn = connect(rocket)?;
+    embedded_migrations::run(&*conn).map_err(|e| format!("Failed to run database migrations: {}", e))?;
+    println!("The database is up to date.");
+    Ok(())
+}
+
+/// Makes the user with the given `login` an admin. If there is no such user
+/// yet and local accounts are enabled, creates one, and prints a link to
+/// choose its password.
+fn create_admin(rocket: &Rocket, login: &str) -> CliResult {
+    let conn = connect(rocket)?;
+    if let Some(user) = User::get_by_login(&conn, login).map_err(database_error)? {
+        // Whoever runs the command line has full access to the database, so
+        // the user is recorded as having made themselves an admin.
+        let admin = Admin(user);
+        conn.0.transaction::<_, Error, _>(|| {
+            Role::grant(&conn, &admin, admin.0.id, Role::Organizer)?;
+            AdminEvent::record(&conn, &admin.0, "became an admin through the command line")
+        }).map_err(database_error)?;
+        println!("{} is now an admin.", admin.0.login);
+        return Ok(());
+    }
+
+    if !rocket.state::<LocalAccounts>().map_or(false, |local| local.enabled) {
+        return Err(format!("There is no user {}. Log in as them first, then run this again.", login));
+    }
+    if !local::is_valid_login(login) {
+        return Err("The login may only contain letters, digits, '-' and '_'.".to_string());
+    }
+    let token = conn.0.transaction::<_, Error, _>(|| {
+        let id = User::create(&conn, &NewUser {
+            login,
+            email: "",
+            name: login,
+            email_confirmed: false,
+        }, true)?;
+        AdminEvent::record(&conn, &User::get(&conn, id)?, "became an admin through the command line")?;
+        local::create_reset_token(&conn, id)
+    }).map_err(database_error)?;
+
+    println!("Created the admin {}. Choose its password within {} hours at:", login, local::RESET_VALIDITY_HOURS);
+    println!("{}", reset_link(rocket, &token));
+    Ok(())
+}
+
+/// Returns the link to choose a new password with the reset `token`.
+fn reset_link(rocket: &Rocket, token: &str) -> String {
+    let public_url = rocket.state::<OAuthConfig>().map_or("", |config| config.public_url.as_str());
+    format!("{}/password/reset/{}", public_url, token)
+}
+
+/// Prints a link for the user with the given `login` to choose a new
+/// password. Users without a confirmed email address can't reset their
+/// password themselves, so the organizers hand them this link instead.
+fn reset_password(rocket: &Rocket, login: &str) -> CliResult {
+    if !rocket.state::<LocalAccounts>().map_or(false, |local| local.enabled) {
+        return Err("Local accounts are disabled.".to_string());
+    }
+    let conn = connect(rocket)?;
+    let user = User::get_by_login(&conn, login)
+        .map_err(database_error)?
+        .ok_or_else(|| format!("There is no user {}.", login))?;
+    let token = local::create_reset_token(&conn, user.id).map_err(database_error)?;
+    println!("{} can choose a new password within {} hours at:", user.login, local::RESET_VALIDITY_HOURS);
+    println!("{}", reset_link(rocket, &token));
+    Ok(())
+}
+
+/// Prints every user, one per line: their ID, login, name, email address and
+/// granted roles, separated by tabs, and whether they are disabled.
+fn list_users(rocket: &Rocket) -> CliResult {
+    let conn = connect(rocket)?;
+    for user in User::get_all(&conn).map_err(database_error)? {
+        let roles: Vec<_> = Role::get_granted(&conn, user.id)
+            .map_err(database_error)?
+            .iter()
+            .map(|role| format!("{:?}", role))
+            .collect();
+        println!(
+            "{}\t{}\t{}\t{}\t{}{}",
+            user.id,
+            user.login,
+            user.name,
+            user.email,
+            roles.join(","),
+            if user.disabled { "\tdisabled" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+/// Sets the status of a talk on behalf of the program chair with the given
+/// `login`, who is recorded in the talk's history, and tells the presenters
+/// about the decision like the web interface does.
+fn set_status(rocket: &Rocket, talk: &str, status: &str, login: &str) -> CliResult {
+    let id: i32 = talk.parse().map_err(|_| format!("Invalid talk ID: '{}'.", talk))?;
+    let status = TalkStatus::from_form_value(RawStr::from_str(status))
+        .map_err(|_| format!("Unknown status: '{}'.", status))?;
+
+    let conn = connect(rocket)?;
+    let user = User::get_by_login(&conn, login)
+        .map_err(database_error)?
+        .ok_or_else(|| format!("There is no user {}.", login))?;
+    if !user.roles.program_chair {
+        return Err(format!("{} is not a program chair.", login));
+    }
+    let chair = ProgramChair(user);
+
+    match Talk::set_status(&conn, &chair, id, status, None).map_err(database_error)? {
+        StatusChange::NotFound => return Err(format!("There is no talk {}.", id)),
+        StatusChange::Unchanged => {
+            println!("Talk {} already is {:?}.", id, status);
+            return Ok(());
+        }
+        StatusChange::Changed => {}
+    }
+    if let (Some(mail), Ok(Some(talk))) = (rocket.state::<Mail>(), Talk::get_one(&conn, &chair.0, id)) {
+        mail.send_decision(&conn, &talk);
+    }
+    println!("Talk {} is now {:?}.", id, status);
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use rocket::data::Data;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::uri::Origin;
+use rocket::http::{Cookie, Cookies, Method, SameSite, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+
+use error::CfpError;
+
+/// The name of the form field carrying the token. Forms must send it right
+/// after `_method`, if they have one, and before any other field: only the
+/// beginning of a request body can be inspected before routing. Routes read
+/// forms as a `LenientForm`, which ignores the field.
+pub const CSRF_FIELD: &str = "csrf_token";
+
+/// The header scripts can send the token in instead.
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// The name of the private cookie holding the token.
+const CSRF_COOKIE: &str = "csrf";
+
+/// Where requests without a valid token are sent instead of their route.
+const FAILURE_PATH: &str = "/csrf";
+
+/// The token that must be submitted with every form, proving that the form
+/// was served by this application rather than by another site. Each browser
+/// gets its own token, and a new one whenever someone logs in with it.
+///
+/// Routes rendering templates with forms pass it on as `csrf_token`.
+#[derive(Serialize)]
+pub struct CsrfToken(String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for CsrfToken {
+    type Error = ();
+
+    /// Retrieves the token from the cookie, or issues a new one.
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let mut cookies = req.cookies();
+        if let Some(cookie) = cookies.get_private(CSRF_COOKIE) {
+            return Outcome::Success(CsrfToken(cookie.value().to_string()));
+        }
+
+        let token: String = thread_rng().sample_iter(&Alphanumeric).take(32).collect();
+        cookies.add_private(
+            Cookie::build(CSRF_COOKIE, token.clone())
+                .same_site(SameSite::Lax)
+                .finish()
+        );
+        Outcome::Success(CsrfToken(token))
+    }
+}
+
+/// Discards the token of the current browser, so that a new one is issued
+/// with the next form. Called when someone logs in, so that a token planted
+/// before doesn't outlive the session, and when they log out, here or
+/// everywhere.
+pub fn rotate(cookies: &mut Cookies) {
+    cookies.remove_private(Cookie::named(CSRF_COOKIE));
+}
+
+/// Compares two tokens in constant time.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Finds the token among the fields of a URL-encoded form. `complete` says
+/// whether `body` is the whole body, or just its beginning, in which case the
+/// last field may be cut off.
+fn form_token(body: &[u8], complete: bool) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let fields: Vec<&str> = body.split('&').collect();
+    let usable = if complete { fields.len() } else { fields.len() - 1 };
+    let prefix = format!("{}=", CSRF_FIELD);
+    fields[..usable]
+        .iter()
+        .find(|field| field.starts_with(&prefix))
+        .map(|field| field[prefix.len()..].to_string())
+}
+
+/// Finds the token in the beginning of a `multipart/form-data` body.
+fn multipart_token(body: &[u8]) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let field = &body[body.find(&format!("name=\"{}\"", CSRF_FIELD))?..];
+    let value = &field[field.find("\r\n\r\n")? + 4..];
+    Some(value[..value.find("\r\n")?].to_string())
+}
+
+/// Returns whether `req` is one a form on another site could have made: any
+/// request that isn't `GET`, `HEAD` or `OPTIONS`, with a body a form can send.
+/// Requests authenticated with an API token don't rely on cookies, and so are
+/// left alone.
+fn needs_token(req: &Request) -> bool {
+    match req.method() {
+        Method::Get | Method::Head | Method::Options => return false,
+        _ => {}
+    }
+    if req.headers().contains("Authorization") {
+        return false;
+    }
+    match req.content_type() {
+        Some(content_type) => content_type.is_form() || content_type.is_form_data() || content_type.is_plain(),
+        None => true,
+    }
+}
+
+/// Returns whether `req` carries the token from its cookie, in the header or
+/// in the form.
+fn has_valid_token(req: &Request, data: &Data) -> bool {
+    let expected = match req.cookies().get_private(CSRF_COOKIE) {
+        Some(cookie) => cookie.value().to_string(),
+        None => return false,
+    };
+    let submitted = match req.headers().get_one(CSRF_HEADER) {
+        Some(token) => Some(token.to_string()),
+        None => match req.content_type() {
+            Some(content_type) if content_type.is_form() => form_token(data.peek(), data.peek_complete()),
+            Some(content_type) if content_type.is_form_data() => multipart_token(data.peek()),
+            _ => None,
+        },
+    };
+    submitted.map_or(false, |token| tokens_match(&token, &expected))
+}
+
+/// Explains that a form was rejected for lacking a valid token.
+#[get("/csrf")]
+fn csrf_failure() -> CfpError {
+    CfpError::new(
+        Status::Forbidden,
+        "The form you submitted has expired, or was sent from another site. \
+         Go back, reload the page and try again.",
+    )
+}
+
+/// Returns a fairing that protects forms against cross-site request forgery:
+/// requests a form could have made are routed to an error page instead,
+/// unless they carry the current browser's `CsrfToken`.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("CSRF Protection", |rocket| {
+        Ok(rocket
+            .mount("/", routes![csrf_failure])
+            .attach(AdHoc::on_request("CSRF Check", |req, data| {
+                if needs_token(req) && !has_valid_token(req, data) {
+                    warn!("Rejected {} {} without a valid CSRF token", req.method(), req.uri());
+                    req.set_method(Method::Get);
+                    req.set_uri(Origin::parse(FAILURE_PATH).expect("valid failure path"));
+                }
+            }))
+        )
+    })
+}
@@ -0,0 +1,218 @@
+use std;
+use std::fmt;
+
+use diesel;
+use reqwest;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::Catcher;
+use rocket_contrib::templates::Template;
+
+use api::ApiError;
+use user::User;
+
+/// An error handling a request. Responds with an error page, or with a JSON
+/// error for API clients. Internal errors are logged along with the request
+/// they occurred in, but their causes are never shown to the user.
+#[derive(Debug)]
+pub enum CfpError {
+    /// A plain error status, rendered by the catcher for it.
+    Status(Status),
+    /// An error status, along with a message explaining it to the user.
+    Message(Status, String),
+    /// A failed database query, reported as a `500 Internal Server Error`.
+    Database(diesel::result::Error),
+    /// A failed request to another service, such as an OAuth provider, or an
+    /// unexpected response from it, reported as a `502 Bad Gateway`.
+    Upstream(reqwest::Error),
+}
+
+pub type CfpResult<T> = Result<T, CfpError>;
+
+impl CfpError {
+    /// An error with `status`, explained to the user by `message`.
+    pub fn new<S: Into<String>>(status: Status, message: S) -> CfpError {
+        CfpError::Message(status, message.into())
+    }
+
+    /// The status the error is reported with.
+    pub fn status(&self) -> Status {
+        match *self {
+            CfpError::Status(status) | CfpError::Message(status, _) => status,
+            CfpError::Database(_) => Status::InternalServerError,
+            CfpError::Upstream(_) => Status::BadGateway,
+        }
+    }
+
+    /// Logs the error as `key=value` pairs, so that failures can be told
+    /// apart by kind. Only internal errors are logged as errors; Rocket
+    /// already logs every response status.
+    pub fn log(&self, req: &Request) {
+        let status = self.status().code;
+        match *self {
+            CfpError::Status(_) => {}
+            CfpError::Message(_, ref message) => {
+                info!("method={} uri={} status={} kind=rejected message={:?}", req.method(), req.uri(), status, message)
+            }
+            CfpError::Database(ref e) => {
+                error!("method={} uri={} status={} kind=database error={:?}", req.method(), req.uri(), status, e.to_string())
+            }
+            CfpError::Upstream(ref e) => {
+                error!("method={} uri={} status={} kind=upstream error={:?}", req.method(), req.uri(), status, e.to_string())
+            }
+        }
+    }
+}
+
+impl fmt::Display for CfpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CfpError::Status(status) => write!(f, "{}", status),
+            CfpError::Message(status, ref message) => write!(f, "{}: {}", status, message),
+            CfpError::Database(ref e) => write!(f, "database error: {}", e),
+            CfpError::Upstream(ref e) => write!(f, "upstream error: {}", e),
+        }
+    }
+}
+
+impl From<Status> for CfpError {
+    fn from(status: Status) -> CfpError {
+        CfpError::Status(status)
+    }
+}
+
+impl From<diesel::result::Error> for CfpError {
+    fn from(e: diesel::result::Error) -> CfpError {
+        CfpError::Database(e)
+    }
+}
+
+impl From<reqwest::Error> for CfpError {
+    fn from(e: reqwest::Error) -> CfpError {
+        CfpError::Upstream(e)
+    }
+}
+
+impl<'r> Responder<'r> for CfpError {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        self.log(req);
+        match self {
+            CfpError::Message(status, message) => {
+                let mut response = error_page(req, status, Some(message)).respond_to(req)?;
+                response.set_status(status);
+                Ok(response)
+            }
+            // Everything else is left to the catchers.
+            error => Err(error.status()),
+        }
+    }
+}
+
+/// An integer read from the database that doesn't correspond to any variant
+/// of the enum stored as it.
+#[derive(Debug)]
+pub struct InvalidValue {
+    pub type_name: &'static str,
+    pub value: i32,
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {} value: {}", self.type_name, self.value)
+    }
+}
+
+impl std::error::Error for InvalidValue {}
+
+/// An error page: a JSON error body for API clients, or HTML otherwise.
+#[derive(Responder)]
+enum ErrorPage {
+    Json(ApiError),
+    Html(Template),
+}
+
+/// Renders the error page for `status`, with `message` if there is one.
+/// Requests to the API, and requests that prefer JSON, get a JSON body.
+fn error_page(req: &Request, status: Status, message: Option<String>) -> ErrorPage {
+    let wants_json = req.uri().path().starts_with("/api/")
+        || req.accept().map_or(false, |accept| accept.preferred().media_type().is_json());
+    if wants_json {
+        let message = message.unwrap_or_else(|| status.reason.to_string());
+        ErrorPage::Json(ApiError::new(status, message))
+    } else {
+        ErrorPage::Html(Template::render("error", json!({
+            "user": req.guard::<User>().succeeded(),
+            "code": status.code,
+            "reason": status.reason,
+            "message": message,
+        })))
+    }
+}
+
+#[catch(400)]
+fn bad_request(req: &Request) -> ErrorPage {
+    error_page(req, Status::BadRequest, None)
+}
+
+#[catch(401)]
+fn unauthorized(req: &Request) -> ErrorPage {
+    error_page(req, Status::Unauthorized, None)
+}
+
+#[catch(403)]
+fn forbidden(req: &Request) -> ErrorPage {
+    error_page(req, Status::Forbidden, None)
+}
+
+#[catch(404)]
+fn not_found(req: &Request) -> ErrorPage {
+    error_page(req, Status::NotFound, None)
+}
+
+#[catch(409)]
+fn conflict(req: &Request) -> ErrorPage {
+    error_page(req, Status::Conflict, None)
+}
+
+#[catch(413)]
+fn payload_too_large(req: &Request) -> ErrorPage {
+    error_page(req, Status::PayloadTooLarge, None)
+}
+
+#[catch(415)]
+fn unsupported_media_type(req: &Request) -> ErrorPage {
+    error_page(req, Status::UnsupportedMediaType, None)
+}
+
+#[catch(422)]
+fn unprocessable_entity(req: &Request) -> ErrorPage {
+    error_page(req, Status::UnprocessableEntity, None)
+}
+
+#[catch(500)]
+fn internal_error(req: &Request) -> ErrorPage {
+    error_page(req, Status::InternalServerError, None)
+}
+
+#[catch(502)]
+fn bad_gateway(req: &Request) -> ErrorPage {
+    error_page(req, Status::BadGateway, None)
+}
+
+/// Returns the catchers rendering error pages for the errors the application
+/// responds with.
+pub fn catchers() -> Vec<Catcher> {
+    catchers![
+        bad_request,
+        unauthorized,
+        forbidden,
+        not_found,
+        conflict,
+        payload_too_large,
+        unsupported_media_type,
+        unprocessable_entity,
+        internal_error,
+        bad_gateway
+    ]
+}
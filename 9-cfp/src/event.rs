@@ -0,0 +1,492 @@
+use chrono::NaiveDateTime;
+use diesel;
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::Connection;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::request::{LenientForm, State};
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+
+use audit::AdminEvent;
+use csrf::CsrfToken;
+use error::{CfpError, CfpResult};
+use mail::Mail;
+use role::{Admin, Speaker};
+use schema::{event_admins, events};
+use talk::{Resubmission, Talk};
+use user::User;
+use window::{CfpWindow, FormTimestamp};
+use {DbConn, NewTalk, SubmitResponse};
+
+/// An event talks are submitted to, such as one edition of a conference.
+/// Events without their own CFP window use the one from the configuration.
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct Event {
+    pub id: i32,
+    /// Identifies the event in URLs, e.g. `/e/<slug>/talks`
+    pub slug: String,
+    pub name: String,
+    pub cfp_opens: Option<NaiveDateTime>,
+    pub cfp_closes: Option<NaiveDateTime>,
+    /// Whether talks are reviewed without knowing who presents them. Only
+    /// organizers see the presenters of talks under blind review.
+    pub blind_review: bool,
+}
+
+/// An event to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "events"]
+struct NewEvent<'a> {
+    pub slug: &'a str,
+    pub name: &'a str,
+}
+
+/// An event administrator to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "event_admins"]
+struct NewEventAdmin {
+    pub event: i32,
+    pub user: i32,
+}
+
+/// The longest slug an event can have, in characters.
+const MAX_SLUG_LENGTH: usize = 50;
+
+/// Returns whether `slug` can identify an event: it must consist of lowercase
+/// letters, digits and dashes only.
+fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= MAX_SLUG_LENGTH
+        && slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+impl Event {
+    /// Retrieve all events, oldest first
+    pub fn get_all(conn: &DbConn) -> Result<Vec<Event>, Error> {
+        events::table.order(events::id).load(&conn.0)
+    }
+
+    /// Get a given event by `id`. Returns an error when the event does not exist
+    pub fn get(conn: &DbConn, id: i32) -> Result<Event, Error> {
+        events::table.filter(events::id.eq(id)).first(&conn.0)
+    }
+
+    /// Get a given event by `slug`, if it exists.
+    pub fn get_by_slug(conn: &DbConn, slug: &str) -> Result<Option<Event>, Error> {
+        events::table
+            .filter(events::slug.eq(slug))
+            .first(&conn.0)
+            .optional()
+    }
+
+    /// Get the event that talks are submitted to when no event is named: the
+    /// oldest one.
+    pub fn get_default(conn: &DbConn) -> Result<Event, Error> {
+        events::table.order(events::id).first(&conn.0)
+    }
+
+    /// Inserts a new event. Fails with a unique violation if the slug is
+    /// already taken.
+    pub fn create(conn: &DbConn, _admin: &Admin, slug: &str, name: &str) -> Result<(), Error> {
+        diesel::insert_into(events::table)
+            .values(NewEvent { slug, name })
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Replaces the name, CFP window and review mode of the administered
+    /// event. A `None` end of the window falls back to the configured one.
+    pub fn update(
+        conn: &DbConn,
+        admin: &EventAdmin,
+        name: &str,
+        cfp_opens: Option<NaiveDateTime>,
+        cfp_closes: Option<NaiveDateTime>,
+        blind_review: bool,
+    ) -> Result<(), Error> {
+        diesel::update(events::table.filter(events::id.eq(admin.event.id)))
+            .set((
+                events::name.eq(name),
+                events::cfp_opens.eq(cfp_opens),
+                events::cfp_closes.eq(cfp_closes),
+                events::blind_review.eq(blind_review),
+            ))
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Returns whether `user` administers this event: organizers administer
+    /// every event, and others only those they were made administrators of.
+    /// Administering an event means managing its settings and administrators;
+    /// reviewing and deciding on its talks takes the global roles.
+    pub fn is_admin(&self, conn: &DbConn, user: &User) -> Result<bool, Error> {
+        if user.roles.organizer {
+            return Ok(true);
+        }
+        diesel::select(exists(
+            event_admins::table.filter(event_admins::event.eq(self.id).and(event_admins::user.eq(user.id))),
+        ))
+        .get_result(&conn.0)
+    }
+
+    /// Retrieve the users who were made administrators of this event, not
+    /// counting organizers
+    pub fn get_admins(&self, conn: &DbConn) -> Result<Vec<User>, Error> {
+        let ids: Vec<i32> = event_admins::table
+            .filter(event_admins::event.eq(self.id))
+            .select(event_admins::user)
+            .order(event_admins::user)
+            .load(&conn.0)?;
+        ids.into_iter().map(|id| User::get(conn, id)).collect()
+    }
+
+    /// Makes the user with the given `id` an administrator of the administered
+    /// event. Adding an administrator twice has no effect.
+    pub fn add_admin(conn: &DbConn, admin: &EventAdmin, id: i32) -> Result<(), Error> {
+        diesel::replace_into(event_admins::table)
+            .values(NewEventAdmin { event: admin.event.id, user: id })
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Removes the user with the given `id` from the administrators of the
+    /// administered event. Returns whether they were one.
+    pub fn remove_admin(conn: &DbConn, admin: &EventAdmin, id: i32) -> Result<bool, Error> {
+        let deleted = diesel::delete(
+            event_admins::table.filter(event_admins::event.eq(admin.event.id).and(event_admins::user.eq(id))),
+        )
+        .execute(&conn.0)?;
+
+        Ok(deleted > 0)
+    }
+}
+
+/// A User who is allowed to change the settings of `event`.
+pub struct EventAdmin {
+    pub user: User,
+    pub event: Event,
+}
+
+impl EventAdmin {
+    /// Returns `user` as an administrator of `event`, or `None` if they
+    /// aren't one.
+    pub fn check(conn: &DbConn, user: User, event: Event) -> Result<Option<EventAdmin>, Error> {
+        if event.is_admin(conn, &user)? {
+            Ok(Some(EventAdmin { user, event }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Retrieves the event with the given `slug`, failing with a 404 if there is
+/// none.
+pub fn find_event(conn: &DbConn, slug: &str) -> CfpResult<Event> {
+    Ok(Event::get_by_slug(conn, slug)?.ok_or(Status::NotFound)?)
+}
+
+/// Retrieves the event with the given `slug`, making sure that `user`
+/// administers it.
+fn administered_event(conn: &DbConn, user: User, slug: &str) -> CfpResult<EventAdmin> {
+    let event = find_event(conn, slug)?;
+    Ok(EventAdmin::check(conn, user, event)?.ok_or(Status::Forbidden)?)
+}
+
+/// Lists all events.
+#[get("/events")]
+fn list_events(conn: DbConn, user: User, csrf: CsrfToken) -> CfpResult<Template> {
+    let events = Event::get_all(&conn)?;
+    Ok(Template::render("events", json!({ "user": user, "events": events, "csrf_token": csrf })))
+}
+
+/// A struct containing the slug and name of a new event.
+#[derive(FromForm)]
+struct NewEventForm {
+    slug: String,
+    name: String,
+}
+
+/// Adds an event. Slugs must be unique.
+#[post("/events", data = "<event>")]
+fn create_event(event: LenientForm<NewEventForm>, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let (slug, name) = (event.slug.trim(), event.name.trim());
+    if !is_valid_slug(slug) || name.is_empty() {
+        return Err(Status::UnprocessableEntity.into());
+    }
+    let created = conn.0.transaction::<_, Error, _>(|| {
+        Event::create(&conn, &admin, slug, name)?;
+        AdminEvent::record(&conn, &admin.0, &format!("created event {}", slug))
+    });
+    match created {
+        Ok(()) => {
+            info!("{} created event {}", admin.0.login, slug);
+            Ok(Redirect::to(format!("/e/{}/talks", slug)))
+        }
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Err(Status::Conflict.into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Renders the `event` template: the CFP window of `event`, the talks `user`
+/// submitted to it, and their talks from other events that could be
+/// resubmitted. When a submission was rejected, `form` and `errors` are used
+/// to fill the form back in and explain why.
+fn render_event(
+    conn: &DbConn,
+    user: &User,
+    window: &CfpWindow,
+    event: &Event,
+    csrf: &CsrfToken,
+    form: Option<&NewTalk>,
+    errors: &[String],
+) -> CfpResult<Template> {
+    let render = || -> Result<Template, Error> {
+        let talks = Talk::get_for_user_in(conn, user, event)?;
+        let resubmitted: Vec<i32> = talks.iter().filter_map(|talk| talk.resubmitted_from).collect();
+        let resubmittable: Vec<_> = Talk::get_for_user(conn, user)?
+            .into_iter()
+            .filter(|talk| talk.event != event.id && !resubmitted.contains(&talk.id))
+            .collect();
+        let is_admin = event.is_admin(conn, user)?;
+        let values = form.cloned().unwrap_or_default();
+        Ok(Template::render("event", json!({
+            "user": user,
+            "event": event,
+            "is_admin": is_admin,
+            "talks": talks,
+            "resubmittable": resubmittable,
+            "cfp": window.for_event(event).status(),
+            "values": values,
+            "errors": errors,
+            "csrf_token": csrf,
+        })))
+    };
+    render().map_err(CfpError::from)
+}
+
+/// The outcome of submitting a talk through a web form.
+pub enum Submission {
+    /// The talk was submitted, and got the given ID.
+    Submitted(i32),
+    /// The form was invalid for the given reasons, and has to be rendered
+    /// again.
+    Invalid(Vec<String>),
+}
+
+/// Submits the talk filled into `form`, presented by `user`, to `event`, and
+/// emails them a receipt. Fails with `403 Forbidden` outside the CFP window
+/// of `event`. Used for the default event by the `new_talk` route.
+pub fn submit(
+    conn: &DbConn,
+    user: &User,
+    window: &CfpWindow,
+    mail: &Mail,
+    event: &Event,
+    form: &NewTalk,
+) -> CfpResult<Submission> {
+    if !window.is_open_for(event) {
+        return Err(Status::Forbidden.into());
+    }
+    let details = match form.validate() {
+        Ok(details) => details,
+        Err(errors) => return Ok(Submission::Invalid(errors)),
+    };
+    let id = Talk::new(conn, user, event, &details)?;
+    mail.send(user, "received", &json!({ "talk": { "id": id, "title": details.title } }));
+    Ok(Submission::Submitted(id))
+}
+
+/// Shows an event: its CFP, and the current user's talks submitted to it.
+#[get("/e/<slug>/talks")]
+fn event_page(
+    slug: String,
+    conn: DbConn,
+    user: User,
+    window: State<CfpWindow>,
+    csrf: CsrfToken,
+) -> CfpResult<Template> {
+    let event = find_event(&conn, &slug)?;
+    render_event(&conn, &user, &window, &event, &csrf, None, &[])
+}
+
+/// Submits a talk to an event, like the `new_talk` route does for the default
+/// event. Responds with a Redirect to the event page, or re-renders it with
+/// the validation errors.
+#[post("/e/<slug>/talks", data = "<talk>")]
+fn submit_talk(
+    slug: String,
+    talk: LenientForm<NewTalk>,
+    conn: DbConn,
+    speaker: Speaker,
+    window: State<CfpWindow>,
+    mail: State<Mail>,
+    csrf: CsrfToken,
+) -> CfpResult<SubmitResponse> {
+    let user = speaker.0;
+    let event = find_event(&conn, &slug)?;
+    match submit(&conn, &user, &window, &mail, &event, &talk)? {
+        Submission::Submitted(_) => Ok(SubmitResponse::Redirect(Redirect::to(format!("/e/{}/talks", event.slug)))),
+        Submission::Invalid(errors) => {
+            let page = render_event(&conn, &user, &window, &event, &csrf, Some(&*talk), &errors)?;
+            Ok(SubmitResponse::Invalid(page))
+        }
+    }
+}
+
+/// A struct containing the ID of a talk from another event.
+#[derive(FromForm)]
+struct ResubmissionForm {
+    talk: i32,
+}
+
+/// Submits a copy of one of the current user's talks from another event,
+/// unless it was already resubmitted to this one. Responds with a Redirect to
+/// the new talk, which can then be edited.
+#[post("/e/<slug>/resubmissions", data = "<resubmission>")]
+fn resubmit_talk(
+    slug: String,
+    resubmission: LenientForm<ResubmissionForm>,
+    conn: DbConn,
+    speaker: Speaker,
+    window: State<CfpWindow>,
+    mail: State<Mail>,
+) -> CfpResult<Redirect> {
+    let user = speaker.0;
+    let event = find_event(&conn, &slug)?;
+    if !window.is_open_for(&event) {
+        return Err(Status::Forbidden.into());
+    }
+    let id = match Talk::resubmit(&conn, &user, resubmission.talk, &event)? {
+        Resubmission::Resubmitted(id) => id,
+        Resubmission::NotFound => return Err(Status::NotFound.into()),
+        Resubmission::AlreadySubmitted => {
+            return Err(CfpError::new(Status::Conflict, "This talk was already submitted to this event."));
+        }
+    };
+    let talk = Talk::get_one(&conn, &user, id)?.ok_or(Status::InternalServerError)?;
+    mail.send(&user, "received", &json!({ "talk": { "id": id, "title": talk.title } }));
+    Ok(Redirect::to(format!("/talks/{}", id)))
+}
+
+/// Shows the settings of an event to its administrators.
+#[get("/e/<slug>/admin")]
+fn event_settings(
+    slug: String,
+    conn: DbConn,
+    user: User,
+    window: State<CfpWindow>,
+    csrf: CsrfToken,
+) -> CfpResult<Template> {
+    let admin = administered_event(&conn, user, &slug)?;
+    let admins = admin.event.get_admins(&conn)?;
+    Ok(Template::render("event_admin", json!({
+        "user": admin.user,
+        "event": admin.event,
+        "admins": admins,
+        "cfp": window.for_event(&admin.event).status(),
+        "csrf_token": csrf,
+    })))
+}
+
+/// A struct containing the name, CFP window and review mode of an event.
+/// Leaving either end of the window empty falls back to the configured one.
+#[derive(FromForm)]
+struct EventSettings {
+    name: String,
+    cfp_opens: Option<FormTimestamp>,
+    cfp_closes: Option<FormTimestamp>,
+    /// Unchecked checkboxes aren't submitted, so a missing value means `false`.
+    blind_review: bool,
+}
+
+/// Updates the settings of an event.
+#[put("/e/<slug>/admin", data = "<settings>")]
+fn update_event(slug: String, settings: LenientForm<EventSettings>, conn: DbConn, user: User) -> CfpResult<Redirect> {
+    let admin = administered_event(&conn, user, &slug)?;
+    let name = settings.name.trim();
+    if name.is_empty() {
+        return Err(Status::UnprocessableEntity.into());
+    }
+    let opens = settings.cfp_opens.as_ref().map(|opens| opens.0.naive_utc());
+    let closes = settings.cfp_closes.as_ref().map(|closes| closes.0.naive_utc());
+    if let (Some(opens), Some(closes)) = (opens, closes) {
+        if opens >= closes {
+            return Err(Status::UnprocessableEntity.into());
+        }
+    }
+    conn.0.transaction::<_, Error, _>(|| {
+        Event::update(&conn, &admin, name, opens, closes, settings.blind_review)?;
+        AdminEvent::record(&conn, &admin.user, &format!("updated the settings of event {}", admin.event.slug))
+    })?;
+    info!("{} updated the settings of event {}", admin.user.login, admin.event.slug);
+    Ok(Redirect::to(format!("/e/{}/admin", admin.event.slug)))
+}
+
+/// A struct containing the login of a user to make an event administrator.
+#[derive(FromForm)]
+struct NewEventAdminForm {
+    login: String,
+}
+
+/// Makes a user an administrator of an event.
+#[post("/e/<slug>/admin/admins", data = "<form>")]
+fn add_event_admin(slug: String, form: LenientForm<NewEventAdminForm>, conn: DbConn, user: User) -> CfpResult<Redirect> {
+    let admin = administered_event(&conn, user, &slug)?;
+    let new_admin = User::get_by_login(&conn, form.login.trim())?.ok_or(Status::NotFound)?;
+    conn.0.transaction::<_, Error, _>(|| {
+        Event::add_admin(&conn, &admin, new_admin.id)?;
+        let action = format!("made {} an administrator of event {}", new_admin.login, admin.event.slug);
+        AdminEvent::record(&conn, &admin.user, &action)
+    })?;
+    info!("{} made {} an administrator of event {}", admin.user.login, new_admin.login, admin.event.slug);
+    Ok(Redirect::to(format!("/e/{}/admin", admin.event.slug)))
+}
+
+/// A struct containing the ID of an event administrator to remove.
+#[derive(FromForm)]
+struct EventAdminRemoval {
+    user: i32,
+}
+
+/// Removes a user from the administrators of an event.
+#[delete("/e/<slug>/admin/admins", data = "<form>")]
+fn remove_event_admin(slug: String, form: LenientForm<EventAdminRemoval>, conn: DbConn, user: User) -> CfpResult<Redirect> {
+    let admin = administered_event(&conn, user, &slug)?;
+    let removed = User::get(&conn, form.user).optional()?.ok_or(Status::NotFound)?;
+    let was_admin = conn.0.transaction::<_, Error, _>(|| {
+        let was_admin = Event::remove_admin(&conn, &admin, removed.id)?;
+        if was_admin {
+            let action = format!("removed {} from the administrators of event {}", removed.login, admin.event.slug);
+            AdminEvent::record(&conn, &admin.user, &action)?;
+        }
+        Ok(was_admin)
+    })?;
+    if !was_admin {
+        return Err(Status::NotFound.into());
+    }
+    info!("{} removed {} from the administrators of event {}", admin.user.login, removed.login, admin.event.slug);
+    Ok(Redirect::to(format!("/e/{}/admin", admin.event.slug)))
+}
+
+/// Returns a fairing that mounts the event routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Events", |rocket| {
+        Ok(rocket.mount("/", routes![
+            list_events,
+            create_event,
+            event_page,
+            submit_talk,
+            resubmit_talk,
+            event_settings,
+            update_event,
+            add_event_admin,
+            remove_event_admin,
+        ]))
+    })
+}
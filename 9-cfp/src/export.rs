@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use chrono::NaiveDateTime;
+use csv;
+use diesel::prelude::*;
+use diesel::result::Error;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::{ContentType, Header};
+use rocket::response::{Content, Stream};
+use serde_json;
+
+use review::ReviewSummary;
+use role::{Admin, Reviewer};
+use schema::{events, talks, users};
+use talk::{AudienceLevel, Tags, Talk, TalkFormat, TalkStatus};
+use DbConn;
+
+/// The number of talks loaded from the database at a time while exporting.
+const BATCH_SIZE: i64 = 200;
+
+/// The columns of a CSV export, in the order of `ExportRow::to_record`.
+const CSV_HEADER: [&str; 15] = [
+    "id",
+    "title",
+    "description",
+    "status",
+    "format",
+    "duration",
+    "level",
+    "tags",
+    "submitted_at",
+    "presenter_login",
+    "presenter_name",
+    "reviews",
+    "average",
+    "variance",
+    "event",
+];
+
+/// A talk as exported for scheduling, with its presenter and reviews summed
+/// up.
+#[derive(Serialize)]
+pub struct ExportRow {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    pub status: TalkStatus,
+    pub format: TalkFormat,
+    pub duration: i32,
+    pub level: AudienceLevel,
+    pub tags: Tags,
+    pub submitted_at: NaiveDateTime,
+    pub presenter_login: String,
+    pub presenter_name: String,
+    pub reviews: ReviewSummary,
+    /// The slug of the event the talk was submitted to
+    pub event: String,
+}
+
+impl ExportRow {
+    /// Retrieve up to `BATCH_SIZE` talks with an ID greater than `after_id`,
+    /// ordered by ID.
+    fn load_batch(
+        conn: &DbConn,
+        _admin: &Admin,
+        reviewer: &Reviewer,
+        after_id: i32,
+    ) -> Result<Vec<ExportRow>, Error> {
+        let rows: Vec<(Talk, String, String, String)> = talks::table
+            .inner_join(users::table)
+            .inner_join(events::table)
+            .filter(talks::id.gt(after_id))
+            .select((talks::all_columns, users::login, users::name, events::slug))
+            .order(talks::id)
+            .limit(BATCH_SIZE)
+            .load(&conn.0)?;
+
+        let ids: Vec<i32> = rows.iter().map(|&(ref talk, _, _, _)| talk.id).collect();
+        let mut summaries: HashMap<i32, ReviewSummary> = ReviewSummary::get_for_talks(conn, reviewer, &ids)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(talk, presenter_login, presenter_name, event)| ExportRow {
+                reviews: summaries.remove(&talk.id).unwrap_or_default(),
+                id: talk.id,
+                title: talk.title,
+                description: talk.description,
+                status: talk.status,
+                format: talk.format,
+                duration: talk.duration,
+                level: talk.level,
+                tags: talk.tags,
+                submitted_at: talk.submitted_at,
+                presenter_login,
+                presenter_name,
+                event,
+            })
+            .collect())
+    }
+
+    /// The fields of the row as a CSV record, matching `CSV_HEADER`.
+    fn to_record(&self) -> Vec<String> {
+        let optional = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+        let record = vec![
+            self.id.to_string(),
+            self.title.clone(),
+            self.description.clone(),
+            format!("{:?}", self.status),
+            format!("{:?}", self.format),
+            self.duration.to_string(),
+            format!("{:?}", self.level),
+            self.tags.to_string(),
+            self.submitted_at.to_string(),
+            self.presenter_login.clone(),
+            self.presenter_name.clone(),
+            self.reviews.count.to_string(),
+            optional(self.reviews.average),
+            optional(self.reviews.variance),
+            self.event.clone(),
+        ];
+        record.into_iter().map(defuse_formula).collect()
+    }
+}
+
+/// Prefixes `cell` with an apostrophe if it starts with a character that
+/// makes spreadsheets evaluate it as a formula, so that a talk titled
+/// `=HYPERLINK(...)` is shown as text rather than run when the export is
+/// opened.
+fn defuse_formula(cell: String) -> String {
+    match cell.chars().next() {
+        Some('=') | Some('+') | Some('-') | Some('@') | Some('\t') | Some('\r') => format!("'{}", cell),
+        _ => cell,
+    }
+}
+
+/// The format of an export.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Produces an export of all talks one batch at a time, so that only a
+/// single batch is ever held in memory. CSV exports start with a header row,
+/// and JSON exports are a single array of objects.
+pub struct ExportReader {
+    conn: DbConn,
+    admin: Admin,
+    reviewer: Reviewer,
+    format: ExportFormat,
+    /// The ID of the last talk exported so far, or `0` before the first one.
+    last_id: i32,
+    /// Output that hasn't been read yet, starting at `position`.
+    buffer: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl ExportReader {
+    pub fn new(conn: DbConn, admin: Admin, reviewer: Reviewer, format: ExportFormat) -> ExportReader {
+        let mut reader = ExportReader {
+            conn,
+            admin,
+            reviewer,
+            format,
+            last_id: 0,
+            buffer: vec![],
+            position: 0,
+            done: false,
+        };
+        match format {
+            ExportFormat::Csv => {
+                let header: Vec<String> = CSV_HEADER.iter().map(|column| column.to_string()).collect();
+                reader.write_records(&[header]).expect("writing to a Vec can't fail");
+            }
+            ExportFormat::Json => reader.buffer.push(b'['),
+        }
+        reader
+    }
+
+    fn write_records(&mut self, records: &[Vec<String>]) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_writer(&mut self.buffer);
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Replaces the buffer with the next batch of talks, or with the end of
+    /// the export if there are none left.
+    fn fill(&mut self) -> io::Result<()> {
+        self.buffer.clear();
+        self.position = 0;
+
+        let rows = ExportRow::load_batch(&self.conn, &self.admin, &self.reviewer, self.last_id)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        if rows.is_empty() {
+            if self.format == ExportFormat::Json {
+                self.buffer.extend_from_slice(b"\n]\n");
+            }
+            self.done = true;
+            return Ok(());
+        }
+
+        match self.format {
+            ExportFormat::Csv => {
+                let records: Vec<_> = rows.iter().map(ExportRow::to_record).collect();
+                self.write_records(&records)?;
+            }
+            ExportFormat::Json => {
+                for (i, row) in rows.iter().enumerate() {
+                    if self.last_id != 0 || i > 0 {
+                        self.buffer.push(b',');
+                    }
+                    self.buffer.push(b'\n');
+                    serde_json::to_writer(&mut self.buffer, row)?;
+                }
+            }
+        }
+        self.last_id = rows[rows.len() - 1].id;
+        Ok(())
+    }
+}
+
+impl Read for ExportReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if self.done {
+                return Ok(0);
+            }
+            if let Err(e) = self.fill() {
+                // The response has already started, so all that's left to do
+                // is to cut it short.
+                error!("Export failed after talk {}: {}", self.last_id, e);
+                return Err(e);
+            }
+        }
+
+        let read = (&self.buffer[self.position..]).read(buf)?;
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// An export, offered as a file download.
+#[derive(Responder)]
+struct Export {
+    body: Content<Stream<ExportReader>>,
+    disposition: Header<'static>,
+}
+
+impl Export {
+    fn new(conn: DbConn, admin: Admin, reviewer: Reviewer, format: ExportFormat) -> Export {
+        let (content_type, file_name) = match format {
+            ExportFormat::Csv => (ContentType::CSV, "talks.csv"),
+            ExportFormat::Json => (ContentType::JSON, "talks.json"),
+        };
+        let reader = ExportReader::new(conn, admin, reviewer, format);
+        Export {
+            body: Content(content_type, Stream::from(reader)),
+            disposition: Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        }
+    }
+}
+
+/// Exports all talks as CSV.
+#[get("/admin/export.csv")]
+fn export_csv(conn: DbConn, admin: Admin, reviewer: Reviewer) -> Export {
+    Export::new(conn, admin, reviewer, ExportFormat::Csv)
+}
+
+/// Exports all talks as a JSON array.
+#[get("/admin/export.json")]
+fn export_json(conn: DbConn, admin: Admin, reviewer: Reviewer) -> Export {
+    Export::new(conn, admin, reviewer, ExportFormat::Json)
+}
+
+/// Returns a fairing that mounts the export routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Export", |rocket| {
+        Ok(rocket.mount("/", routes![export_csv, export_json]))
+    })
+}
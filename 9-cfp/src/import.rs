@@ -0,0 +1,417 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use csv;
+use diesel::prelude::*;
+use diesel::result::Error;
+use rocket::data::Data;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::{RawStr, Status};
+use rocket::request::{FormItems, FromForm, FromFormValue};
+use rocket::Rocket;
+use rocket_contrib::json::Json;
+use rocket_contrib::templates::Template;
+use serde_json;
+
+use audit::AdminEvent;
+use auth::AutoAdmin;
+use csrf::CsrfToken;
+use error::{CfpError, CfpResult};
+use event::Event;
+use role::Admin;
+use talk::{Talk, TalkDetails, TalkStatus};
+use user::{Identity, User};
+use {DbConn, NewTalk};
+
+/// The largest file that can be uploaded or pasted for import, in bytes.
+const MAX_UPLOAD_SIZE: u64 = 16 * 1024 * 1024;
+
+/// A talk to be imported, along with its presenter. CSV files have a header
+/// row naming these fields, and JSON files are an array of objects with these
+/// fields. Only `github_id`, `name`, `title` and `description` are required.
+#[derive(Debug, Deserialize)]
+pub struct ImportRow {
+    /// The numeric ID of the presenter's GitHub account. Presenters are
+    /// looked up, or created, by their GitHub identity, so that they can log
+    /// in with GitHub and find their talks. Logins can't be used, since
+    /// GitHub users can rename themselves.
+    pub github_id: String,
+    /// The presenter's GitHub login, which presenters who aren't users yet
+    /// are created with. Defaults to `github-<github_id>`.
+    #[serde(default)]
+    pub github_login: String,
+    pub name: String,
+    #[serde(default)]
+    pub email: String,
+    pub title: String,
+    pub description: String,
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[serde(default = "default_duration")]
+    pub duration: i32,
+    #[serde(default = "default_level")]
+    pub level: String,
+    #[serde(default)]
+    pub outline: String,
+    #[serde(default)]
+    pub notes: String,
+    /// A comma-separated list of tags
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default = "default_status")]
+    pub status: String,
+    /// The slug of the event the talk was submitted to. Talks without one go
+    /// to the default event.
+    #[serde(default)]
+    pub event: String,
+}
+
+fn default_format() -> String {
+    "talk".to_string()
+}
+
+fn default_duration() -> i32 {
+    30
+}
+
+fn default_level() -> String {
+    "beginner".to_string()
+}
+
+fn default_status() -> String {
+    "pending".to_string()
+}
+
+impl ImportRow {
+    /// Validates the row like a submission through the web form, returning
+    /// either the talk details and status or a list of human-readable errors.
+    fn validate(&self) -> Result<(TalkDetails, TalkStatus), Vec<String>> {
+        let mut errors = vec![];
+        if self.github_id.trim().is_empty() {
+            errors.push("The GitHub account ID must not be empty.".to_string());
+        } else if self.github_id.trim().parse::<u64>().is_err() {
+            errors.push(format!("The GitHub account ID must be a number, not '{}'.", self.github_id.trim()));
+        }
+        if self.name.trim().is_empty() {
+            errors.push("The presenter's name must not be empty.".to_string());
+        }
+
+        let status = TalkStatus::from_form_value(RawStr::from_str(self.status.trim())).ok();
+        if status.is_none() {
+            errors.push(format!("Unknown status: '{}'.", self.status));
+        }
+
+        let talk = NewTalk {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            format: self.format.clone(),
+            duration: self.duration.to_string(),
+            level: self.level.clone(),
+            outline: self.outline.clone(),
+            notes: self.notes.clone(),
+            tags: self.tags.clone(),
+        };
+        match (talk.validate(), status) {
+            (Ok(details), Some(status)) if errors.is_empty() => Ok((details, status)),
+            (Ok(_), _) => Err(errors),
+            (Err(talk_errors), _) => {
+                errors.extend(talk_errors);
+                Err(errors)
+            }
+        }
+    }
+}
+
+/// The format of a file to import.
+#[derive(Copy, Clone, Debug, FromFormValue)]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+/// Parses `input` into rows. Rows that can't be parsed are returned as
+/// errors, so that they can be reported along with the others; only JSON
+/// that isn't an array at all fails the whole import.
+pub fn parse<R: Read>(format: ImportFormat, input: R) -> Result<Vec<Result<ImportRow, String>>, String> {
+    match format {
+        ImportFormat::Csv => Ok(csv::Reader::from_reader(input)
+            .deserialize()
+            .map(|row| row.map_err(|e| e.to_string()))
+            .collect()),
+        ImportFormat::Json => {
+            let values: Vec<serde_json::Value> = serde_json::from_reader(input)
+                .map_err(|e| format!("Expected a JSON array of talks: {}", e))?;
+            Ok(values
+                .into_iter()
+                .map(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+                .collect())
+        }
+    }
+}
+
+/// What happened to a row during an import.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOutcome {
+    Imported,
+    /// The presenter already submitted a talk with the same title to the
+    /// same event.
+    Skipped,
+    Invalid,
+}
+
+/// The result of importing a single row. Rows are numbered from 1, not
+/// counting the CSV header.
+#[derive(Debug, Serialize)]
+pub struct RowReport {
+    pub row: usize,
+    pub title: String,
+    pub outcome: RowOutcome,
+    /// The imported talk, or the existing talk a skipped row matched. In a
+    /// dry run, this is the ID the talk would have had.
+    pub talk: Option<i32>,
+    pub errors: Vec<String>,
+}
+
+impl RowReport {
+    fn invalid(row: usize, title: String, errors: Vec<String>) -> RowReport {
+        RowReport { row, title, outcome: RowOutcome::Invalid, talk: None, errors }
+    }
+}
+
+/// The result of an import
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub imported: usize,
+    pub skipped: usize,
+    pub invalid: usize,
+    pub rows: Vec<RowReport>,
+}
+
+/// Imports a single row, creating the presenter if necessary. Talks are
+/// recorded as submitted by `admin`, or by their presenter if no admin is
+/// doing the import.
+fn import_row(
+    conn: &DbConn,
+    admin: Option<&Admin>,
+    row: usize,
+    input: Result<ImportRow, String>,
+) -> Result<RowReport, Error> {
+    let input = match input {
+        Ok(input) => input,
+        Err(e) => return Ok(RowReport::invalid(row, String::new(), vec![e])),
+    };
+    let (details, status) = match input.validate() {
+        Ok(valid) => valid,
+        Err(errors) => return Ok(RowReport::invalid(row, input.title, errors)),
+    };
+
+    let event = match input.event.trim() {
+        "" => Event::get_default(conn)?,
+        slug => match Event::get_by_slug(conn, slug)? {
+            Some(event) => event,
+            None => {
+                let errors = vec![format!("Unknown event: '{}'.", slug)];
+                return Ok(RowReport::invalid(row, details.title, errors));
+            }
+        },
+    };
+
+    let email = input.email.trim();
+    let github_login = input.github_login.trim();
+    let identity = Identity {
+        subject: input.github_id.trim().to_string(),
+        // Presenters who logged in before GitHub identities were keyed on
+        // the account ID are still known by their login.
+        legacy_subject: if github_login.is_empty() { None } else { Some(format!("login:{}", github_login)) },
+        login: if github_login.is_empty() {
+            format!("github-{}", input.github_id.trim())
+        } else {
+            github_login.to_string()
+        },
+        name: input.name.trim().to_string(),
+        email: if email.is_empty() { None } else { Some(email.to_string()) },
+    };
+    let presenter = User::get(conn, User::get_or_create(conn, "github", &identity, &AutoAdmin(false))?)?;
+
+    let (outcome, talk) = match Talk::find_by_title(conn, &presenter, &event, &details.title)? {
+        Some(id) => (RowOutcome::Skipped, id),
+        None => {
+            let actor = admin.map_or(&presenter, |admin| &admin.0);
+            (RowOutcome::Imported, Talk::import(conn, actor, &presenter, &event, &details, status)?)
+        }
+    };
+    Ok(RowReport { row, title: details.title, outcome, talk: Some(talk), errors: vec![] })
+}
+
+/// Imports `rows`, skipping invalid rows and talks that were already
+/// imported. A dry run reports what would happen without changing anything.
+/// Imports by an `admin` are recorded in the audit log.
+pub fn import(
+    conn: &DbConn,
+    admin: Option<&Admin>,
+    rows: Vec<Result<ImportRow, String>>,
+    dry_run: bool,
+) -> Result<ImportReport, Error> {
+    let mut report = ImportReport { dry_run, ..ImportReport::default() };
+    let result = conn.0.transaction::<_, Error, _>(|| {
+        for (i, row) in rows.into_iter().enumerate() {
+            report.rows.push(import_row(conn, admin, i + 1, row)?);
+        }
+        if dry_run {
+            return Err(Error::RollbackTransaction);
+        }
+        let imported = report.rows.iter().filter(|row| row.outcome == RowOutcome::Imported).count();
+        if let Some(admin) = admin.filter(|_| imported > 0) {
+            let action = format!("imported {} {}", imported, if imported == 1 { "talk" } else { "talks" });
+            AdminEvent::record(conn, &admin.0, &action)?;
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) | Err(Error::RollbackTransaction) => {}
+        Err(e) => return Err(e),
+    }
+
+    for row in &report.rows {
+        match row.outcome {
+            RowOutcome::Imported => report.imported += 1,
+            RowOutcome::Skipped => report.skipped += 1,
+            RowOutcome::Invalid => report.invalid += 1,
+        }
+    }
+    Ok(report)
+}
+
+/// Runs `cfp import [--dry-run] <file>`, importing a `.csv` or `.json` file
+/// and printing a report. Returns the exit code: `0` if every row was
+/// imported or skipped, or `1` otherwise.
+pub fn run_cli(rocket: Rocket, args: &[String]) -> i32 {
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let paths: Vec<_> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    if paths.len() != 1 {
+        eprintln!("Usage: cfp import [--dry-run] <file.csv|file.json>");
+        return 2;
+    }
+
+    let path = Path::new(paths[0]);
+    let format = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => ImportFormat::Json,
+        _ => ImportFormat::Csv,
+    };
+    let rows = match File::open(path).map_err(|e| e.to_string()).and_then(|file| parse(format, file)) {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+
+    let conn = match DbConn::get_one(&rocket) {
+        Some(conn) => conn,
+        None => {
+            eprintln!("Could not connect to the database. Check its configuration.");
+            return 1;
+        }
+    };
+    let report = match import(&conn, None, rows, dry_run) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Import failed, nothing was imported: {}", e);
+            return 1;
+        }
+    };
+
+    for row in &report.rows {
+        match row.outcome {
+            RowOutcome::Imported => println!("row {}: imported \"{}\"", row.row, row.title),
+            RowOutcome::Skipped => println!("row {}: skipped \"{}\", already imported", row.row, row.title),
+            RowOutcome::Invalid => println!("row {}: invalid: {}", row.row, row.errors.join(" ")),
+        }
+    }
+    println!(
+        "{}{} imported, {} skipped, {} invalid",
+        if dry_run { "Dry run: " } else { "" },
+        report.imported,
+        report.skipped,
+        report.invalid
+    );
+
+    if report.invalid == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+/// Shows the import form.
+#[get("/admin/import")]
+fn import_page(admin: Admin, csrf: CsrfToken) -> Template {
+    Template::render("import", json!({ "user": admin.0, "report": null, "csrf_token": csrf }))
+}
+
+/// An import pasted into the import form.
+#[derive(FromForm)]
+struct ImportForm {
+    format: ImportFormat,
+    data: String,
+    dry_run: bool,
+}
+
+/// Imports talks pasted into the import form, and shows the report. The form
+/// is read with the same limit as uploads: the `forms` limit would be too
+/// small for most imports, and Rocket cuts forms off at it without an error.
+#[post("/admin/import", format = "application/x-www-form-urlencoded", data = "<data>")]
+fn import_form(data: Data, conn: DbConn, admin: Admin, csrf: CsrfToken) -> CfpResult<Template> {
+    let body = String::from_utf8(read_upload(data)?)
+        .map_err(|_| CfpError::new(Status::BadRequest, "The form isn't valid UTF-8."))?;
+    let form = ImportForm::from_form(&mut FormItems::from(body.as_str()), false)
+        .map_err(|_| Status::UnprocessableEntity)?;
+    let rows = parse(form.format, form.data.as_bytes()).map_err(|e| CfpError::new(Status::BadRequest, e))?;
+    let report = import(&conn, Some(&admin), rows, form.dry_run)?;
+    Ok(Template::render("import", json!({ "user": admin.0, "report": report, "csrf_token": csrf })))
+}
+
+/// Imports an uploaded CSV file, and responds with the report.
+#[post("/admin/import?<dry_run>", format = "text/csv", data = "<data>", rank = 2)]
+fn upload_csv(dry_run: Option<bool>, data: Data, conn: DbConn, admin: Admin) -> CfpResult<Json<ImportReport>> {
+    upload(ImportFormat::Csv, dry_run.unwrap_or(false), data, &conn, &admin)
+}
+
+/// Imports an uploaded JSON file, and responds with the report.
+#[post("/admin/import?<dry_run>", format = "json", data = "<data>", rank = 3)]
+fn upload_json(dry_run: Option<bool>, data: Data, conn: DbConn, admin: Admin) -> CfpResult<Json<ImportReport>> {
+    upload(ImportFormat::Json, dry_run.unwrap_or(false), data, &conn, &admin)
+}
+
+fn upload(format: ImportFormat, dry_run: bool, data: Data, conn: &DbConn, admin: &Admin) -> CfpResult<Json<ImportReport>> {
+    let input = read_upload(data)?;
+    let rows = parse(format, &input[..]).map_err(|e| CfpError::new(Status::BadRequest, e))?;
+    Ok(Json(import(conn, Some(admin), rows, dry_run)?))
+}
+
+/// Reads an upload, failing with `413 Payload Too Large` if it is larger than
+/// `MAX_UPLOAD_SIZE`.
+fn read_upload(data: Data) -> CfpResult<Vec<u8>> {
+    // Read one byte more than allowed, to tell a file of exactly the maximum
+    // size from a larger one.
+    let mut input = vec![];
+    data.open()
+        .take(MAX_UPLOAD_SIZE + 1)
+        .read_to_end(&mut input)
+        .map_err(|e| CfpError::new(Status::BadRequest, e.to_string()))?;
+    if input.len() as u64 > MAX_UPLOAD_SIZE {
+        let message = format!("Uploads can be at most {} MiB.", MAX_UPLOAD_SIZE / 1024 / 1024);
+        return Err(CfpError::new(Status::PayloadTooLarge, message));
+    }
+    Ok(input)
+}
+
+/// Returns a fairing that mounts the import routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Import", |rocket| {
+        Ok(rocket.mount("/", routes![import_page, import_form, upload_csv, upload_json]))
+    })
+}
@@ -0,0 +1,27 @@
+/// Implements diesel's `FromSql` and `ToSql` for an enum that is stored as an
+/// integer. The enum must be `#[repr(i32)]` and implement `TryFrom<i32>`;
+/// integers that don't correspond to any variant fail to deserialize.
+///
+/// The module invoking the macro has to import `Backend`, `FromSql`,
+/// `ToSql`, `Integer`, `Output`, `deserialize`, `serialize` and `io`.
+macro_rules! integer_sql_enum {
+    ($name:ident) => {
+        impl<DB: Backend> FromSql<Integer, DB> for $name
+        where
+            i32: FromSql<Integer, DB>,
+        {
+            fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+                Ok(<$name as ::std::convert::TryFrom<i32>>::try_from(i32::from_sql(bytes)?)?)
+            }
+        }
+
+        impl<DB: Backend> ToSql<Integer, DB> for $name
+        where
+            i32: ToSql<Integer, DB>,
+        {
+            fn to_sql<W: io::Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+                i32::to_sql(&(*self as i32), out)
+            }
+        }
+    };
+}
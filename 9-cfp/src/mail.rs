@@ -0,0 +1,360 @@
+use std;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use chrono::Utc;
+use lettre::smtp::authentication::Credentials;
+use lettre::smtp::error::Error as SmtpError;
+use lettre::{EmailTransport, SmtpTransport};
+use lettre_email::error::Error as BuildError;
+use lettre_email::EmailBuilder;
+use rocket::config::{Config, ConfigError, Table, Value};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::request::LenientForm;
+use rocket::response::Redirect;
+use rocket_contrib::templates::tera::{self, Tera};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use auth;
+use csrf::CsrfToken;
+use error::CfpResult;
+use speaker::CoSpeaker;
+use talk::{Talk, TalkStatus};
+use user::User;
+use {DbConn, SubmitResponse};
+
+/// An email ready to be sent.
+pub struct Email {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// An error setting up email, or preparing or delivering an email.
+#[derive(Debug)]
+pub enum MailError {
+    /// The `mail` configuration table is invalid.
+    Config(ConfigError),
+    /// A template failed to load or render.
+    Template(tera::Error),
+    /// A rendered template doesn't start with a `Subject: ` line.
+    MissingSubject(String),
+    /// The email couldn't be built, e.g. because an address is invalid.
+    Build(BuildError),
+    /// The SMTP server couldn't be reached, or refused the email.
+    Smtp(SmtpError),
+    /// The email couldn't be written to the outbox.
+    Io(io::Error),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MailError::Config(ref e) => write!(f, "{}", e),
+            MailError::Template(ref e) => write!(f, "template error: {}", e),
+            MailError::MissingSubject(ref name) => write!(f, "mail template {} has no subject line", name),
+            MailError::Build(ref e) => write!(f, "invalid email: {}", e),
+            MailError::Smtp(ref e) => write!(f, "SMTP error: {}", e),
+            MailError::Io(ref e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+impl From<ConfigError> for MailError {
+    fn from(e: ConfigError) -> MailError {
+        MailError::Config(e)
+    }
+}
+
+impl From<tera::Error> for MailError {
+    fn from(e: tera::Error) -> MailError {
+        MailError::Template(e)
+    }
+}
+
+impl From<BuildError> for MailError {
+    fn from(e: BuildError) -> MailError {
+        MailError::Build(e)
+    }
+}
+
+impl From<SmtpError> for MailError {
+    fn from(e: SmtpError) -> MailError {
+        MailError::Smtp(e)
+    }
+}
+
+impl From<io::Error> for MailError {
+    fn from(e: io::Error) -> MailError {
+        MailError::Io(e)
+    }
+}
+
+/// A way of delivering emails.
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/// Delivers emails through an SMTP server, using STARTTLS on the submission
+/// port.
+pub struct SmtpMailer {
+    transport: Mutex<SmtpTransport>,
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = EmailBuilder::new()
+            .from(email.from.as_str())
+            .to(email.to.as_str())
+            .subject(email.subject.as_str())
+            .text(email.body.as_str())
+            .build()?;
+        self.transport.lock().expect("SMTP transport lock").send(&message)?;
+        Ok(())
+    }
+}
+
+/// Writes every email to a file in a directory, instead of sending it. Useful
+/// during development and in tests.
+pub struct FileMailer {
+    dir: PathBuf,
+    count: AtomicUsize,
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        fs::create_dir_all(&self.dir)?;
+        let name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%d%H%M%S"),
+            self.count.fetch_add(1, Ordering::SeqCst)
+        );
+        let mut file = fs::File::create(self.dir.join(name))?;
+        write!(
+            file,
+            "From: {}\nTo: {}\nSubject: {}\n\n{}",
+            email.from, email.to, email.subject, email.body
+        )?;
+        Ok(())
+    }
+}
+
+/// Writes the recipient and subject of every email to the server log. Used
+/// when no mailer is configured. The body is left out, since it can contain
+/// secrets such as password reset links; use the `file` backend to read
+/// emails during development.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        info!("Email to {}: {} (not sent, no mailer is configured)", email.to, email.subject);
+        Ok(())
+    }
+}
+
+/// Reads the optional string `key` from the `mail` configuration table.
+fn get_str<'a>(table: &'a Table, key: &str) -> Result<Option<&'a str>, ConfigError> {
+    match table.get(key) {
+        Some(Value::String(value)) => Ok(Some(value.as_str())),
+        Some(value) => Err(ConfigError::BadType(format!("mail.{}", key), "a string", value.type_str(), None)),
+        None => Ok(None),
+    }
+}
+
+/// Sends templated emails to users.
+///
+/// Email templates live in `mail/` in the template directory. The first line
+/// of a template is the subject, prefixed by `Subject: `, and the rest is the
+/// body.
+pub struct Mail {
+    from: String,
+    public_url: String,
+    mailer: Box<Mailer>,
+    templates: Tera,
+}
+
+impl Mail {
+    /// Reads the `mail` table, e.g.:
+    ///
+    /// ```toml
+    /// [global.mail]
+    /// from = "cfp@example.com"
+    /// backend = "smtp"
+    /// host = "smtp.example.com"
+    /// username = "..."
+    /// password = "..."
+    /// ```
+    ///
+    /// The `backend` is `smtp`, `file` (with a `dir` to write to, `outbox`
+    /// by default), or `log`, which is the default.
+    pub fn from_config(config: &Config) -> Result<Mail, MailError> {
+        let empty = Table::new();
+        let table = match config.get_table("mail") {
+            Ok(table) => table,
+            Err(ConfigError::Missing(_)) => &empty,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mailer: Box<Mailer> = match get_str(table, "backend")?.unwrap_or("log") {
+            "smtp" => {
+                let host = get_str(table, "host")?
+                    .ok_or_else(|| ConfigError::Missing("mail.host".into()))?;
+                let mut builder = SmtpTransport::simple_builder(host.to_string())?;
+                if let Some(username) = get_str(table, "username")? {
+                    let password = get_str(table, "password")?.unwrap_or("");
+                    builder = builder.credentials(Credentials::new(username.into(), password.into()));
+                }
+                Box::new(SmtpMailer { transport: Mutex::new(builder.build()) })
+            }
+            "file" => Box::new(FileMailer {
+                dir: config.root_relative(get_str(table, "dir")?.unwrap_or("outbox")),
+                count: AtomicUsize::new(0),
+            }),
+            "log" => Box::new(LogMailer),
+            _ => {
+                let key = "mail.backend".to_string();
+                return Err(ConfigError::BadType(key, "smtp, file or log", "string", None).into());
+            }
+        };
+
+        let template_dir = config.get_str("template_dir").unwrap_or("templates");
+        let pattern = config.root_relative(template_dir).join("mail").join("*.txt");
+        let templates = Tera::new(&pattern.to_string_lossy())?;
+
+        Ok(Mail {
+            from: get_str(table, "from")?.unwrap_or("cfp@localhost").to_string(),
+            public_url: auth::public_url(config)?,
+            mailer,
+            templates,
+        })
+    }
+
+    /// Renders the template `mail/<name>.txt` with `context`, which can also
+    /// use the recipient as `user` and the application's URL as `url`, and
+    /// sends it to `to`.
+    ///
+    /// Users without an email address, or who haven't confirmed it, are
+    /// skipped. Failures are logged rather than returned, so that a broken
+    /// mail server never fails a request.
+    pub fn send<T: Serialize>(&self, to: &User, name: &str, context: &T) {
+        if to.email.is_empty() {
+            info!("Not sending {} to {}, who has no email address", name, to.login);
+            return;
+        }
+        if !to.email_confirmed {
+            info!("Not sending {} to {}, who hasn't confirmed their email address", name, to.login);
+            return;
+        }
+
+        let result = self.render(to, name, context).and_then(|email| self.mailer.send(&email));
+        if let Err(e) = result {
+            error!("Failed to send {} to {}: {}", name, to.email, e);
+        }
+    }
+
+    fn render<T: Serialize>(&self, to: &User, name: &str, context: &T) -> Result<Email, MailError> {
+        let mut context = json!(context);
+        context["user"] = json!(to);
+        context["url"] = json!(self.public_url);
+        let rendered = self.templates.render(&format!("{}.txt", name), &context)?;
+
+        let mut lines = rendered.splitn(2, '\n');
+        let subject = lines.next().unwrap_or("");
+        if !subject.starts_with("Subject: ") {
+            return Err(MailError::MissingSubject(name.to_string()));
+        }
+        Ok(Email {
+            from: self.from.clone(),
+            to: to.email.clone(),
+            subject: subject["Subject: ".len()..].trim().to_string(),
+            body: lines.next().unwrap_or("").trim_left().to_string(),
+        })
+    }
+
+    /// Tells the presenters of `talk` that it was accepted or rejected. Other
+    /// status changes aren't announced.
+    pub fn send_decision(&self, conn: &DbConn, talk: &Talk) {
+        let name = match talk.status {
+            TalkStatus::Approved => "accepted",
+            TalkStatus::Rejected => "rejected",
+            _ => return,
+        };
+        match CoSpeaker::get_presenters(conn, talk) {
+            Ok(presenters) => {
+                for presenter in &presenters {
+                    self.send(presenter, name, &json!({ "talk": talk }));
+                }
+            }
+            Err(e) => error!("Failed to look up the presenters of talk {}: {}", talk.id, e),
+        }
+    }
+}
+
+/// Shows the current user's email address, and lets them confirm or change
+/// it.
+#[get("/email")]
+fn email_page(user: User, csrf: CsrfToken) -> Template {
+    Template::render("email", json!({
+        "user": user,
+        "values": { "email": user.email },
+        "errors": [],
+        "csrf_token": csrf,
+    }))
+}
+
+/// A struct containing a user's email address.
+#[derive(FromForm, Serialize)]
+struct EmailAddress {
+    email: String,
+}
+
+/// Confirms the current user's email address, changing it if necessary.
+#[put("/email", data = "<address>")]
+fn set_email(
+    address: LenientForm<EmailAddress>,
+    conn: DbConn,
+    user: User,
+    csrf: CsrfToken,
+) -> CfpResult<SubmitResponse> {
+    let email = address.email.trim();
+    if !email.contains('@') || email.contains(char::is_whitespace) {
+        let errors = [format!("'{}' is not a valid email address.", email)];
+        let values = &*address;
+        let page = Template::render("email", json!({
+            "user": user,
+            "values": values,
+            "errors": errors,
+            "csrf_token": csrf,
+        }));
+        return Ok(SubmitResponse::Invalid(page));
+    }
+    User::confirm_email(&conn, &user, email)?;
+    Ok(SubmitResponse::Redirect(Redirect::to("/")))
+}
+
+/// Returns a fairing that sets up email:
+///
+/// * Reads the mail configuration and the email templates.
+/// * Mounts the routes to confirm one's email address.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Mail", |rocket| {
+        match Mail::from_config(rocket.config()) {
+            Ok(mail) => Ok(rocket
+                .manage(mail)
+                .mount("/", routes![email_page, set_email])
+            ),
+            Err(e) => {
+                error!("Invalid mail configuration: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
@@ -1,44 +1,94 @@
-#![feature(proc_macro_hygiene, decl_macro)]
+#![feature(proc_macro_hygiene, decl_macro, try_from)]
 #![allow(proc_macro_derive_resolution_fallback)]
 
+extern crate argon2;
+extern crate chrono;
+extern crate csv;
 #[macro_use]
 extern crate diesel_migrations;
 #[macro_use]
 extern crate diesel;
+extern crate lettre;
+extern crate lettre_email;
 #[macro_use]
 extern crate log;
+extern crate multipart;
 extern crate rand;
 extern crate reqwest;
 #[macro_use]
 extern crate rocket;
 extern crate rocket_contrib;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate sha2;
 
+#[cfg(test)]
+mod tests;
+
+use reqwest::Url;
 use rocket::fairing::AdHoc;
-use rocket::http::{Cookie, Cookies, Status};
-use rocket::outcome::IntoOutcome;
-use rocket::request::{Form, FromRequest, Request};
+use rocket::http::{Cookie, Cookies, RawStr, Status};
+use rocket::outcome::{IntoOutcome, Outcome};
+use rocket::request::{FromFormValue, FromRequest, LenientForm, Request, State};
 use rocket::response::Redirect;
-use rocket_contrib::{database, serve::StaticFiles, templates::Template};
+use rocket_contrib::{database, json::Json, serve::StaticFiles, templates::Template};
 use std::collections::HashMap;
 
+#[macro_use]
+mod macros;
+
+mod api;
+mod audit;
 mod auth;
+mod cli;
+mod csrf;
+mod error;
+mod event;
+mod export;
+mod import;
+mod mail;
+mod profile;
+mod review;
+mod role;
+mod schedule;
 mod schema;
+mod session;
+mod speaker;
 mod talk;
+mod token;
 mod user;
+mod user_admin;
+mod window;
 
-use talk::{Talk, TalkStatus};
+use api::{ApiError, ApiResult, ApiUser};
+use audit::TalkEvent;
+use csrf::CsrfToken;
+use error::CfpError;
+use event::{Event, Submission};
+use mail::Mail;
+use review::{Review, ReviewSummary, Score};
+use role::{ProgramChair, Reviewer, Speaker};
+use session::{Session, SESSION_COOKIE};
+use speaker::{CoSpeaker, Invitation};
+use talk::{
+    AudienceLevel, PresenterVisibility, StatusChange, Tags, Talk, TalkDetails, TalkFilter, TalkFormat, TalkSort,
+    TalkStatus, TalkView,
+};
+use token::ApiToken;
 use user::User;
+use window::CfpWindow;
 
-type Result<T> = std::result::Result<T, Status>;
+type Result<T> = std::result::Result<T, CfpError>;
 
 #[database("cfp")]
 pub struct DbConn(diesel::SqliteConnection);
 
-/// Retrieves the logged-in user based on the session cookie.
+/// Retrieves the logged-in user based on the session cookie, or on an API
+/// token passed as `Authorization: Bearer <token>`. Fails with a 401 if an
+/// invalid token is passed. Revoked or expired sessions are ignored.
 impl<'a, 'r> FromRequest<'a, 'r> for User {
     type Error = ();
 
@@ -46,36 +96,27 @@ impl<'a, 'r> FromRequest<'a, 'r> for User {
         // FIXME: Implement a request guard that provides a "User" based on the
         // authentication cookie.
         let conn = req.guard::<DbConn>()?;
-        req.cookies()
-            .get_private("user")
-            .and_then(|cookie| {
-                let id = cookie.value().parse::<i32>().ok()?;
-                User::get(&conn, id).ok()
-            })
-            .or_forward(())
-    }
-}
-
-/// A User that is guaranteed to also be an administrator.
-#[derive(Debug)]
-pub struct Admin(pub User);
 
-/// Retrieves the logged-in administrator based on the session cookie.
-/// Forwards if no user is logged in, or if a non-administrator is logged in.
-impl<'a, 'r> FromRequest<'a, 'r> for Admin {
-    type Error = ();
-
-    fn from_request(req: &'a Request<'r>) -> rocket::request::Outcome<Self, Self::Error> {
-        // FIXME: Implement a request guard that verifies that a user is logged in and is also an
-        // administrator.
-        req.guard::<User>().and_then(|user| {
-            if user.is_admin {
-                Some(Admin(user))
-            } else {
-                None
+        if let Some(header) = req.headers().get_one("Authorization") {
+            const BEARER: &str = "Bearer ";
+            if !header.starts_with(BEARER) {
+                return Outcome::Failure((Status::Unauthorized, ()));
             }
-            .or_forward(())
-        })
+            return match ApiToken::authenticate(&conn, header[BEARER.len()..].trim()) {
+                Ok(Some(ref user)) if user.disabled => Outcome::Failure((Status::Forbidden, ())),
+                Ok(Some(user)) => Outcome::Success(user),
+                Ok(None) => Outcome::Failure((Status::Unauthorized, ())),
+                Err(e) => {
+                    error!("Failed to look up an API token: {}", e);
+                    Outcome::Failure((Status::InternalServerError, ()))
+                }
+            };
+        }
+
+        match session::current_user(req, &conn) {
+            Some(ref user) if user.disabled => Outcome::Failure((Status::Forbidden, ())),
+            user => user.or_forward(()),
+        }
     }
 }
 
@@ -85,15 +126,38 @@ impl<'a, 'r> FromRequest<'a, 'r> for Admin {
 //
 //    When a user is logged in, render the current user's talks using a
 //    Template. On the same page, provide a form that will post data to the
-//    `new_talk` route.
+//    `new_talk` route, which submits talks to the default event.
 
 #[get("/")]
-fn index(conn: DbConn, user: User) -> Option<Template> {
-    let talks = Talk::get_for_user(&conn, &user).ok()?;
-    Some(Template::render(
-        "index",
-        json!({ "user": user, "talks": talks }),
-    ))
+fn index(conn: DbConn, user: User, window: State<CfpWindow>, csrf: CsrfToken) -> Result<Template> {
+    let event = Event::get_default(&conn)?;
+    render_index(&conn, &user, &window, &event, &csrf, None, &[])
+}
+
+/// Renders the `index` template for `user`. When a submission was rejected,
+/// `form` and `errors` are used to fill the form back in and explain why.
+fn render_index(
+    conn: &DbConn,
+    user: &User,
+    window: &CfpWindow,
+    event: &Event,
+    csrf: &CsrfToken,
+    form: Option<&NewTalk>,
+    errors: &[String],
+) -> Result<Template> {
+    let talks = Talk::get_for_user(conn, user)?;
+    let invitations = Invitation::get_for_user(conn, user)?;
+    let values = form.cloned().unwrap_or_default();
+    Ok(Template::render("index", json!({
+        "user": user,
+        "talks": talks,
+        "invitations": invitations,
+        "event": event,
+        "cfp": window.for_event(event).status(),
+        "values": values,
+        "errors": errors,
+        "csrf_token": csrf,
+    })))
 }
 
 //  * (index_anonymous) GET /
@@ -106,31 +170,136 @@ fn index_anonymous() -> Template {
     Template::render("welcome", &ctx)
 }
 
-//  * (logout) GET /logout
+//  * (logout_page) GET /logout
 //
-//    Delete the "user" cookie, clearing the session.
+//    Render the "logout" template, a form that logs the user out. Logging out
+//    takes a form, so that other sites can't log users out with a link.
 
 #[get("/logout")]
-fn logout(mut cookies: Cookies) -> Redirect {
-    cookies.remove_private(Cookie::named("user"));
-    Redirect::to(uri!(index_anonymous))
+fn logout_page(user: User, csrf: CsrfToken) -> Template {
+    Template::render("logout", json!({ "user": user, "csrf_token": csrf }))
 }
 
-//  * (admin) GET /admin
+//  * (logout) POST /logout
 //
-//    When an administrator is logged in, render a Template containing all talks
-//    in the database.
+//    End the current session, and delete the "session" cookie.
+
+#[post("/logout")]
+fn logout(conn: DbConn, mut cookies: Cookies) -> Result<Redirect> {
+    if let Some(cookie) = cookies.get_private(SESSION_COOKIE) {
+        Session::end(&conn, cookie.value())?;
+    }
+    cookies.remove_private(Cookie::named(SESSION_COOKIE));
+    csrf::rotate(&mut cookies);
+    Ok(Redirect::to(uri!(index_anonymous)))
+}
 
-#[get("/admin")]
-fn admin(conn: DbConn, admin: Admin) -> Option<Template> {
-    Talk::get_all(&conn, &admin)
-        .ok()
-        .map(|talks| Template::render("admin", json!({ "user": admin.0, "talks": talks })))
+//  * (admin) GET /admin?<event>&<q>&<status>&<tag>&<presenter>&<sort>&<page>
+//
+//    When a reviewer (or any more privileged user) is logged in, render a
+//    Template containing one page of the talks matching the given search
+//    criteria, along with their review summaries. `event` is the slug of the
+//    event to list talks of; all events are listed without it. Only program
+//    chairs can search by presenter.
+
+/// Returns `value` with surrounding whitespace removed, or `None` if nothing
+/// is left.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Returns the URL of the given `page` of the talks matching `filter`.
+fn admin_page_url(filter: &TalkFilter, page: i64) -> String {
+    // Only the query string of this URL is used.
+    let mut url = Url::parse("http://localhost/admin").expect("valid URL");
+    {
+        let mut pairs = url.query_pairs_mut();
+        if let Some(ref event) = filter.event {
+            pairs.append_pair("event", &event.slug);
+        }
+        if let Some(ref q) = filter.query {
+            pairs.append_pair("q", q);
+        }
+        if let Some(status) = filter.status {
+            pairs.append_pair("status", &format!("{:?}", status));
+        }
+        if let Some(ref tag) = filter.tag {
+            pairs.append_pair("tag", tag);
+        }
+        if let Some(ref presenter) = filter.presenter {
+            pairs.append_pair("presenter", presenter);
+        }
+        pairs.append_pair("sort", filter.sort.as_str());
+        pairs.append_pair("page", &page.to_string());
+    }
+    format!("/admin?{}", url.query().unwrap_or(""))
+}
+
+#[get("/admin?<event>&<q>&<status>&<tag>&<presenter>&<sort>&<page>")]
+fn admin(
+    event: Option<String>,
+    q: Option<String>,
+    status: Option<TalkStatus>,
+    tag: Option<String>,
+    presenter: Option<String>,
+    sort: Option<TalkSort>,
+    page: Option<i64>,
+    conn: DbConn,
+    reviewer: Reviewer,
+    chair: Option<ProgramChair>,
+    window: State<CfpWindow>,
+    csrf: CsrfToken,
+) -> Result<Template> {
+    let event = match non_empty(event) {
+        // An unknown event is not found, rather than ignored.
+        Some(slug) => Some(Event::get_by_slug(&conn, &slug)?.ok_or(Status::NotFound)?),
+        None => None,
+    };
+    let filter = TalkFilter {
+        event,
+        query: non_empty(q),
+        status,
+        tag: non_empty(tag),
+        presenter: non_empty(presenter).filter(|_| chair.is_some()),
+        sort: sort.unwrap_or_default(),
+    };
+    let page = page.unwrap_or(1).max(1);
+
+    let visibility = PresenterVisibility::for_user(&conn, &reviewer.0)?;
+    let (talks, total) = Talk::search(&conn, &reviewer, &visibility, &filter, page)?;
+    let ids: Vec<i32> = talks.iter().map(|talk| talk.id).collect();
+    let mut summaries = ReviewSummary::get_for_talks(&conn, &reviewer, &ids)?;
+    let talks: Vec<_> = talks
+        .into_iter()
+        .map(|talk| {
+            let reviews = summaries.remove(&talk.id).unwrap_or_default();
+            json!({ "talk": visibility.view(talk), "reviews": reviews })
+        })
+        .collect();
+
+    let pages = ((total + talk::PAGE_SIZE - 1) / talk::PAGE_SIZE).max(1);
+    let previous = if page > 1 { Some(admin_page_url(&filter, page - 1)) } else { None };
+    let next = if page < pages { Some(admin_page_url(&filter, page + 1)) } else { None };
+    Ok(Template::render("admin", json!({
+        "user": reviewer.0,
+        "talks": talks,
+        "total": total,
+        "page": page,
+        "pages": pages,
+        "previous": previous,
+        "next": next,
+        "filter": filter,
+        "events": Event::get_all(&conn)?,
+        "cfp": window.status(),
+        "csrf_token": csrf,
+    })))
 }
 
 //  * (admin_notice) GET /admin
 //
-//    Render a Template indicating that only logged in administrators can use the page.
+//    Render a Template indicating that only logged in reviewers can use the page.
 
 #[get("/admin", rank = 2)]
 fn admin_notice() -> Template {
@@ -141,58 +310,365 @@ fn admin_notice() -> Template {
 //  * (get_talk) GET /talks/<id>
 //
 //    Render a Template containing all information on the given talk.
+//    Requests that prefer JSON are answered by `get_talk_json` instead.
+
+#[get("/talks/<id>", format = "html")]
+fn get_talk(
+    id: i32,
+    conn: DbConn,
+    user: User,
+    reviewer: Option<Reviewer>,
+    chair: Option<ProgramChair>,
+    csrf: CsrfToken,
+) -> Result<Template> {
+    let talk = Talk::get_one(&conn, &user, id)?.ok_or(Status::NotFound)?;
+    render_talk(&conn, &user, reviewer.as_ref(), chair.as_ref(), &csrf, talk, None, &[])
+}
+
+//  * (get_talk_json) GET /talks/<id> (Accept = application/json)
+//
+//    Returns the same JSON as the `/api/v1/talks/<id>` API route.
+
+#[get("/talks/<id>", format = "json", rank = 2)]
+fn get_talk_json(id: i32, conn: DbConn, user: ApiUser) -> ApiResult<Json<TalkView>> {
+    api::get_talk(id, conn, user)
+}
 
-#[get("/talks/<id>")]
-fn get_talk(id: i32, conn: DbConn, user: User) -> Option<Template> {
-    Talk::get_one(&conn, &user, id)
-        .ok()?
-        .map(|talk| Template::render("talk", json!({ "talk": talk, "user": user })))
+/// Renders the `talk` template for `talk`. When an edit was rejected, `form`
+/// and `errors` are used to fill the edit form back in and explain why.
+///
+/// The presenters' details, and who changed the talk's status, are only shown
+/// to program chairs, and only to organizers if the talk's event is under
+/// blind review; reviewers only see the talk itself. Presenters see their
+/// co-speakers, and the primary speaker can invite more.
+fn render_talk(
+    conn: &DbConn,
+    user: &User,
+    reviewer: Option<&Reviewer>,
+    chair: Option<&ProgramChair>,
+    csrf: &CsrfToken,
+    talk: Talk,
+    form: Option<&NewTalk>,
+    errors: &[String],
+) -> Result<Template> {
+    let (reviews, own_review) = match reviewer {
+        Some(reviewer) => (
+            Review::get_for_talk(conn, reviewer, talk.id)?,
+            Review::get_own(conn, reviewer, talk.id)?,
+        ),
+        None => (vec![], None),
+    };
+    let is_presenter = Talk::is_presented_by(conn, user, talk.id)?;
+    let visibility = PresenterVisibility::for_user(conn, user)?;
+    let chair = chair.filter(|_| visibility.shows_presenter(&talk));
+    let presenters = match chair {
+        Some(_) => CoSpeaker::get_presenters(conn, &talk)?,
+        None => vec![],
+    };
+    let co_speakers = if is_presenter || chair.is_some() {
+        CoSpeaker::get_for_talk(conn, talk.id)?
+    } else {
+        vec![]
+    };
+    let events = TalkEvent::get_for_talk(conn, chair, talk.id)?;
+    let event = Event::get(conn, talk.event)?;
+    let values = form.cloned().unwrap_or_else(|| NewTalk::from_talk(&talk));
+    Ok(Template::render("talk", json!({
+        "can_edit": is_presenter && talk.status.is_editable(),
+        "can_withdraw": is_presenter && talk.status.is_withdrawable(),
+        "is_presenter": is_presenter,
+        "is_primary_speaker": talk.presenter == user.id,
+        "talk": talk,
+        "event": event,
+        "presenters": presenters,
+        "co_speakers": co_speakers,
+        "user": user,
+        "reviews": reviews,
+        "own_review": own_review,
+        "events": events,
+        "values": values,
+        "errors": errors,
+        "csrf_token": csrf,
+    })))
 }
 
 //  * (new_talk) POST /talks/
 //
-//    Accepts a web form in the body with two fields:
+//    Accepts a web form in the body with the following fields:
 //
 //        `title` - The title of the talk
-//        `description` - A description of the talk
+//        `description` - A description (abstract) of the talk
+//        `format` - a `TalkFormat`
+//        `duration` - The length of the talk in minutes
+//        `level` - an `AudienceLevel`
+//        `outline` - An outline of the talk
+//        `notes` - Notes for the reviewers, not shown publicly
+//        `tags` - A comma-separated list of tags
+//
+//    Creates a new talk with the given details, presented by the current
+//    user and submitted to the default event. Responds with a Redirect to the
+//    `index` route. If the submission is invalid, re-renders the `index`
+//    template with the errors instead. Submissions are refused with a 403
+//    outside of the event's CFP window, and only speakers can submit talks.
+//    The speaker gets a confirmation email.
 //
-//    Creates a new talk with the given title and description, presented by
-//    the current user. Responds with a Redirect to the `index` route.
+//    Like every form, it must also carry the `csrf_token` field; the `csrf`
+//    fairing refuses it otherwise.
 
-#[derive(FromForm)]
+#[derive(Clone, FromForm, Serialize)]
 struct NewTalk {
     title: String,
     description: String,
+    format: String,
+    duration: String,
+    level: String,
+    outline: String,
+    notes: String,
+    tags: String,
+}
+
+/// An empty submission form.
+impl Default for NewTalk {
+    fn default() -> NewTalk {
+        NewTalk {
+            title: String::new(),
+            description: String::new(),
+            format: "talk".to_string(),
+            duration: "30".to_string(),
+            level: "beginner".to_string(),
+            outline: String::new(),
+            notes: String::new(),
+            tags: String::new(),
+        }
+    }
+}
+
+impl NewTalk {
+    /// Fills in the form with the current details of `talk`.
+    fn from_talk(talk: &Talk) -> NewTalk {
+        NewTalk {
+            title: talk.title.clone(),
+            description: talk.description.clone(),
+            format: format!("{:?}", talk.format).to_lowercase(),
+            duration: talk.duration.to_string(),
+            level: format!("{:?}", talk.level).to_lowercase(),
+            outline: talk.outline.clone(),
+            notes: talk.notes.clone(),
+            tags: talk.tags.to_string(),
+        }
+    }
+
+    /// Validates the submitted fields, returning either the talk details or a
+    /// list of human-readable errors.
+    fn validate(&self) -> std::result::Result<TalkDetails, Vec<String>> {
+        let mut errors = vec![];
+
+        let format = TalkFormat::from_form_value(RawStr::from_str(&self.format)).ok();
+        if format.is_none() {
+            errors.push(format!("Unknown talk format: '{}'.", self.format));
+        }
+
+        let level = AudienceLevel::from_form_value(RawStr::from_str(&self.level)).ok();
+        if level.is_none() {
+            errors.push(format!("Unknown audience level: '{}'.", self.level));
+        }
+
+        let duration = self.duration.trim().parse::<i32>().ok();
+        if duration.is_none() {
+            errors.push("The duration must be a number of minutes.".to_string());
+        }
+
+        let (format, level, duration) = match (format, level, duration) {
+            (Some(format), Some(level), Some(duration)) => (format, level, duration),
+            _ => return Err(errors),
+        };
+
+        let details = TalkDetails {
+            title: self.title.trim().to_string(),
+            description: self.description.trim().to_string(),
+            format,
+            duration,
+            level,
+            outline: self.outline.trim().to_string(),
+            notes: self.notes.trim().to_string(),
+            tags: Tags::parse(&self.tags),
+        };
+        errors.extend(details.validate());
+
+        if errors.is_empty() {
+            Ok(details)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The response to a form submission: a Redirect on success, or the form
+/// re-rendered with validation errors.
+#[derive(Responder)]
+pub enum SubmitResponse {
+    Redirect(Redirect),
+    #[response(status = 422)]
+    Invalid(Template),
 }
 
 #[post("/talks", data = "<talk>")]
-fn new_talk(talk: Form<NewTalk>, conn: DbConn, user: User) -> Result<Redirect> {
-    Talk::new(&conn, &user, &talk.title, &talk.description)
-        .map_err(|_| Status::InternalServerError)?;
+fn new_talk(
+    talk: LenientForm<NewTalk>,
+    conn: DbConn,
+    speaker: Speaker,
+    window: State<CfpWindow>,
+    mail: State<Mail>,
+    csrf: CsrfToken,
+) -> Result<SubmitResponse> {
+    let user = speaker.0;
+    let event = Event::get_default(&conn)?;
+    match event::submit(&conn, &user, &window, &mail, &event, &talk)? {
+        Submission::Submitted(_) => Ok(SubmitResponse::Redirect(Redirect::to(uri!(index)))),
+        Submission::Invalid(errors) => {
+            let page = render_index(&conn, &user, &window, &event, &csrf, Some(&*talk), &errors)?;
+            Ok(SubmitResponse::Invalid(page))
+        }
+    }
+}
+
+/// Retrieves the talk with the given `id`, making sure that `user` presents
+/// it, either as its primary speaker or as a co-speaker.
+fn presented_talk(conn: &DbConn, user: &User, id: i32) -> Result<Talk> {
+    let talk = Talk::get_one(conn, user, id)?.ok_or(Status::NotFound)?;
+    if !Talk::is_presented_by(conn, user, id)? {
+        return Err(Status::Forbidden.into());
+    }
+    Ok(talk)
+}
+
+//  * (edit_talk) PUT /talks/<id>
+//
+//    Accepts the same web form as `new_talk`. Replaces the details of the
+//    talk, which must be presented (or co-presented) by the current user and
+//    still be `Pending`. Responds with a Redirect to the `get_talk` route, or
+//    re-renders the `talk` template with the validation errors.
+
+#[put("/talks/<id>", data = "<talk>")]
+fn edit_talk(
+    id: i32,
+    talk: LenientForm<NewTalk>,
+    conn: DbConn,
+    user: User,
+    reviewer: Option<Reviewer>,
+    chair: Option<ProgramChair>,
+    csrf: CsrfToken,
+) -> Result<SubmitResponse> {
+    let existing = presented_talk(&conn, &user, id)?;
+    if !existing.status.is_editable() {
+        return Err(Status::Conflict.into());
+    }
+    let details = match talk.validate() {
+        Ok(details) => details,
+        Err(errors) => {
+            let page = render_talk(
+                &conn,
+                &user,
+                reviewer.as_ref(),
+                chair.as_ref(),
+                &csrf,
+                existing,
+                Some(&*talk),
+                &errors,
+            )?;
+            return Ok(SubmitResponse::Invalid(page));
+        }
+    };
+    let updated = Talk::update(&conn, &user, id, &details)?;
+    if !updated {
+        return Err(Status::Conflict.into());
+    }
+    Ok(SubmitResponse::Redirect(Redirect::to(uri!(get_talk: id))))
+}
+
+//  * (withdraw_talk) DELETE /talks/<id>
+//
+//    Withdraws the talk, which must be presented (or co-presented) by the
+//    current user and be either `Pending` or `Approved`. The talk is kept with a `Withdrawn`
+//    status. Responds with a Redirect to the `index` route.
+
+#[delete("/talks/<id>")]
+fn withdraw_talk(id: i32, conn: DbConn, user: User) -> Result<Redirect> {
+    if !presented_talk(&conn, &user, id)?.status.is_withdrawable() {
+        return Err(Status::Conflict.into());
+    }
+    let withdrawn = Talk::withdraw(&conn, &user, id)?;
+    if !withdrawn {
+        return Err(Status::Conflict.into());
+    }
     Ok(Redirect::to(uri!(index)))
 }
 
 //  * (set_status) PUT /talks/<id>/status
 //
-//    Accepts a web form in the body with one field:
+//    Accepts a web form in the body with the following fields:
 //
 //        `new_status` - a `TalkStatus`.
+//        `note` - an optional note explaining the change
 //
 //    `TalkStatus` already implements `FromFormValue` so that it can be
 //    conveniently used in a form.
 //
-//    Updates the talk's status. Responds with a Redirect to the `admin` route.
+//    Updates the talk's status, which only program chairs may do, and records
+//    the change in the talk's history. Speakers are emailed when their talk
+//    is approved or rejected. Responds with a Redirect back to the talk.
 
 #[derive(FromForm)]
 struct NewStatus {
     new_status: TalkStatus,
+    note: Option<String>,
 }
 
 #[put("/talks/<id>/status", data = "<status>")]
-fn set_status(id: i32, status: Form<NewStatus>, conn: DbConn, admin: Admin) -> Result<Redirect> {
-    Talk::set_status(&conn, &admin, id, status.new_status)
-        .map_err(|_| Status::InternalServerError)?;
-    Ok(Redirect::to(uri!(admin)))
+fn set_status(
+    id: i32,
+    status: LenientForm<NewStatus>,
+    conn: DbConn,
+    chair: ProgramChair,
+    mail: State<Mail>,
+) -> Result<Redirect> {
+    let note = status.note.as_ref().map(|note| note.trim()).filter(|note| !note.is_empty());
+    match Talk::set_status(&conn, &chair, id, status.new_status, note)? {
+        StatusChange::NotFound => return Err(Status::NotFound.into()),
+        StatusChange::Unchanged => {}
+        StatusChange::Changed => {
+            if let Ok(Some(talk)) = Talk::get_one(&conn, &chair.0, id) {
+                mail.send_decision(&conn, &talk);
+            }
+        }
+    }
+    Ok(Redirect::to(uri!(get_talk: id)))
+}
+
+//  * (submit_review) PUT /talks/<id>/review
+//
+//    Accepts a web form in the body with two fields:
+//
+//        `score` - a `Score` between `MIN_SCORE` and `MAX_SCORE`
+//        `comments` - the reviewer's free-text comments
+//
+//    Creates or replaces the current reviewer's review of the talk.
+//    Responds with a Redirect back to the `get_talk` route.
+
+#[derive(FromForm)]
+struct NewReview {
+    score: Score,
+    comments: String,
+}
+
+#[put("/talks/<id>/review", data = "<review>")]
+fn submit_review(id: i32, review: LenientForm<NewReview>, conn: DbConn, reviewer: Reviewer) -> Result<Redirect> {
+    Talk::get_one(&conn, &reviewer.0, id)?.ok_or(Status::NotFound)?;
+    if Talk::is_presented_by(&conn, &reviewer.0, id)? {
+        return Err(CfpError::new(Status::Forbidden, "You can't review your own talk."));
+    }
+    Review::submit(&conn, &reviewer, id, review.score, &review.comments)?;
+    Ok(Redirect::to(uri!(get_talk: id)))
 }
 
 // Embed the SQL database schema (in the `migrations/` directory) directly into
@@ -201,18 +677,25 @@ fn set_status(id: i32, status: Form<NewStatus>, conn: DbConn, admin: Admin) -> R
 embed_migrations!();
 
 /// Initialize and launch the Rocket application.
-fn main() {
-    rocket::ignite()
+/// Sets up the application on top of `rocket`, which is either configured
+/// from `Rocket.toml` or, in tests, built from a custom configuration.
+fn build(rocket: rocket::Rocket) -> rocket::Rocket {
+    rocket
         .mount(
             "/",
-            routes![index, index_anonymous, logout, admin, admin_notice],
+            routes![index, index_anonymous, logout_page, logout, admin, admin_notice],
         )
-        .mount("/", routes![get_talk, set_status, new_talk])
+        .mount("/", routes![get_talk, get_talk_json, set_status, new_talk, edit_talk, withdraw_talk])
+        .mount("/", routes![submit_review])
         .mount("/static/css", StaticFiles::from("templates/css"))
         .attach(Template::fairing())
         .attach(DbConn::fairing())
         .attach(AdHoc::on_attach("Database Migrations", |rocket| {
-            let conn = DbConn::get_one(&rocket).expect("database connection");
+            // Without a connection, the database fairing has already failed.
+            let conn = match DbConn::get_one(&rocket) {
+                Some(conn) => conn,
+                None => return Err(rocket),
+            };
             match embedded_migrations::run(&*conn) {
                 Ok(()) => Ok(rocket),
                 Err(e) => {
@@ -222,5 +705,35 @@ fn main() {
             }
         }))
         .attach(auth::fairing())
-        .launch();
+        .attach(auth::local::fairing())
+        .attach(session::fairing())
+        .attach(csrf::fairing())
+        .attach(window::fairing())
+        .attach(api::fairing())
+        .attach(token::fairing())
+        .attach(role::fairing())
+        .attach(user_admin::fairing())
+        .attach(audit::fairing())
+        .attach(event::fairing())
+        .attach(export::fairing())
+        .attach(speaker::fairing())
+        .attach(profile::fairing())
+        .attach(import::fairing())
+        .attach(schedule::fairing())
+        .attach(mail::fairing())
+        .register(error::catchers())
+}
+
+fn rocket() -> rocket::Rocket {
+    build(rocket::ignite())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None | Some("serve") => {
+            rocket().launch();
+        }
+        Some(command) => std::process::exit(cli::run(rocket(), command, &args[1..])),
+    }
 }
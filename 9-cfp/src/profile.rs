@@ -0,0 +1,342 @@
+use std::fs;
+use std::io::Read;
+use std::mem;
+use std::path::PathBuf;
+
+use diesel;
+use diesel::prelude::*;
+use diesel::result::Error;
+use multipart::server::Multipart;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use rocket::config::{self, Config, ConfigError};
+use rocket::data::Data;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::{ContentType, Status};
+use rocket::request::{LenientForm, State};
+use rocket::response::Redirect;
+use rocket_contrib::serve::StaticFiles;
+use rocket_contrib::templates::Template;
+
+use csrf::CsrfToken;
+use error::CfpResult;
+use schema::profiles;
+use user::User;
+use {DbConn, SubmitResponse};
+
+/// The largest headshot that can be uploaded, in bytes.
+const MAX_AVATAR_SIZE: u64 = 2 * 1024 * 1024;
+
+/// The longest bio a speaker can have, in characters.
+const MAX_BIO_LENGTH: usize = 2000;
+
+/// The longest any other profile field can be, in characters.
+const MAX_FIELD_LENGTH: usize = 100;
+
+/// A speaker's public profile. Every user has one, even if it is still
+/// empty.
+#[derive(Clone, Debug, Default, Queryable, Insertable, Serialize)]
+#[table_name = "profiles"]
+pub struct Profile {
+    pub user: i32,
+    pub bio: String,
+    pub pronouns: String,
+    pub company: String,
+    /// An `http` or `https` URL
+    pub website: String,
+    /// A Twitter handle, without the `@`
+    pub twitter: String,
+    /// A GitHub login
+    pub github: String,
+    /// The file name of the headshot in the avatar directory
+    pub avatar: Option<String>,
+}
+
+impl Profile {
+    /// Get the profile of `user`, which is empty if they never saved one.
+    pub fn get(conn: &DbConn, user: &User) -> Result<Profile, Error> {
+        let profile = profiles::table
+            .filter(profiles::user.eq(user.id))
+            .first(&conn.0)
+            .optional()?;
+
+        Ok(profile.unwrap_or_else(|| Profile { user: user.id, ..Profile::default() }))
+    }
+
+    /// Stores the profile, replacing the previous one.
+    pub fn save(&self, conn: &DbConn) -> Result<(), Error> {
+        diesel::replace_into(profiles::table)
+            .values(self)
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+}
+
+/// The directory uploaded headshots are stored in, which is served under
+/// `/avatars`.
+pub struct AvatarDir(pub PathBuf);
+
+impl AvatarDir {
+    /// Reads the `avatar_dir` configuration parameter, which defaults to
+    /// `avatars`, relative to the configuration file.
+    pub fn from_config(config: &Config) -> config::Result<AvatarDir> {
+        let dir = match config.get_str("avatar_dir") {
+            Ok(dir) => dir,
+            Err(ConfigError::Missing(_)) => "avatars",
+            Err(e) => return Err(e),
+        };
+        Ok(AvatarDir(config.root_relative(dir)))
+    }
+}
+
+/// Returns the file extension of a PNG, JPEG or WebP image, going by its
+/// contents rather than by what the browser claims it is, or `None` for
+/// anything else.
+fn image_extension(image: &[u8]) -> Option<&'static str> {
+    if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if image.starts_with(b"\xff\xd8\xff") {
+        Some("jpg")
+    } else if image.len() >= 12 && &image[..4] == b"RIFF" && &image[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// Returns `value` trimmed, and without a leading `@`.
+fn handle(value: &str) -> String {
+    value.trim().trim_left_matches('@').to_string()
+}
+
+/// The profile fields submitted through the profile form.
+#[derive(Clone, FromForm, Serialize)]
+struct ProfileForm {
+    bio: String,
+    pronouns: String,
+    company: String,
+    website: String,
+    twitter: String,
+    github: String,
+}
+
+impl ProfileForm {
+    /// Fills in the form with the current contents of `profile`.
+    fn from_profile(profile: &Profile) -> ProfileForm {
+        ProfileForm {
+            bio: profile.bio.clone(),
+            pronouns: profile.pronouns.clone(),
+            company: profile.company.clone(),
+            website: profile.website.clone(),
+            twitter: profile.twitter.clone(),
+            github: profile.github.clone(),
+        }
+    }
+
+    /// Validates the submitted fields, returning either `profile` with the
+    /// fields replaced or a list of human-readable errors.
+    fn validate(&self, profile: &Profile) -> Result<Profile, Vec<String>> {
+        let mut errors = vec![];
+        let updated = Profile {
+            bio: self.bio.trim().to_string(),
+            pronouns: self.pronouns.trim().to_string(),
+            company: self.company.trim().to_string(),
+            website: self.website.trim().to_string(),
+            twitter: handle(&self.twitter),
+            github: handle(&self.github),
+            ..profile.clone()
+        };
+
+        if updated.bio.chars().count() > MAX_BIO_LENGTH {
+            errors.push(format!("The bio must be at most {} characters long.", MAX_BIO_LENGTH));
+        }
+        let fields = [
+            ("pronouns", &updated.pronouns),
+            ("company", &updated.company),
+            ("website", &updated.website),
+            ("Twitter handle", &updated.twitter),
+            ("GitHub login", &updated.github),
+        ];
+        for &(name, value) in &fields {
+            if value.chars().count() > MAX_FIELD_LENGTH {
+                errors.push(format!("The {} must be at most {} characters long.", name, MAX_FIELD_LENGTH));
+            }
+        }
+        if !updated.website.is_empty()
+            && !updated.website.starts_with("https://")
+            && !updated.website.starts_with("http://")
+        {
+            errors.push("The website must start with http:// or https://.".to_string());
+        }
+        for &(name, value) in &[("Twitter handle", &updated.twitter), ("GitHub login", &updated.github)] {
+            if !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                errors.push(format!("The {} may only contain letters, digits, '_' and '-'.", name));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(updated)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Renders the `profile` template for `user`. When a change was rejected,
+/// `form` and `errors` are used to fill the form back in and explain why.
+fn render_profile(
+    conn: &DbConn,
+    user: &User,
+    csrf: &CsrfToken,
+    form: Option<&ProfileForm>,
+    errors: &[String],
+) -> CfpResult<Template> {
+    let profile = Profile::get(conn, user)?;
+    let values = form.cloned().unwrap_or_else(|| ProfileForm::from_profile(&profile));
+    Ok(Template::render("profile", json!({
+        "user": user,
+        "profile": profile,
+        "values": values,
+        "errors": errors,
+        "max_avatar_size": MAX_AVATAR_SIZE / 1024 / 1024,
+        "csrf_token": csrf,
+    })))
+}
+
+/// Shows the current user's profile, and lets them edit it.
+#[get("/profile")]
+fn profile_page(conn: DbConn, user: User, csrf: CsrfToken) -> CfpResult<Template> {
+    render_profile(&conn, &user, &csrf, None, &[])
+}
+
+/// Updates the current user's profile. Responds with a Redirect back to the
+/// profile, or re-renders it with the validation errors.
+#[put("/profile", data = "<form>")]
+fn update_profile(
+    form: LenientForm<ProfileForm>,
+    conn: DbConn,
+    user: User,
+    csrf: CsrfToken,
+) -> CfpResult<SubmitResponse> {
+    let profile = Profile::get(&conn, &user)?;
+    let profile = match form.validate(&profile) {
+        Ok(profile) => profile,
+        Err(errors) => {
+            let page = render_profile(&conn, &user, &csrf, Some(&*form), &errors)?;
+            return Ok(SubmitResponse::Invalid(page));
+        }
+    };
+    profile.save(&conn)?;
+    Ok(SubmitResponse::Redirect(Redirect::to("/profile")))
+}
+
+/// Reads the file uploaded as the `avatar` field of a multipart form. Fails
+/// with a 413 if it is larger than `MAX_AVATAR_SIZE`.
+fn read_avatar(content_type: &ContentType, data: Data) -> CfpResult<Vec<u8>> {
+    let boundary = content_type
+        .params()
+        .find(|&(name, _)| name == "boundary")
+        .map(|(_, value)| value)
+        .ok_or(Status::BadRequest)?;
+    // Leave some room for the other fields and the multipart headers.
+    let body = data.open().take(MAX_AVATAR_SIZE + 64 * 1024);
+    let mut multipart = Multipart::with_body(body, boundary);
+
+    loop {
+        let mut field = match multipart.read_entry() {
+            Ok(Some(field)) => field,
+            Ok(None) | Err(_) => return Err(Status::BadRequest.into()),
+        };
+        if &*field.headers.name != "avatar" {
+            continue;
+        }
+
+        let mut image = vec![];
+        field
+            .data
+            .by_ref()
+            .take(MAX_AVATAR_SIZE + 1)
+            .read_to_end(&mut image)
+            .map_err(|_| Status::BadRequest)?;
+        if image.len() as u64 > MAX_AVATAR_SIZE {
+            return Err(Status::PayloadTooLarge.into());
+        }
+        return Ok(image);
+    }
+}
+
+/// Removes a headshot that is no longer used.
+pub fn remove_avatar_file(dir: &AvatarDir, file_name: &str) {
+    if let Err(e) = fs::remove_file(dir.0.join(file_name)) {
+        warn!("Failed to remove avatar {}: {}", file_name, e);
+    }
+}
+
+/// Uploads a headshot for the current user, replacing the previous one.
+/// Only PNG, JPEG and WebP images up to `MAX_AVATAR_SIZE` are accepted.
+#[post("/profile/avatar", format = "multipart/form-data", data = "<data>")]
+fn upload_avatar(
+    content_type: &ContentType,
+    data: Data,
+    conn: DbConn,
+    user: User,
+    dir: State<AvatarDir>,
+) -> CfpResult<Redirect> {
+    let image = read_avatar(content_type, data)?;
+    let extension = image_extension(&image).ok_or(Status::UnsupportedMediaType)?;
+
+    // A new name for every upload keeps browsers from showing a cached
+    // headshot.
+    let random: String = thread_rng().sample_iter(&Alphanumeric).take(16).collect();
+    let file_name = format!("{}-{}.{}", user.id, random, extension);
+    fs::write(dir.0.join(&file_name), &image).map_err(|e| {
+        error!("Failed to store avatar {}: {}", file_name, e);
+        Status::InternalServerError
+    })?;
+
+    let mut profile = Profile::get(&conn, &user)?;
+    let old = mem::replace(&mut profile.avatar, Some(file_name));
+    profile.save(&conn)?;
+    if let Some(old) = old {
+        remove_avatar_file(&dir, &old);
+    }
+    Ok(Redirect::to("/profile"))
+}
+
+/// Removes the current user's headshot.
+#[delete("/profile/avatar")]
+fn delete_avatar(conn: DbConn, user: User, dir: State<AvatarDir>) -> CfpResult<Redirect> {
+    let mut profile = Profile::get(&conn, &user)?;
+    let old = profile.avatar.take().ok_or(Status::NotFound)?;
+    profile.save(&conn)?;
+    remove_avatar_file(&dir, &old);
+    Ok(Redirect::to("/profile"))
+}
+
+/// Returns a fairing that sets up speaker profiles:
+///
+/// * Reads the avatar directory from the configuration, and creates it.
+/// * Mounts the profile routes, and serves the headshots under `/avatars`.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Profiles", |rocket| {
+        let dir = match AvatarDir::from_config(rocket.config()) {
+            Ok(dir) => dir,
+            Err(e) => {
+                error!("Invalid avatar directory: {}", e);
+                return Err(rocket);
+            }
+        };
+        if let Err(e) = fs::create_dir_all(&dir.0) {
+            error!("Failed to create the avatar directory {}: {}", dir.0.display(), e);
+            return Err(rocket);
+        }
+
+        let avatars = StaticFiles::from(&dir.0);
+        Ok(rocket
+            .manage(dir)
+            .mount("/", routes![profile_page, update_profile, upload_avatar, delete_avatar])
+            .mount("/avatars", avatars)
+        )
+    })
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use diesel;
+use diesel::prelude::*;
+use diesel::result::Error;
+use rocket::http::RawStr;
+use rocket::request::FromFormValue;
+
+use role::Reviewer;
+use schema::{reviews, users};
+use DbConn;
+
+/// The lowest score a reviewer can give a talk.
+pub const MIN_SCORE: i32 = 1;
+/// The highest score a reviewer can give a talk.
+pub const MAX_SCORE: i32 = 5;
+
+/// A review score, guaranteed to be between `MIN_SCORE` and `MAX_SCORE`.
+#[derive(Copy, Clone, Debug)]
+pub struct Score(pub i32);
+
+impl<'v> FromFormValue<'v> for Score {
+    type Error = &'v RawStr;
+
+    fn from_form_value(value: &'v RawStr) -> Result<Self, Self::Error> {
+        match value.parse::<i32>() {
+            Ok(score) if score >= MIN_SCORE && score <= MAX_SCORE => Ok(Score(score)),
+            _ => Err(value),
+        }
+    }
+}
+
+/// Review data retrieved from the database
+#[derive(Queryable, Serialize)]
+pub struct Review {
+    pub id: i32,
+    pub reviewer: i32,
+    pub talk: i32,
+    pub score: i32,
+    pub comments: String,
+}
+
+/// A review together with the name of the reviewer who wrote it
+#[derive(Serialize)]
+pub struct NamedReview {
+    #[serde(flatten)]
+    pub review: Review,
+    pub reviewer_name: String,
+}
+
+/// Review data to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "reviews"]
+struct NewReview<'a> {
+    pub reviewer: i32,
+    pub talk: i32,
+    pub score: i32,
+    pub comments: &'a str,
+}
+
+impl Review {
+    /// Retrieve all reviews of the talk with the given `talk_id`
+    pub fn get_for_talk(
+        conn: &DbConn,
+        _reviewer: &Reviewer,
+        talk_id: i32,
+    ) -> Result<Vec<NamedReview>, Error> {
+        let rows: Vec<(Review, String)> = reviews::table
+            .inner_join(users::table)
+            .filter(reviews::talk.eq(talk_id))
+            .select((reviews::all_columns, users::name))
+            .load(&conn.0)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(review, reviewer_name)| NamedReview { review, reviewer_name })
+            .collect())
+    }
+
+    /// Retrieve the review `reviewer` has written for the talk with the given
+    /// `talk_id`, if any
+    pub fn get_own(conn: &DbConn, reviewer: &Reviewer, talk_id: i32) -> Result<Option<Review>, Error> {
+        reviews::table
+            .filter(reviews::talk.eq(talk_id).and(reviews::reviewer.eq(reviewer.0.id)))
+            .first(&conn.0)
+            .optional()
+    }
+
+    /// Submits `reviewer`'s review of the talk with the given `talk_id`,
+    /// replacing their previous review of that talk if there is one.
+    pub fn submit(
+        conn: &DbConn,
+        reviewer: &Reviewer,
+        talk_id: i32,
+        score: Score,
+        comments: &str,
+    ) -> Result<(), Error> {
+        diesel::replace_into(reviews::table)
+            .values(NewReview {
+                reviewer: reviewer.0.id,
+                talk: talk_id,
+                score: score.0,
+                comments,
+            })
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+}
+
+/// Aggregate statistics over all reviews of a single talk
+#[derive(Debug, Default, Serialize)]
+pub struct ReviewSummary {
+    pub count: usize,
+    pub average: Option<f64>,
+    pub variance: Option<f64>,
+}
+
+impl ReviewSummary {
+    /// Computes the summary of the given scores. The variance is the
+    /// population variance, i.e. it is `0` for a single review.
+    pub fn from_scores(scores: &[i32]) -> ReviewSummary {
+        if scores.is_empty() {
+            return ReviewSummary::default();
+        }
+
+        let count = scores.len();
+        let average = scores.iter().sum::<i32>() as f64 / count as f64;
+        let variance = scores
+            .iter()
+            .map(|&score| (score as f64 - average).powi(2))
+            .sum::<f64>() / count as f64;
+
+        ReviewSummary { count, average: Some(average), variance: Some(variance) }
+    }
+
+    /// Retrieve review summaries for the talks with the given `talk_ids`,
+    /// keyed by talk ID. Talks that haven't been reviewed yet are missing
+    /// from the map.
+    pub fn get_for_talks(
+        conn: &DbConn,
+        _reviewer: &Reviewer,
+        talk_ids: &[i32],
+    ) -> Result<HashMap<i32, ReviewSummary>, Error> {
+        let rows: Vec<(i32, i32)> = reviews::table
+            .filter(reviews::talk.eq_any(talk_ids))
+            .select((reviews::talk, reviews::score))
+            .load(&conn.0)?;
+
+        let mut scores: HashMap<i32, Vec<i32>> = HashMap::new();
+        for (talk, score) in rows {
+            scores.entry(talk).or_insert_with(Vec::new).push(score);
+        }
+
+        Ok(scores
+            .into_iter()
+            .map(|(talk, scores)| (talk, ReviewSummary::from_scores(&scores)))
+            .collect())
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+
+use diesel;
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Integer;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, LenientForm, Request};
+use rocket::response::Redirect;
+use rocket::FromFormValue;
+use rocket_contrib::templates::Template;
+
+use audit::AdminEvent;
+use csrf::CsrfToken;
+use error::{CfpResult, InvalidValue};
+use schema::user_roles;
+use user::User;
+use DbConn;
+
+/// A role a user can be granted. Roles are hierarchical: organizers can do
+/// everything program chairs can, who can do everything reviewers can.
+///
+///  * Speakers submit talks. Every new user is a speaker.
+///  * Reviewers score talks, but don't get to see who the speakers are.
+///  * Program chairs also decide on talks and see the speakers' details.
+///  * Organizers also manage the CFP itself and grant roles to others.
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, PartialEq, FromSqlRow, AsExpression, Serialize, Deserialize, FromFormValue)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Integer"]
+pub enum Role {
+    Speaker = 0,
+    Reviewer = 1,
+    #[form(value = "program_chair")]
+    ProgramChair = 2,
+    Organizer = 3,
+}
+
+impl TryFrom<i32> for Role {
+    type Error = InvalidValue;
+
+    fn try_from(i: i32) -> Result<Role, InvalidValue> {
+        match i {
+            0 => Ok(Role::Speaker),
+            1 => Ok(Role::Reviewer),
+            2 => Ok(Role::ProgramChair),
+            3 => Ok(Role::Organizer),
+            _ => Err(InvalidValue { type_name: "Role", value: i }),
+        }
+    }
+}
+
+integer_sql_enum!(Role);
+
+/// All roles, from least to most privileged.
+pub const ALL_ROLES: [Role; 4] = [Role::Speaker, Role::Reviewer, Role::ProgramChair, Role::Organizer];
+
+/// The roles a user effectively has, taking the hierarchy of roles into
+/// account.
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct Roles {
+    pub speaker: bool,
+    pub reviewer: bool,
+    pub program_chair: bool,
+    pub organizer: bool,
+}
+
+impl Roles {
+    /// Computes the effective roles of a user who was granted `granted`.
+    pub fn from_granted(granted: &[Role]) -> Roles {
+        let organizer = granted.contains(&Role::Organizer);
+        let program_chair = organizer || granted.contains(&Role::ProgramChair);
+        let reviewer = program_chair || granted.contains(&Role::Reviewer);
+        Roles { speaker: granted.contains(&Role::Speaker), reviewer, program_chair, organizer }
+    }
+
+    /// Whether the user effectively has `role`.
+    pub fn has(&self, role: Role) -> bool {
+        match role {
+            Role::Speaker => self.speaker,
+            Role::Reviewer => self.reviewer,
+            Role::ProgramChair => self.program_chair,
+            Role::Organizer => self.organizer,
+        }
+    }
+}
+
+/// A role grant to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "user_roles"]
+struct NewUserRole {
+    pub user: i32,
+    pub role: Role,
+}
+
+impl Role {
+    /// Retrieve the roles explicitly granted to the user with the given `id`
+    pub fn get_granted(conn: &DbConn, id: i32) -> Result<Vec<Role>, Error> {
+        user_roles::table
+            .filter(user_roles::user.eq(id))
+            .select(user_roles::role)
+            .order(user_roles::role)
+            .load(&conn.0)
+    }
+
+    /// Retrieve the roles explicitly granted to every user, keyed by user ID.
+    /// Users without any roles are missing from the map.
+    pub fn get_all_granted(conn: &DbConn, _admin: &Admin) -> Result<HashMap<i32, Vec<Role>>, Error> {
+        let rows: Vec<(i32, Role)> = user_roles::table
+            .order((user_roles::user, user_roles::role))
+            .load(&conn.0)?;
+
+        let mut granted: HashMap<i32, Vec<Role>> = HashMap::new();
+        for (user, role) in rows {
+            granted.entry(user).or_insert_with(Vec::new).push(role);
+        }
+        Ok(granted)
+    }
+
+    /// Grants `role` to the user with the given `id`. Granting a role twice
+    /// has no effect.
+    pub fn grant(conn: &DbConn, _admin: &Admin, id: i32, role: Role) -> Result<(), Error> {
+        Role::insert(conn, id, role)
+    }
+
+    /// Grants the roles every new user gets: speaker, and organizer if
+    /// `is_admin`.
+    pub fn grant_initial(conn: &DbConn, id: i32, is_admin: bool) -> Result<(), Error> {
+        Role::insert(conn, id, Role::Speaker)?;
+        if is_admin {
+            Role::insert(conn, id, Role::Organizer)?;
+        }
+        Ok(())
+    }
+
+    fn insert(conn: &DbConn, id: i32, role: Role) -> Result<(), Error> {
+        diesel::replace_into(user_roles::table)
+            .values(NewUserRole { user: id, role })
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Revokes `role` from the user with the given `id`. Returns whether the
+    /// user had been granted the role.
+    pub fn revoke(conn: &DbConn, _admin: &Admin, id: i32, role: Role) -> Result<bool, Error> {
+        let deleted = diesel::delete(
+            user_roles::table.filter(user_roles::user.eq(id).and(user_roles::role.eq(role))),
+        )
+        .execute(&conn.0)?;
+
+        Ok(deleted > 0)
+    }
+}
+
+/// Defines a request guard for a User that effectively has the given role.
+/// The guard forwards if no user is logged in, or if the logged-in user lacks
+/// the role.
+macro_rules! role_guard {
+    ($(#[$attr:meta])* $name:ident => $role:expr) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        pub struct $name(pub User);
+
+        impl<'a, 'r> FromRequest<'a, 'r> for $name {
+            type Error = ();
+
+            fn from_request(req: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+                let user = req.guard::<User>()?;
+                if user.roles.has($role) {
+                    Outcome::Success($name(user))
+                } else {
+                    Outcome::Forward(())
+                }
+            }
+        }
+    };
+}
+
+role_guard! {
+    /// A User that is allowed to submit talks.
+    Speaker => Role::Speaker
+}
+
+role_guard! {
+    /// A User that is allowed to see and score all talks.
+    Reviewer => Role::Reviewer
+}
+
+role_guard! {
+    /// A User that is allowed to decide on talks and see who submitted them.
+    ProgramChair => Role::ProgramChair
+}
+
+role_guard! {
+    /// A User that is guaranteed to also be an administrator, i.e. an
+    /// organizer.
+    Admin => Role::Organizer
+}
+
+/// Lists all users and the roles they were granted.
+#[get("/admin/roles")]
+fn list_roles(conn: DbConn, admin: Admin, csrf: CsrfToken) -> CfpResult<Template> {
+    let users = User::get_all(&conn)?;
+    let mut granted = Role::get_all_granted(&conn, &admin)?;
+    let users: Vec<_> = users
+        .into_iter()
+        .map(|user| {
+            let roles = granted.remove(&user.id).unwrap_or_default();
+            json!({ "user": user, "granted": roles })
+        })
+        .collect();
+    Ok(Template::render("roles", json!({
+        "user": admin.0,
+        "users": users,
+        "roles": ALL_ROLES,
+        "csrf_token": csrf,
+    })))
+}
+
+/// A struct containing a user ID and a role to grant to or revoke from them.
+#[derive(FromForm)]
+struct RoleChange {
+    user: i32,
+    role: Role,
+}
+
+/// Grants a role to a user.
+#[post("/admin/roles", data = "<change>")]
+fn grant_role(change: LenientForm<RoleChange>, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let user = User::get(&conn, change.user).map_err(|_| Status::NotFound)?;
+    conn.0.transaction::<_, Error, _>(|| {
+        Role::grant(&conn, &admin, user.id, change.role)?;
+        AdminEvent::record(&conn, &admin.0, &format!("granted {:?} to {}", change.role, user.login))
+    })?;
+    info!("{} granted {:?} to user {}", admin.0.login, change.role, change.user);
+    Ok(Redirect::to("/admin/roles"))
+}
+
+/// Revokes a role from a user. Organizers can't revoke their own organizer
+/// role, so that there is always at least one organizer left.
+#[delete("/admin/roles", data = "<change>")]
+fn revoke_role(change: LenientForm<RoleChange>, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    if change.user == admin.0.id && change.role == Role::Organizer {
+        return Err(Status::Conflict.into());
+    }
+    let user = User::get(&conn, change.user).map_err(|_| Status::NotFound)?;
+    let revoked = conn.0.transaction::<_, Error, _>(|| {
+        if !Role::revoke(&conn, &admin, user.id, change.role)? {
+            return Ok(false);
+        }
+        AdminEvent::record(&conn, &admin.0, &format!("revoked {:?} from {}", change.role, user.login))?;
+        Ok(true)
+    })?;
+    if !revoked {
+        return Err(Status::NotFound.into());
+    }
+    info!("{} revoked {:?} from user {}", admin.0.login, change.role, change.user);
+    Ok(Redirect::to("/admin/roles"))
+}
+
+/// Returns a fairing that mounts the role management routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Roles", |rocket| {
+        Ok(rocket.mount("/", routes![list_roles, grant_role, revoke_role]))
+    })
+}
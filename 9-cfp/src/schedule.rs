@@ -0,0 +1,716 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::{ContentType, Status};
+use rocket::request::LenientForm;
+use rocket::response::{Content, Redirect};
+use rocket_contrib::templates::Template;
+
+use audit::AdminEvent;
+use csrf::CsrfToken;
+use error::{CfpError, CfpResult};
+use event::{find_event, Event};
+use role::Admin;
+use schema::{rooms, schedule_entries, slots, talk_speakers, talks, users};
+use talk::TalkStatus;
+use user::User;
+use window::FormTimestamp;
+use {DbConn, SubmitResponse};
+
+/// A room the talks of an event take place in
+#[derive(Queryable, Serialize)]
+pub struct Room {
+    pub id: i32,
+    pub name: String,
+    pub event: i32,
+}
+
+/// A room to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "rooms"]
+struct NewRoom<'a> {
+    pub name: &'a str,
+    pub event: i32,
+}
+
+/// A time slot the talks of an event can be scheduled in. Slots are shared by
+/// all rooms of the event, and may overlap, e.g. to fit workshops next to
+/// shorter talks. Times are UTC.
+#[derive(Queryable, Serialize)]
+pub struct Slot {
+    pub id: i32,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub event: i32,
+}
+
+/// A time slot to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "slots"]
+struct NewSlot {
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub event: i32,
+}
+
+/// A talk's place in the schedule, to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "schedule_entries"]
+struct NewScheduleEntry {
+    pub talk: i32,
+    pub room: i32,
+    pub slot: i32,
+}
+
+/// The outcome of removing a room or a slot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RemoveOutcome {
+    Removed,
+    /// The event has no such room or slot.
+    NotFound,
+    /// Talks are scheduled in the room or slot, so it was kept.
+    InUse,
+}
+
+/// A scheduled talk, with where and when it takes place
+#[derive(Serialize)]
+pub struct ScheduledTalk {
+    pub talk: i32,
+    pub title: String,
+    pub description: String,
+    /// The IDs and names of the primary speaker and the confirmed
+    /// co-speakers
+    #[serde(skip_serializing)]
+    pub presenters: Vec<(i32, String)>,
+    /// The names of all presenters, e.g. `Bob and Carol`
+    pub presenter_name: String,
+    pub status: TalkStatus,
+    pub room: i32,
+    pub room_name: String,
+    pub slot: i32,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+}
+
+/// The columns of a scheduled talk, before its presenters are looked up
+type ScheduleRow = (i32, String, String, TalkStatus, i32, String, i32, NaiveDateTime, NaiveDateTime);
+
+impl ScheduledTalk {
+    fn from_row(
+        (talk, title, description, status, room, room_name, slot, starts_at, ends_at): ScheduleRow,
+        presenters: Vec<(i32, String)>,
+    ) -> ScheduledTalk {
+        let names: Vec<&str> = presenters.iter().map(|&(_, ref name)| name.as_str()).collect();
+        let presenter_name = match names.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+            _ => names.concat(),
+        };
+        ScheduledTalk {
+            talk,
+            title,
+            description,
+            presenters,
+            presenter_name,
+            status,
+            room,
+            room_name,
+            slot,
+            starts_at,
+            ends_at,
+        }
+    }
+
+    /// Whether this talk and `other` take place at the same time, at least
+    /// in part.
+    fn overlaps(&self, other: &ScheduledTalk) -> bool {
+        self.starts_at < other.ends_at && other.starts_at < self.ends_at
+    }
+
+    /// The name of someone who presents both this talk and `other`, if
+    /// anyone does.
+    fn shared_presenter(&self, other: &ScheduledTalk) -> Option<&str> {
+        self.presenters
+            .iter()
+            .find(|&&(id, _)| other.presenters.iter().any(|&(other_id, _)| other_id == id))
+            .map(|&(_, ref name)| name.as_str())
+    }
+
+    /// Describes why this talk and `other` can't both take place as
+    /// scheduled, if they can't.
+    fn clash(&self, other: &ScheduledTalk) -> Option<String> {
+        if !self.overlaps(other) {
+            None
+        } else if self.room == other.room {
+            Some(format!(
+                "\"{}\" and \"{}\" overlap in {}.",
+                self.title, other.title, self.room_name
+            ))
+        } else if let Some(name) = self.shared_presenter(other) {
+            Some(format!(
+                "{} can't present \"{}\" in {} and \"{}\" in {} at the same time.",
+                name, self.title, self.room_name, other.title, other.room_name
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Retrieve the IDs and names of the presenters of the talks with the given
+/// `ids`, keyed by talk ID: the primary speaker first, then the confirmed
+/// co-speakers.
+fn get_presenters(conn: &DbConn, ids: &[i32]) -> Result<HashMap<i32, Vec<(i32, String)>>, Error> {
+    let primary: Vec<(i32, i32, String)> = talks::table
+        .inner_join(users::table)
+        .filter(talks::id.eq_any(ids))
+        .select((talks::id, users::id, users::name))
+        .load(&conn.0)?;
+    let co_speakers: Vec<(i32, i32, String)> = talk_speakers::table
+        .inner_join(users::table)
+        .filter(talk_speakers::talk.eq_any(ids).and(talk_speakers::confirmed.eq(true)))
+        .select((talk_speakers::talk, users::id, users::name))
+        .order(users::name)
+        .load(&conn.0)?;
+
+    let mut presenters: HashMap<i32, Vec<(i32, String)>> = HashMap::new();
+    for (talk, id, name) in primary.into_iter().chain(co_speakers) {
+        presenters.entry(talk).or_insert_with(Vec::new).push((id, name));
+    }
+    Ok(presenters)
+}
+
+/// Describes everything that is wrong with `schedule`: talks that are no
+/// longer approved, and talks that clash with each other.
+pub fn find_conflicts(schedule: &[ScheduledTalk]) -> Vec<String> {
+    let mut conflicts = vec![];
+    for (i, talk) in schedule.iter().enumerate() {
+        if talk.status != TalkStatus::Approved {
+            conflicts.push(format!("\"{}\" is scheduled, but {:?}.", talk.title, talk.status));
+        }
+        conflicts.extend(schedule[i + 1..].iter().filter_map(|other| talk.clash(other)));
+    }
+    conflicts
+}
+
+impl Room {
+    /// Retrieve the rooms of `event`, ordered by name
+    pub fn get_all(conn: &DbConn, event: &Event) -> Result<Vec<Room>, Error> {
+        rooms::table.filter(rooms::event.eq(event.id)).order(rooms::name).load(&conn.0)
+    }
+
+    /// Adds a room named `name` to `event`, and records it in the audit log.
+    pub fn create(conn: &DbConn, admin: &Admin, event: &Event, name: &str) -> Result<(), Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            diesel::insert_into(rooms::table)
+                .values(NewRoom { name, event: event.id })
+                .execute(&conn.0)?;
+            AdminEvent::record(conn, &admin.0, &format!("added room {} to event {}", name, event.slug))
+        })
+    }
+
+    /// Removes the room of `event` with the given `id`, unless talks are
+    /// scheduled in it, and records it in the audit log.
+    pub fn delete(conn: &DbConn, admin: &Admin, event: &Event, id: i32) -> Result<RemoveOutcome, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let room: Option<Room> = rooms::table
+                .filter(rooms::id.eq(id).and(rooms::event.eq(event.id)))
+                .first(&conn.0)
+                .optional()?;
+            let room = match room {
+                Some(room) => room,
+                None => return Ok(RemoveOutcome::NotFound),
+            };
+            let used: i64 = schedule_entries::table
+                .filter(schedule_entries::room.eq(id))
+                .count()
+                .get_result(&conn.0)?;
+            if used > 0 {
+                return Ok(RemoveOutcome::InUse);
+            }
+
+            diesel::delete(rooms::table.filter(rooms::id.eq(id))).execute(&conn.0)?;
+            AdminEvent::record(conn, &admin.0, &format!("removed room {} from event {}", room.name, event.slug))?;
+            Ok(RemoveOutcome::Removed)
+        })
+    }
+}
+
+impl Slot {
+    /// Retrieve the slots of `event`, in chronological order
+    pub fn get_all(conn: &DbConn, event: &Event) -> Result<Vec<Slot>, Error> {
+        slots::table
+            .filter(slots::event.eq(event.id))
+            .order((slots::starts_at, slots::ends_at))
+            .load(&conn.0)
+    }
+
+    /// Adds a slot from `starts_at` until `ends_at`, which must be later, to
+    /// `event`, and records it in the audit log.
+    pub fn create(
+        conn: &DbConn,
+        admin: &Admin,
+        event: &Event,
+        starts_at: NaiveDateTime,
+        ends_at: NaiveDateTime,
+    ) -> Result<(), Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            diesel::insert_into(slots::table)
+                .values(NewSlot { starts_at, ends_at, event: event.id })
+                .execute(&conn.0)?;
+            let action = format!("added the slot from {} until {} to event {}", starts_at, ends_at, event.slug);
+            AdminEvent::record(conn, &admin.0, &action)
+        })
+    }
+
+    /// Removes the slot of `event` with the given `id`, unless talks are
+    /// scheduled in it, and records it in the audit log.
+    pub fn delete(conn: &DbConn, admin: &Admin, event: &Event, id: i32) -> Result<RemoveOutcome, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let slot: Option<Slot> = slots::table
+                .filter(slots::id.eq(id).and(slots::event.eq(event.id)))
+                .first(&conn.0)
+                .optional()?;
+            let slot = match slot {
+                Some(slot) => slot,
+                None => return Ok(RemoveOutcome::NotFound),
+            };
+            let used: i64 = schedule_entries::table
+                .filter(schedule_entries::slot.eq(id))
+                .count()
+                .get_result(&conn.0)?;
+            if used > 0 {
+                return Ok(RemoveOutcome::InUse);
+            }
+
+            diesel::delete(slots::table.filter(slots::id.eq(id))).execute(&conn.0)?;
+            let action = format!(
+                "removed the slot from {} until {} from event {}",
+                slot.starts_at, slot.ends_at, event.slug
+            );
+            AdminEvent::record(conn, &admin.0, &action)?;
+            Ok(RemoveOutcome::Removed)
+        })
+    }
+}
+
+/// The schedule of an event
+pub struct Schedule;
+
+impl Schedule {
+    /// Retrieve the talks scheduled at `event` in chronological order,
+    /// including those that are no longer approved.
+    pub fn get_all(conn: &DbConn, event: &Event) -> Result<Vec<ScheduledTalk>, Error> {
+        let rows: Vec<ScheduleRow> = schedule_entries::table
+            .inner_join(talks::table)
+            .inner_join(rooms::table)
+            .inner_join(slots::table)
+            .filter(rooms::event.eq(event.id))
+            .select((
+                talks::id,
+                talks::title,
+                talks::description,
+                talks::status,
+                rooms::id,
+                rooms::name,
+                slots::id,
+                slots::starts_at,
+                slots::ends_at,
+            ))
+            .order((slots::starts_at, rooms::name))
+            .load(&conn.0)?;
+
+        let ids: Vec<i32> = rows.iter().map(|row| row.0).collect();
+        let mut presenters = get_presenters(conn, &ids)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let talk_presenters = presenters.remove(&row.0).unwrap_or_default();
+                ScheduledTalk::from_row(row, talk_presenters)
+            })
+            .collect())
+    }
+
+    /// Retrieve the public schedule of `event`: all scheduled talks that are
+    /// approved, in chronological order
+    pub fn get_public(conn: &DbConn, event: &Event) -> Result<Vec<ScheduledTalk>, Error> {
+        let mut schedule = Schedule::get_all(conn, event)?;
+        schedule.retain(|talk| talk.status == TalkStatus::Approved);
+        Ok(schedule)
+    }
+
+    /// Schedules the talk with the given `talk_id` in a room and slot of
+    /// `event`, moving it if it is already scheduled. Only approved talks
+    /// submitted to `event` can be scheduled, and only where they don't clash
+    /// with other talks. Returns the reasons the talk can't be scheduled, if
+    /// any. Fails with `NotFound` if the talk doesn't exist, or if `event` has
+    /// no such room or slot. Scheduling a talk is recorded in the audit log.
+    pub fn add(
+        conn: &DbConn,
+        admin: &Admin,
+        event: &Event,
+        talk_id: i32,
+        room_id: i32,
+        slot_id: i32,
+    ) -> Result<Vec<String>, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let (title, description, status, talk_event): (String, String, TalkStatus, i32) = talks::table
+                .filter(talks::id.eq(talk_id))
+                .select((talks::title, talks::description, talks::status, talks::event))
+                .first(&conn.0)?;
+            let room: Room = rooms::table
+                .filter(rooms::id.eq(room_id).and(rooms::event.eq(event.id)))
+                .first(&conn.0)?;
+            let slot: Slot = slots::table
+                .filter(slots::id.eq(slot_id).and(slots::event.eq(event.id)))
+                .first(&conn.0)?;
+            if talk_event != event.id {
+                return Ok(vec![format!("\"{}\" was submitted to another event.", title)]);
+            }
+            let presenters = get_presenters(conn, &[talk_id])?.remove(&talk_id).unwrap_or_default();
+            let candidate = ScheduledTalk::from_row(
+                (talk_id, title, description, status, room.id, room.name, slot.id, slot.starts_at, slot.ends_at),
+                presenters,
+            );
+
+            if candidate.status != TalkStatus::Approved {
+                return Ok(vec![format!(
+                    "Only approved talks can be scheduled, but \"{}\" is {:?}.",
+                    candidate.title, candidate.status
+                )]);
+            }
+            let conflicts: Vec<String> = Schedule::get_all(conn, event)?
+                .iter()
+                .filter(|other| other.talk != talk_id)
+                .filter_map(|other| candidate.clash(other))
+                .collect();
+            if !conflicts.is_empty() {
+                return Ok(conflicts);
+            }
+
+            // Replacing the entry of the talk moves it. No other entry can be
+            // replaced, since that would have been a clash.
+            diesel::replace_into(schedule_entries::table)
+                .values(NewScheduleEntry { talk: talk_id, room: room_id, slot: slot_id })
+                .execute(&conn.0)?;
+            let action = format!(
+                "scheduled \"{}\" of event {} in {} at {}",
+                candidate.title, event.slug, candidate.room_name, candidate.starts_at
+            );
+            AdminEvent::record(conn, &admin.0, &action)?;
+            Ok(vec![])
+        })
+    }
+
+    /// Removes the talk with the given `talk_id` from the schedule of `event`,
+    /// and records it in the audit log. Returns whether it was scheduled
+    /// there.
+    pub fn remove(conn: &DbConn, admin: &Admin, event: &Event, talk_id: i32) -> Result<bool, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let rooms: Vec<i32> = rooms::table
+                .filter(rooms::event.eq(event.id))
+                .select(rooms::id)
+                .load(&conn.0)?;
+            let deleted = diesel::delete(
+                schedule_entries::table
+                    .filter(schedule_entries::talk.eq(talk_id).and(schedule_entries::room.eq_any(rooms))),
+            )
+            .execute(&conn.0)?;
+            if deleted == 0 {
+                return Ok(false);
+            }
+
+            let title: String = talks::table.filter(talks::id.eq(talk_id)).select(talks::title).first(&conn.0)?;
+            let action = format!("removed \"{}\" from the schedule of event {}", title, event.slug);
+            AdminEvent::record(conn, &admin.0, &action)?;
+            Ok(true)
+        })
+    }
+}
+
+/// Escapes `text` for use as an iCalendar property value.
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Appends the iCalendar content line `line` to `out`, folding it so that no
+/// line is longer than 75 bytes.
+fn ics_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Formats a UTC time for iCalendar.
+fn ics_time(time: NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Renders `schedule` as an iCalendar file with one event per talk.
+pub fn to_ics(schedule: &[ScheduledTalk]) -> String {
+    let now = ics_time(Utc::now().naive_utc());
+    let mut out = String::new();
+    ics_line(&mut out, "BEGIN:VCALENDAR");
+    ics_line(&mut out, "VERSION:2.0");
+    ics_line(&mut out, "PRODID:-//Rocket//Call for Papers//EN");
+    for talk in schedule {
+        ics_line(&mut out, "BEGIN:VEVENT");
+        ics_line(&mut out, &format!("UID:talk-{}@cfp", talk.talk));
+        ics_line(&mut out, &format!("DTSTAMP:{}", now));
+        ics_line(&mut out, &format!("DTSTART:{}", ics_time(talk.starts_at)));
+        ics_line(&mut out, &format!("DTEND:{}", ics_time(talk.ends_at)));
+        ics_line(&mut out, &format!("SUMMARY:{}", ics_escape(&talk.title)));
+        ics_line(&mut out, &format!("LOCATION:{}", ics_escape(&talk.room_name)));
+        ics_line(&mut out, &format!(
+            "DESCRIPTION:{}",
+            ics_escape(&format!("{}\n\n{}", talk.presenter_name, talk.description))
+        ));
+        ics_line(&mut out, "END:VEVENT");
+    }
+    ics_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// Renders the public schedule of `event`.
+fn render_schedule(conn: &DbConn, user: Option<User>, event: &Event) -> CfpResult<Template> {
+    let schedule = Schedule::get_public(conn, event)?;
+    Ok(Template::render("schedule", json!({ "user": user, "event": event, "schedule": schedule })))
+}
+
+/// Shows the public schedule of an event.
+#[get("/e/<slug>/schedule")]
+fn public_schedule(slug: String, conn: DbConn, user: Option<User>) -> CfpResult<Template> {
+    let event = find_event(&conn, &slug)?;
+    render_schedule(&conn, user, &event)
+}
+
+/// Shows the public schedule of the default event.
+#[get("/schedule")]
+fn default_schedule(conn: DbConn, user: Option<User>) -> CfpResult<Template> {
+    let event = Event::get_default(&conn)?;
+    render_schedule(&conn, user, &event)
+}
+
+/// Exports the public schedule of an event as an iCalendar file.
+#[get("/e/<slug>/schedule.ics")]
+fn schedule_ics(slug: String, conn: DbConn) -> CfpResult<Content<String>> {
+    let event = find_event(&conn, &slug)?;
+    let schedule = Schedule::get_public(&conn, &event)?;
+    Ok(Content(ContentType::Calendar, to_ics(&schedule)))
+}
+
+/// Exports the public schedule of the default event as an iCalendar file.
+#[get("/schedule.ics")]
+fn default_schedule_ics(conn: DbConn) -> CfpResult<Content<String>> {
+    let schedule = Schedule::get_public(&conn, &Event::get_default(&conn)?)?;
+    Ok(Content(ContentType::Calendar, to_ics(&schedule)))
+}
+
+/// The URL of the scheduler of `event`.
+fn scheduler_url(event: &Event) -> String {
+    format!("/e/{}/admin/schedule", event.slug)
+}
+
+/// Renders the scheduler of `event`, with a grid of its slots and rooms, and
+/// the talks that can be scheduled. `errors` explain why the last change was
+/// refused.
+fn render_scheduler(
+    conn: &DbConn,
+    admin: &Admin,
+    event: &Event,
+    csrf: &CsrfToken,
+    errors: &[String],
+) -> CfpResult<Template> {
+    let render = || -> Result<Template, Error> {
+        let rooms = Room::get_all(conn, event)?;
+        let slots = Slot::get_all(conn, event)?;
+        let schedule = Schedule::get_all(conn, event)?;
+        let talks: Vec<(i32, String)> = talks::table
+            .filter(talks::status.eq(TalkStatus::Approved as i32).and(talks::event.eq(event.id)))
+            .select((talks::id, talks::title))
+            .order(talks::title)
+            .load(&conn.0)?;
+
+        let grid: Vec<_> = slots
+            .iter()
+            .map(|slot| {
+                let cells: Vec<_> = rooms
+                    .iter()
+                    .map(|room| schedule.iter().find(|talk| talk.slot == slot.id && talk.room == room.id))
+                    .collect();
+                json!({ "slot": slot, "cells": cells })
+            })
+            .collect();
+        let talks: Vec<_> = talks
+            .into_iter()
+            .map(|(id, title)| {
+                let scheduled = schedule.iter().any(|talk| talk.talk == id);
+                json!({ "id": id, "title": title, "scheduled": scheduled })
+            })
+            .collect();
+
+        Ok(Template::render("scheduler", json!({
+            "user": admin.0,
+            "event": event,
+            "rooms": rooms,
+            "slots": slots,
+            "grid": grid,
+            "talks": talks,
+            "conflicts": find_conflicts(&schedule),
+            "errors": errors,
+            "csrf_token": csrf,
+        })))
+    };
+    render().map_err(CfpError::from)
+}
+
+/// Shows the scheduler of an event.
+#[get("/e/<slug>/admin/schedule")]
+fn scheduler(slug: String, conn: DbConn, admin: Admin, csrf: CsrfToken) -> CfpResult<Template> {
+    let event = find_event(&conn, &slug)?;
+    render_scheduler(&conn, &admin, &event, &csrf, &[])
+}
+
+/// Sends organizers to the scheduler of the default event.
+#[get("/admin/schedule")]
+fn default_scheduler(conn: DbConn, _admin: Admin) -> CfpResult<Redirect> {
+    let event = Event::get_default(&conn)?;
+    Ok(Redirect::to(scheduler_url(&event)))
+}
+
+/// A struct containing a talk and where and when to schedule it.
+#[derive(FromForm)]
+struct Placement {
+    talk: i32,
+    room: i32,
+    slot: i32,
+}
+
+/// Schedules (or moves) a talk of an event. If it can't be scheduled there,
+/// re-renders the scheduler with the reasons instead.
+#[post("/e/<slug>/admin/schedule", data = "<placement>")]
+fn add_to_schedule(
+    slug: String,
+    placement: LenientForm<Placement>,
+    conn: DbConn,
+    admin: Admin,
+    csrf: CsrfToken,
+) -> CfpResult<SubmitResponse> {
+    let event = find_event(&conn, &slug)?;
+    let conflicts = match Schedule::add(&conn, &admin, &event, placement.talk, placement.room, placement.slot) {
+        Ok(conflicts) => conflicts,
+        Err(Error::NotFound) => return Err(Status::NotFound.into()),
+        Err(e) => return Err(e.into()),
+    };
+    if !conflicts.is_empty() {
+        return Ok(SubmitResponse::Invalid(render_scheduler(&conn, &admin, &event, &csrf, &conflicts)?));
+    }
+    info!("{} scheduled talk {} in room {}, slot {}", admin.0.login, placement.talk, placement.room, placement.slot);
+    Ok(SubmitResponse::Redirect(Redirect::to(scheduler_url(&event))))
+}
+
+/// Removes a talk from the schedule of an event.
+#[delete("/e/<slug>/admin/schedule/<talk>")]
+fn remove_from_schedule(slug: String, talk: i32, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let event = find_event(&conn, &slug)?;
+    if !Schedule::remove(&conn, &admin, &event, talk)? {
+        return Err(Status::NotFound.into());
+    }
+    Ok(Redirect::to(scheduler_url(&event)))
+}
+
+/// A struct containing the name of a new room.
+#[derive(FromForm)]
+struct NewRoomForm {
+    name: String,
+}
+
+/// Adds a room to an event. Room names must be unique within the event.
+#[post("/e/<slug>/admin/schedule/rooms", data = "<room>")]
+fn add_room(slug: String, room: LenientForm<NewRoomForm>, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let event = find_event(&conn, &slug)?;
+    let name = room.name.trim();
+    if name.is_empty() {
+        return Err(Status::UnprocessableEntity.into());
+    }
+    match Room::create(&conn, &admin, &event, name) {
+        Ok(()) => Ok(Redirect::to(scheduler_url(&event))),
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Err(Status::Conflict.into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Removes a room of an event, which must not have any talks scheduled in it.
+#[delete("/e/<slug>/admin/schedule/rooms/<id>")]
+fn delete_room(slug: String, id: i32, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let event = find_event(&conn, &slug)?;
+    match Room::delete(&conn, &admin, &event, id)? {
+        RemoveOutcome::Removed => Ok(Redirect::to(scheduler_url(&event))),
+        RemoveOutcome::NotFound => Err(Status::NotFound.into()),
+        RemoveOutcome::InUse => Err(CfpError::new(Status::Conflict, "Talks are scheduled in this room.")),
+    }
+}
+
+/// A struct containing the start and end of a new slot.
+#[derive(FromForm)]
+struct NewSlotForm {
+    starts_at: FormTimestamp,
+    ends_at: FormTimestamp,
+}
+
+/// Adds a slot to an event.
+#[post("/e/<slug>/admin/schedule/slots", data = "<slot>")]
+fn add_slot(slug: String, slot: LenientForm<NewSlotForm>, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let event = find_event(&conn, &slug)?;
+    let (starts_at, ends_at) = (slot.starts_at.0.naive_utc(), slot.ends_at.0.naive_utc());
+    if starts_at >= ends_at {
+        return Err(Status::UnprocessableEntity.into());
+    }
+    Slot::create(&conn, &admin, &event, starts_at, ends_at)?;
+    Ok(Redirect::to(scheduler_url(&event)))
+}
+
+/// Removes a slot of an event, which must not have any talks scheduled in it.
+#[delete("/e/<slug>/admin/schedule/slots/<id>")]
+fn delete_slot(slug: String, id: i32, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let event = find_event(&conn, &slug)?;
+    match Slot::delete(&conn, &admin, &event, id)? {
+        RemoveOutcome::Removed => Ok(Redirect::to(scheduler_url(&event))),
+        RemoveOutcome::NotFound => Err(Status::NotFound.into()),
+        RemoveOutcome::InUse => Err(CfpError::new(Status::Conflict, "Talks are scheduled in this slot.")),
+    }
+}
+
+/// Returns a fairing that mounts the public schedules and the scheduler.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Schedule", |rocket| {
+        Ok(rocket.mount("/", routes![
+            public_schedule,
+            default_schedule,
+            schedule_ics,
+            default_schedule_ics,
+            scheduler,
+            default_scheduler,
+            add_to_schedule,
+            remove_from_schedule,
+            add_room,
+            delete_room,
+            add_slot,
+            delete_slot,
+        ]))
+    })
+}
@@ -1,3 +1,147 @@
+table! {
+    admin_events (id) {
+        id -> Integer,
+        actor -> Integer,
+        action -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    api_tokens (id) {
+        id -> Integer,
+        user -> Integer,
+        name -> Text,
+        token_hash -> Text,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    event_admins (event, user) {
+        event -> Integer,
+        user -> Integer,
+    }
+}
+
+table! {
+    events (id) {
+        id -> Integer,
+        slug -> Text,
+        name -> Text,
+        cfp_opens -> Nullable<Timestamp>,
+        cfp_closes -> Nullable<Timestamp>,
+        blind_review -> Bool,
+    }
+}
+
+table! {
+    local_credentials (user) {
+        user -> Integer,
+        password_hash -> Text,
+    }
+}
+
+table! {
+    login_attempts (login, ip) {
+        login -> Text,
+        ip -> Text,
+        failed_attempts -> Integer,
+        locked_until -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    password_resets (id) {
+        id -> Integer,
+        user -> Integer,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    profiles (user) {
+        user -> Integer,
+        bio -> Text,
+        pronouns -> Text,
+        company -> Text,
+        website -> Text,
+        twitter -> Text,
+        github -> Text,
+        avatar -> Nullable<Text>,
+    }
+}
+
+table! {
+    reviews (id) {
+        id -> Integer,
+        reviewer -> Integer,
+        talk -> Integer,
+        score -> Integer,
+        comments -> Text,
+    }
+}
+
+table! {
+    rooms (id) {
+        id -> Integer,
+        name -> Text,
+        event -> Integer,
+    }
+}
+
+table! {
+    schedule_entries (id) {
+        id -> Integer,
+        talk -> Integer,
+        room -> Integer,
+        slot -> Integer,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Integer,
+        user -> Integer,
+        token_hash -> Text,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+        user_agent -> Text,
+        ip -> Text,
+    }
+}
+
+table! {
+    slots (id) {
+        id -> Integer,
+        starts_at -> Timestamp,
+        ends_at -> Timestamp,
+        event -> Integer,
+    }
+}
+
+table! {
+    talk_events (id) {
+        id -> Integer,
+        talk -> Integer,
+        actor -> Integer,
+        old_status -> Nullable<Integer>,
+        new_status -> Integer,
+        created_at -> Timestamp,
+        note -> Nullable<Text>,
+    }
+}
+
+table! {
+    talk_speakers (talk, user) {
+        talk -> Integer,
+        user -> Integer,
+        confirmed -> Bool,
+    }
+}
+
 table! {
     talks (id) {
         id -> Integer,
@@ -5,19 +149,96 @@ table! {
         title -> Text,
         status -> Integer,
         description -> Text,
+        format -> Integer,
+        duration -> Integer,
+        level -> Integer,
+        outline -> Text,
+        notes -> Text,
+        tags -> Text,
+        submitted_at -> Timestamp,
+        event -> Integer,
+        resubmitted_from -> Nullable<Integer>,
+    }
+}
+
+table! {
+    talks_fts (rowid) {
+        rowid -> Integer,
+        title -> Text,
+        description -> Text,
+    }
+}
+
+table! {
+    user_identities (id) {
+        id -> Integer,
+        user -> Integer,
+        provider -> Text,
+        subject -> Text,
+    }
+}
+
+table! {
+    user_roles (user, role) {
+        user -> Integer,
+        role -> Integer,
     }
 }
 
 table! {
     users (id) {
         id -> Integer,
-        github_id -> Text,
+        login -> Text,
         email -> Text,
         name -> Text,
-        is_admin -> Bool,
+        email_confirmed -> Bool,
+        disabled -> Bool,
     }
 }
 
+joinable!(admin_events -> users (actor));
+joinable!(api_tokens -> users (user));
+joinable!(event_admins -> events (event));
+joinable!(event_admins -> users (user));
+joinable!(local_credentials -> users (user));
+joinable!(password_resets -> users (user));
+joinable!(profiles -> users (user));
+joinable!(reviews -> talks (talk));
+joinable!(reviews -> users (reviewer));
+joinable!(rooms -> events (event));
+joinable!(schedule_entries -> rooms (room));
+joinable!(schedule_entries -> slots (slot));
+joinable!(schedule_entries -> talks (talk));
+joinable!(sessions -> users (user));
+joinable!(slots -> events (event));
+joinable!(talk_events -> talks (talk));
+joinable!(talk_events -> users (actor));
+joinable!(talk_speakers -> talks (talk));
+joinable!(talk_speakers -> users (user));
+joinable!(talks -> events (event));
 joinable!(talks -> users (presenter));
+joinable!(user_identities -> users (user));
+joinable!(user_roles -> users (user));
 
-allow_tables_to_appear_in_same_query!(talks, users,);
+allow_tables_to_appear_in_same_query!(
+    admin_events,
+    api_tokens,
+    event_admins,
+    events,
+    local_credentials,
+    login_attempts,
+    password_resets,
+    profiles,
+    reviews,
+    rooms,
+    schedule_entries,
+    sessions,
+    slots,
+    talk_events,
+    talk_speakers,
+    talks,
+    talks_fts,
+    user_identities,
+    user_roles,
+    users,
+);
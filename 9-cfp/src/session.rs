@@ -0,0 +1,284 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel;
+use diesel::prelude::*;
+use diesel::result::Error;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use rocket::config::{self, Config, ConfigError};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::{Cookie, Cookies, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request, State};
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+
+use csrf::{self, CsrfToken};
+use error::CfpResult;
+use schema::sessions;
+use token::hash_token;
+use user::User;
+use DbConn;
+
+/// The name of the private cookie holding the session token.
+pub const SESSION_COOKIE: &str = "session";
+
+/// The longest user agent stored with a session, in characters.
+const MAX_USER_AGENT_LENGTH: usize = 255;
+
+/// How often a session's last activity is recorded, in seconds. Recording it
+/// on every request would mean a database write per request.
+const LAST_SEEN_PRECISION: i64 = 60;
+
+/// Generates a new random session token.
+fn generate_token() -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(40).collect()
+}
+
+/// How long sessions stay valid, read from the `session_idle_timeout` and
+/// `session_max_age` configuration parameters, in hours. Sessions end when
+/// they haven't been used for the idle timeout (a week by default), and
+/// at the latest when they reach the maximum age (30 days by default).
+pub struct SessionConfig {
+    pub idle_timeout: Duration,
+    pub max_age: Duration,
+}
+
+/// Reads the number of hours `name`, falling back to `default`.
+fn get_hours(config: &Config, name: &str, default: i64) -> config::Result<Duration> {
+    match config.get_int(name) {
+        Ok(hours) => Ok(Duration::hours(hours)),
+        Err(ConfigError::Missing(_)) => Ok(Duration::hours(default)),
+        Err(e) => Err(e),
+    }
+}
+
+impl SessionConfig {
+    pub fn from_config(config: &Config) -> config::Result<SessionConfig> {
+        Ok(SessionConfig {
+            idle_timeout: get_hours(config, "session_idle_timeout", 7 * 24)?,
+            max_age: get_hours(config, "session_max_age", 30 * 24)?,
+        })
+    }
+
+    /// Returns whether a session created at `created_at` and last used at
+    /// `last_seen_at` has expired by `now`.
+    fn is_expired(&self, created_at: NaiveDateTime, last_seen_at: NaiveDateTime, now: NaiveDateTime) -> bool {
+        now - last_seen_at > self.idle_timeout || now - created_at > self.max_age
+    }
+}
+
+/// The browser a user logs in with, as recorded with their session. The IP
+/// address is the one the connection comes from: headers like `X-Real-IP`
+/// can be sent by anyone, so they aren't trusted.
+pub struct ClientInfo {
+    pub user_agent: String,
+    pub ip: String,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ClientInfo {
+    type Error = ();
+
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let user_agent = req.headers().get_one("User-Agent").unwrap_or("");
+        Outcome::Success(ClientInfo {
+            user_agent: user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect(),
+            ip: req.remote().map(|addr| addr.ip().to_string()).unwrap_or_default(),
+        })
+    }
+}
+
+/// Session data retrieved from the database. The token itself is not
+/// stored, only its hash.
+#[derive(Queryable, Serialize)]
+pub struct Session {
+    pub id: i32,
+    pub user: i32,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub user_agent: String,
+    pub ip: String,
+}
+
+/// New session data to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "sessions"]
+struct NewSession<'a> {
+    pub user: i32,
+    pub token_hash: &'a str,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub user_agent: &'a str,
+    pub ip: &'a str,
+}
+
+impl Session {
+    /// Starts a session for the user with the given `id`, logging in from
+    /// `client`. Returns the session token, which is only stored in the
+    /// session cookie.
+    pub fn create(conn: &DbConn, id: i32, client: &ClientInfo) -> Result<String, Error> {
+        let token = generate_token();
+        let now = Utc::now().naive_utc();
+        diesel::insert_into(sessions::table)
+            .values(NewSession {
+                user: id,
+                token_hash: &hash_token(&token),
+                created_at: now,
+                last_seen_at: now,
+                user_agent: &client.user_agent,
+                ip: &client.ip,
+            })
+            .execute(&conn.0)?;
+
+        Ok(token)
+    }
+
+    /// Retrieve the sessions of `user`, most recently used first
+    pub fn get_for_user(conn: &DbConn, user: &User) -> Result<Vec<Session>, Error> {
+        sessions::table
+            .filter(sessions::user.eq(user.id))
+            .order((sessions::last_seen_at.desc(), sessions::id.desc()))
+            .load(&conn.0)
+    }
+
+    /// Looks up the user owning the session with the given `token`, and
+    /// records that the session was used. Expired sessions are removed, and
+    /// yield no user.
+    pub fn authenticate(conn: &DbConn, config: &SessionConfig, token: &str) -> Result<Option<User>, Error> {
+        let hash = hash_token(token);
+        let session: Option<Session> = sessions::table
+            .filter(sessions::token_hash.eq(&hash))
+            .first(&conn.0)
+            .optional()?;
+        let session = match session {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+
+        let now = Utc::now().naive_utc();
+        if config.is_expired(session.created_at, session.last_seen_at, now) {
+            diesel::delete(sessions::table.filter(sessions::id.eq(session.id))).execute(&conn.0)?;
+            return Ok(None);
+        }
+        if now - session.last_seen_at > Duration::seconds(LAST_SEEN_PRECISION) {
+            diesel::update(sessions::table.filter(sessions::id.eq(session.id)))
+                .set(sessions::last_seen_at.eq(now))
+                .execute(&conn.0)?;
+        }
+        User::get(conn, session.user).map(Some)
+    }
+
+    /// Ends the session with the given `token`, e.g. when logging out.
+    pub fn end(conn: &DbConn, token: &str) -> Result<(), Error> {
+        diesel::delete(sessions::table.filter(sessions::token_hash.eq(hash_token(token))))
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Revokes the session with the given `id`, which must belong to `user`.
+    /// Returns whether a session was revoked.
+    pub fn revoke(conn: &DbConn, user: &User, id: i32) -> Result<bool, Error> {
+        let deleted = diesel::delete(
+            sessions::table.filter(sessions::id.eq(id).and(sessions::user.eq(user.id))),
+        )
+        .execute(&conn.0)?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Revokes all sessions of the user with the given `id`, logging them out
+    /// everywhere.
+    pub fn revoke_all(conn: &DbConn, id: i32) -> Result<(), Error> {
+        diesel::delete(sessions::table.filter(sessions::user.eq(id))).execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Removes all expired sessions, of any user.
+    pub fn purge_expired(conn: &DbConn, config: &SessionConfig) -> Result<(), Error> {
+        let now = Utc::now().naive_utc();
+        diesel::delete(sessions::table.filter(
+            sessions::last_seen_at
+                .lt(now - config.idle_timeout)
+                .or(sessions::created_at.lt(now - config.max_age)),
+        ))
+        .execute(&conn.0)?;
+
+        Ok(())
+    }
+}
+
+/// Retrieves the user logged in with the session cookie of `req`, if the
+/// session is still valid.
+pub fn current_user(req: &Request, conn: &DbConn) -> Option<User> {
+    let config = req.guard::<State<SessionConfig>>().succeeded()?;
+    let token = req.cookies().get_private(SESSION_COOKIE)?.value().to_string();
+    match Session::authenticate(conn, &config, &token) {
+        Ok(user) => user,
+        Err(e) => {
+            error!("Failed to look up a session: {}", e);
+            None
+        }
+    }
+}
+
+/// Lists the current user's active sessions, marking the one this request
+/// was made with.
+#[get("/sessions")]
+fn list_sessions(conn: DbConn, user: User, csrf: CsrfToken, cookies: Cookies) -> CfpResult<Template> {
+    let current = cookies.get_private(SESSION_COOKIE).map(|cookie| hash_token(cookie.value()));
+    let sessions: Vec<_> = Session::get_for_user(&conn, &user)?
+        .into_iter()
+        .map(|session| {
+            let is_current = current.as_ref() == Some(&session.token_hash);
+            json!({ "session": session, "current": is_current })
+        })
+        .collect();
+    Ok(Template::render("sessions", json!({
+        "user": user,
+        "sessions": sessions,
+        "csrf_token": csrf,
+    })))
+}
+
+/// Revokes one of the current user's sessions, logging out the browser that
+/// uses it.
+#[delete("/sessions/<id>")]
+fn revoke_session(id: i32, conn: DbConn, user: User) -> CfpResult<Redirect> {
+    if !Session::revoke(&conn, &user, id)? {
+        return Err(Status::NotFound.into());
+    }
+    Ok(Redirect::to("/sessions"))
+}
+
+/// Revokes all of the current user's sessions, including the current one,
+/// and the CSRF token of this browser, like logging out does.
+#[delete("/sessions")]
+fn revoke_all_sessions(conn: DbConn, user: User, mut cookies: Cookies) -> CfpResult<Redirect> {
+    Session::revoke_all(&conn, user.id)?;
+    cookies.remove_private(Cookie::named(SESSION_COOKIE));
+    csrf::rotate(&mut cookies);
+    info!("{} logged out everywhere", user.login);
+    Ok(Redirect::to("/"))
+}
+
+/// Returns a fairing that sets up sessions:
+///
+/// * Reads the session timeouts from the configuration.
+/// * Mounts the session management routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Sessions", |rocket| {
+        match SessionConfig::from_config(rocket.config()) {
+            Ok(config) => Ok(rocket
+                .manage(config)
+                .mount("/", routes![list_sessions, revoke_session, revoke_all_sessions])
+            ),
+            Err(e) => {
+                error!("Invalid session configuration: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
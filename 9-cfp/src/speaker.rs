@@ -0,0 +1,247 @@
+use diesel;
+use diesel::prelude::*;
+use diesel::result::Error;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::request::{LenientForm, State};
+use rocket::response::Redirect;
+
+use error::CfpResult;
+use mail::Mail;
+use schema::{talk_speakers, talks, users};
+use talk::Talk;
+use user::User;
+use DbConn;
+
+/// A co-speaker of a talk, or a user who was invited to become one and
+/// hasn't accepted yet
+#[derive(Queryable, Serialize)]
+pub struct CoSpeaker {
+    pub user: i32,
+    pub login: String,
+    pub name: String,
+    pub confirmed: bool,
+}
+
+/// An invitation to co-present a talk, as shown to the invitee
+#[derive(Queryable, Serialize)]
+pub struct Invitation {
+    pub talk: i32,
+    pub title: String,
+    /// The name of the primary speaker, who sent the invitation
+    pub presenter_name: String,
+}
+
+/// A co-speaker to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "talk_speakers"]
+struct NewCoSpeaker {
+    pub talk: i32,
+    pub user: i32,
+    pub confirmed: bool,
+}
+
+/// What happened when a user was invited to co-present a talk.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InviteOutcome {
+    Invited,
+    /// The user was already invited, or already co-presents the talk.
+    AlreadyInvited,
+    /// The user is the primary speaker of the talk.
+    IsPresenter,
+}
+
+impl CoSpeaker {
+    /// Retrieve the co-speakers of the talk with the given `id`, including
+    /// those who haven't accepted their invitation yet
+    pub fn get_for_talk(conn: &DbConn, id: i32) -> Result<Vec<CoSpeaker>, Error> {
+        talk_speakers::table
+            .inner_join(users::table)
+            .filter(talk_speakers::talk.eq(id))
+            .select((talk_speakers::user, users::login, users::name, talk_speakers::confirmed))
+            .order(users::login)
+            .load(&conn.0)
+    }
+
+    /// Retrieve everyone who presents `talk`: the primary speaker first, and
+    /// then the confirmed co-speakers.
+    pub fn get_presenters(conn: &DbConn, talk: &Talk) -> Result<Vec<User>, Error> {
+        let co_speakers: Vec<i32> = talk_speakers::table
+            .filter(talk_speakers::talk.eq(talk.id).and(talk_speakers::confirmed.eq(true)))
+            .select(talk_speakers::user)
+            .order(talk_speakers::user)
+            .load(&conn.0)?;
+
+        ::std::iter::once(talk.presenter)
+            .chain(co_speakers)
+            .map(|id| User::get(conn, id))
+            .collect()
+    }
+
+    /// Invites `invitee` to co-present `talk`, whose primary speaker must be
+    /// `presenter`.
+    pub fn invite(conn: &DbConn, presenter: &User, talk: &Talk, invitee: &User) -> Result<InviteOutcome, Error> {
+        if talk.presenter != presenter.id {
+            return Err(Error::NotFound);
+        }
+        if invitee.id == presenter.id {
+            return Ok(InviteOutcome::IsPresenter);
+        }
+
+        conn.0.transaction::<_, Error, _>(|| {
+            let existing: i64 = talk_speakers::table
+                .filter(talk_speakers::talk.eq(talk.id).and(talk_speakers::user.eq(invitee.id)))
+                .count()
+                .get_result(&conn.0)?;
+            if existing > 0 {
+                return Ok(InviteOutcome::AlreadyInvited);
+            }
+
+            diesel::insert_into(talk_speakers::table)
+                .values(NewCoSpeaker { talk: talk.id, user: invitee.id, confirmed: false })
+                .execute(&conn.0)?;
+            Ok(InviteOutcome::Invited)
+        })
+    }
+
+    /// Removes the user with the given `id` from the co-speakers of `talk`,
+    /// or withdraws their invitation. Only the primary speaker can remove
+    /// others, but co-speakers can always remove themselves. Returns whether
+    /// the user was a co-speaker or invited.
+    pub fn remove(conn: &DbConn, user: &User, talk: &Talk, id: i32) -> Result<bool, Error> {
+        if talk.presenter != user.id && id != user.id {
+            return Ok(false);
+        }
+        let deleted = diesel::delete(
+            talk_speakers::table.filter(talk_speakers::talk.eq(talk.id).and(talk_speakers::user.eq(id))),
+        )
+        .execute(&conn.0)?;
+
+        Ok(deleted > 0)
+    }
+}
+
+impl Invitation {
+    /// Retrieve the invitations `user` hasn't accepted or declined yet
+    pub fn get_for_user(conn: &DbConn, user: &User) -> Result<Vec<Invitation>, Error> {
+        talk_speakers::table
+            .inner_join(talks::table.inner_join(users::table))
+            .filter(talk_speakers::user.eq(user.id).and(talk_speakers::confirmed.eq(false)))
+            .select((talks::id, talks::title, users::name))
+            .order(talks::id)
+            .load(&conn.0)
+    }
+
+    /// Accepts `user`'s invitation to co-present the talk with the given
+    /// `id`. Returns whether there was such an invitation.
+    pub fn accept(conn: &DbConn, user: &User, id: i32) -> Result<bool, Error> {
+        let updated = diesel::update(talk_speakers::table.filter(
+            talk_speakers::talk.eq(id)
+                .and(talk_speakers::user.eq(user.id))
+                .and(talk_speakers::confirmed.eq(false)),
+        ))
+        .set(talk_speakers::confirmed.eq(true))
+        .execute(&conn.0)?;
+
+        Ok(updated > 0)
+    }
+
+    /// Declines `user`'s invitation to co-present the talk with the given
+    /// `id`. Returns whether there was such an invitation.
+    pub fn decline(conn: &DbConn, user: &User, id: i32) -> Result<bool, Error> {
+        let deleted = diesel::delete(talk_speakers::table.filter(
+            talk_speakers::talk.eq(id)
+                .and(talk_speakers::user.eq(user.id))
+                .and(talk_speakers::confirmed.eq(false)),
+        ))
+        .execute(&conn.0)?;
+
+        Ok(deleted > 0)
+    }
+}
+
+/// Retrieves the talk with the given `id`, making sure that `user` presents
+/// it.
+fn presented_talk(conn: &DbConn, user: &User, id: i32) -> CfpResult<Talk> {
+    if !Talk::is_presented_by(conn, user, id)? {
+        return Err(Status::NotFound.into());
+    }
+    Ok(Talk::get_one(conn, user, id)?.ok_or(Status::NotFound)?)
+}
+
+/// A struct containing the login of a user to invite.
+#[derive(FromForm)]
+struct NewInvitation {
+    login: String,
+}
+
+/// Invites a registered user to co-present a talk. Only the primary speaker
+/// can invite others. The invitee is emailed about the invitation.
+#[post("/talks/<id>/speakers", data = "<invitation>")]
+fn invite_speaker(
+    id: i32,
+    invitation: LenientForm<NewInvitation>,
+    conn: DbConn,
+    user: User,
+    mail: State<Mail>,
+) -> CfpResult<Redirect> {
+    let talk = presented_talk(&conn, &user, id)?;
+    if talk.presenter != user.id {
+        return Err(Status::Forbidden.into());
+    }
+    let invitee = User::get_by_login(&conn, invitation.login.trim())?.ok_or(Status::NotFound)?;
+    match CoSpeaker::invite(&conn, &user, &talk, &invitee)? {
+        InviteOutcome::Invited => {}
+        InviteOutcome::AlreadyInvited | InviteOutcome::IsPresenter => return Err(Status::Conflict.into()),
+    }
+    mail.send(&invitee, "invitation", &json!({ "talk": talk, "presenter": user }));
+    Ok(Redirect::to(format!("/talks/{}", id)))
+}
+
+/// A struct containing the ID of a co-speaker to remove.
+#[derive(FromForm)]
+struct SpeakerRemoval {
+    user: i32,
+}
+
+/// Removes a co-speaker from a talk, or withdraws an invitation. Co-speakers
+/// can also use this to step down from a talk.
+#[delete("/talks/<id>/speakers", data = "<removal>")]
+fn remove_speaker(id: i32, removal: LenientForm<SpeakerRemoval>, conn: DbConn, user: User) -> CfpResult<Redirect> {
+    let talk = presented_talk(&conn, &user, id)?;
+    if !CoSpeaker::remove(&conn, &user, &talk, removal.user)? {
+        return Err(Status::NotFound.into());
+    }
+    if removal.user == user.id {
+        Ok(Redirect::to("/"))
+    } else {
+        Ok(Redirect::to(format!("/talks/{}", id)))
+    }
+}
+
+/// Accepts an invitation to co-present a talk. Responds with a Redirect to
+/// the talk, which the user now presents.
+#[post("/invitations/<talk>")]
+fn accept_invitation(talk: i32, conn: DbConn, user: User) -> CfpResult<Redirect> {
+    if !Invitation::accept(&conn, &user, talk)? {
+        return Err(Status::NotFound.into());
+    }
+    info!("{} now co-presents talk {}", user.login, talk);
+    Ok(Redirect::to(format!("/talks/{}", talk)))
+}
+
+/// Declines an invitation to co-present a talk.
+#[delete("/invitations/<talk>")]
+fn decline_invitation(talk: i32, conn: DbConn, user: User) -> CfpResult<Redirect> {
+    if !Invitation::decline(&conn, &user, talk)? {
+        return Err(Status::NotFound.into());
+    }
+    Ok(Redirect::to("/"))
+}
+
+/// Returns a fairing that mounts the co-speaker routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("Co-speakers", |rocket| {
+        Ok(rocket.mount("/", routes![invite_speaker, remove_speaker, accept_invitation, decline_invitation]))
+    })
+}
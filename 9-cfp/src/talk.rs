@@ -1,67 +1,340 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
 use std::io;
 
+use chrono::{NaiveDateTime, Utc};
 use diesel;
 use diesel::backend::Backend;
 use diesel::deserialize::{self, FromSql};
+use diesel::dsl::{exists, sql};
 use diesel::prelude::*;
 use diesel::result::Error;
 use diesel::serialize::{self, Output, ToSql};
-use diesel::sql_types::Integer;
+use diesel::sql_types::{Bool, Double, Integer, Nullable, Text};
+use diesel::sqlite::Sqlite;
 use rocket::FromFormValue;
+use serde::{Deserialize, Deserializer};
 
-use {Admin, DbConn};
-use schema::talks;
+use audit::TalkEvent;
+use error::InvalidValue;
+use event::Event;
+use role::{ProgramChair, Reviewer};
+use schema::{events, talk_speakers, talks, talks_fts, users};
 use user::User;
+use DbConn;
 
-/// Talk status: Pending, Approved, or Rejected.
+/// Talk status: Pending, Approved, Rejected, or Withdrawn.
 /// Derives lots of traits so it can be used via serde (as a string)
 /// and in diesel (as an integer)
 #[repr(i32)]
-#[derive(Copy, Clone, Debug, FromSqlRow, AsExpression, Serialize, FromFormValue)]
+#[derive(Copy, Clone, Debug, PartialEq, FromSqlRow, AsExpression, Serialize, Deserialize, FromFormValue)]
 #[sql_type = "Integer"]
 pub enum TalkStatus {
     Pending = 0,
     Approved = 1,
     Rejected = 2,
+    Withdrawn = 3,
 }
 
-impl From<i32> for TalkStatus {
-    fn from(i: i32) -> TalkStatus {
+impl TryFrom<i32> for TalkStatus {
+    type Error = InvalidValue;
+
+    fn try_from(i: i32) -> Result<TalkStatus, InvalidValue> {
+        match i {
+            0 => Ok(TalkStatus::Pending),
+            1 => Ok(TalkStatus::Approved),
+            2 => Ok(TalkStatus::Rejected),
+            3 => Ok(TalkStatus::Withdrawn),
+            _ => Err(InvalidValue { type_name: "TalkStatus", value: i }),
+        }
+    }
+}
+
+impl TalkStatus {
+    /// Whether the presenter may still edit a talk with this status. Talks
+    /// are locked as soon as they leave `Pending`.
+    pub fn is_editable(self) -> bool {
+        match self {
+            TalkStatus::Pending => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the presenter may withdraw a talk with this status.
+    pub fn is_withdrawable(self) -> bool {
+        match self {
+            TalkStatus::Pending | TalkStatus::Approved => true,
+            TalkStatus::Rejected | TalkStatus::Withdrawn => false,
+        }
+    }
+}
+
+integer_sql_enum!(TalkStatus);
+
+/// Talk format: a regular Talk, a Workshop, or a Lightning talk.
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromSqlRow, AsExpression, Serialize, Deserialize, FromFormValue)]
+#[sql_type = "Integer"]
+pub enum TalkFormat {
+    Talk = 0,
+    Workshop = 1,
+    Lightning = 2,
+}
+
+impl TryFrom<i32> for TalkFormat {
+    type Error = InvalidValue;
+
+    fn try_from(i: i32) -> Result<TalkFormat, InvalidValue> {
         match i {
-            0 => TalkStatus::Pending,
-            1 => TalkStatus::Approved,
-            2 => TalkStatus::Rejected,
-            _ => panic!("Invalid TalkStatus value: {}", i),
+            0 => Ok(TalkFormat::Talk),
+            1 => Ok(TalkFormat::Workshop),
+            2 => Ok(TalkFormat::Lightning),
+            _ => Err(InvalidValue { type_name: "TalkFormat", value: i }),
+        }
+    }
+}
+
+impl TalkFormat {
+    /// The shortest and longest allowed duration of this format, in minutes.
+    pub fn duration_range(self) -> (i32, i32) {
+        match self {
+            TalkFormat::Talk => (15, 60),
+            TalkFormat::Workshop => (60, 240),
+            TalkFormat::Lightning => (5, 10),
+        }
+    }
+}
+
+integer_sql_enum!(TalkFormat);
+
+/// The audience level a talk is aimed at.
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromSqlRow, AsExpression, Serialize, Deserialize, FromFormValue)]
+#[sql_type = "Integer"]
+pub enum AudienceLevel {
+    Beginner = 0,
+    Intermediate = 1,
+    Advanced = 2,
+}
+
+impl TryFrom<i32> for AudienceLevel {
+    type Error = InvalidValue;
+
+    fn try_from(i: i32) -> Result<AudienceLevel, InvalidValue> {
+        match i {
+            0 => Ok(AudienceLevel::Beginner),
+            1 => Ok(AudienceLevel::Intermediate),
+            2 => Ok(AudienceLevel::Advanced),
+            _ => Err(InvalidValue { type_name: "AudienceLevel", value: i }),
+        }
+    }
+}
+
+integer_sql_enum!(AudienceLevel);
+
+/// Free-form tags attached to a talk. Tags are trimmed, lowercased and
+/// deduplicated, and are stored as a single comma-separated string.
+#[derive(Clone, Debug, Default, FromSqlRow, AsExpression, Serialize)]
+#[sql_type = "Text"]
+pub struct Tags(pub Vec<String>);
+
+impl Tags {
+    /// Normalizes the given tags, ignoring empty entries.
+    pub fn new<I, S>(input: I) -> Tags
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut tags: Vec<String> = vec![];
+        for tag in input {
+            let tag = tag.as_ref().trim().to_lowercase();
+            if !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+            }
         }
+        Tags(tags)
+    }
+
+    /// Parses a comma-separated list of tags, ignoring empty entries.
+    pub fn parse(input: &str) -> Tags {
+        Tags::new(input.split(','))
+    }
+}
+
+/// Tags are deserialized from a list of strings, normalizing them on the way.
+impl<'de> Deserialize<'de> for Tags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tags, D::Error> {
+        Vec::<String>::deserialize(deserializer).map(Tags::new)
+    }
+}
+
+impl fmt::Display for Tags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(","))
     }
 }
 
-impl<DB: Backend> FromSql<Integer, DB> for TalkStatus
+impl<DB: Backend> FromSql<Text, DB> for Tags
 where
-    i32: FromSql<Integer, DB>,
+    String: FromSql<Text, DB>,
 {
     fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
-        Ok(i32::from_sql(bytes)?.into())
+        Ok(Tags::parse(&String::from_sql(bytes)?))
     }
 }
 
-impl<DB: Backend> ToSql<Integer, DB> for TalkStatus
+impl<DB: Backend> ToSql<Text, DB> for Tags
 where
-    i32: ToSql<Integer, DB>,
+    String: ToSql<Text, DB>,
 {
     fn to_sql<W: io::Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
-        i32::to_sql(&(*self as i32), out)
+        String::to_sql(&self.to_string(), out)
     }
 }
 
-/// Talk data retrieved from the database
+// SQLite has no `RETURNING` clause, so the ID of an inserted row has to be
+// asked for separately (on the same connection).
+no_arg_sql_function!(last_insert_rowid, Integer, "Returns the ID of the last inserted row");
+
+/// Talk data retrieved from the database. The presenter is never serialized
+/// along with the talk, since not everyone may know who presents it; see
+/// `PresenterVisibility`.
 #[derive(Queryable, Serialize)]
 pub struct Talk {
-    id: i32,
-    presenter: i32,
-    title: String,
-    status: TalkStatus,
-    description: String,
+    pub id: i32,
+    #[serde(skip_serializing)]
+    pub presenter: i32,
+    pub title: String,
+    pub status: TalkStatus,
+    pub description: String,
+    pub format: TalkFormat,
+    pub duration: i32,
+    pub level: AudienceLevel,
+    pub outline: String,
+    pub notes: String,
+    pub tags: Tags,
+    pub submitted_at: NaiveDateTime,
+    /// The ID of the event the talk was submitted to
+    pub event: i32,
+    /// The ID of the talk this one is a copy of, if it was resubmitted from
+    /// another event
+    pub resubmitted_from: Option<i32>,
+}
+
+/// A talk as shown to a particular user, along with its presenter if the user
+/// may know who that is.
+#[derive(Serialize)]
+pub struct TalkView {
+    #[serde(flatten)]
+    pub talk: Talk,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presenter: Option<i32>,
+}
+
+/// Decides whether a user may know who presents a talk. Presenters know their
+/// own talks, and organizers know every talk. Program chairs know the
+/// presenters of talks unless the talk's event is under blind review, and
+/// reviewers never do.
+pub struct PresenterVisibility {
+    user: i32,
+    organizer: bool,
+    program_chair: bool,
+    /// The talks the user co-presents
+    co_presented: HashSet<i32>,
+    /// The events under blind review
+    blind_events: Vec<i32>,
+}
+
+impl PresenterVisibility {
+    /// Looks up what `user` may know.
+    pub fn for_user(conn: &DbConn, user: &User) -> Result<PresenterVisibility, Error> {
+        let co_presented = talk_speakers::table
+            .filter(talk_speakers::user.eq(user.id).and(talk_speakers::confirmed.eq(true)))
+            .select(talk_speakers::talk)
+            .load::<i32>(&conn.0)?
+            .into_iter()
+            .collect();
+        let blind_events = events::table
+            .filter(events::blind_review.eq(true))
+            .select(events::id)
+            .load(&conn.0)?;
+
+        Ok(PresenterVisibility {
+            user: user.id,
+            organizer: user.roles.organizer,
+            program_chair: user.roles.program_chair,
+            co_presented,
+            blind_events,
+        })
+    }
+
+    /// Whether the user may know who presents `talk`.
+    pub fn shows_presenter(&self, talk: &Talk) -> bool {
+        talk.presenter == self.user
+            || self.co_presented.contains(&talk.id)
+            || self.organizer
+            || (self.program_chair && !self.blind_events.contains(&talk.event))
+    }
+
+    /// Returns `talk` as the user may see it.
+    pub fn view(&self, talk: Talk) -> TalkView {
+        let presenter = if self.shows_presenter(&talk) { Some(talk.presenter) } else { None };
+        TalkView { talk, presenter }
+    }
+
+    /// Returns `talks` as the user may see them.
+    pub fn view_all(&self, talks: Vec<Talk>) -> Vec<TalkView> {
+        talks.into_iter().map(|talk| self.view(talk)).collect()
+    }
+}
+
+/// The longest title a talk can have, in characters.
+pub const MAX_TITLE_LENGTH: usize = 150;
+
+/// The parts of a talk that are provided (and can be edited) by its presenter
+#[derive(AsChangeset, Deserialize)]
+#[table_name = "talks"]
+pub struct TalkDetails {
+    pub title: String,
+    pub description: String,
+    pub format: TalkFormat,
+    pub duration: i32,
+    pub level: AudienceLevel,
+    #[serde(default)]
+    pub outline: String,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Tags,
+}
+
+impl TalkDetails {
+    /// Checks the details for problems, returning a human-readable
+    /// description of each one. The details are valid if none are returned.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+
+        if self.title.trim().is_empty() {
+            errors.push("The title must not be empty.".to_string());
+        } else if self.title.chars().count() > MAX_TITLE_LENGTH {
+            errors.push(format!("The title must be at most {} characters long.", MAX_TITLE_LENGTH));
+        }
+
+        if self.description.trim().is_empty() {
+            errors.push("The description must not be empty.".to_string());
+        }
+
+        let (min, max) = self.format.duration_range();
+        if self.duration < min || self.duration > max {
+            errors.push(format!(
+                "A {:?} must last between {} and {} minutes.",
+                self.format, min, max
+            ));
+        }
+
+        errors
+    }
 }
 
 /// Talk data to be inserted into the database
@@ -72,54 +345,427 @@ struct NewTalk<'a> {
     pub title: &'a str,
     pub status: TalkStatus,
     pub description: &'a str,
+    pub format: TalkFormat,
+    pub duration: i32,
+    pub level: AudienceLevel,
+    pub outline: &'a str,
+    pub notes: &'a str,
+    pub tags: &'a Tags,
+    pub submitted_at: NaiveDateTime,
+    pub event: i32,
+}
+
+/// The outcome of resubmitting a talk to another event.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Resubmission {
+    /// The talk was copied; this is the ID of the copy.
+    Resubmitted(i32),
+    /// The user doesn't present such a talk at another event.
+    NotFound,
+    /// The talk was already resubmitted to the event.
+    AlreadySubmitted,
+}
+
+/// The outcome of setting the status of a talk.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StatusChange {
+    /// The talk's status was changed.
+    Changed,
+    /// The talk already had the status, so nothing was recorded.
+    Unchanged,
+    /// There is no such talk.
+    NotFound,
+}
+
+/// The order in which talks are listed.
+#[derive(Copy, Clone, Debug, Serialize, FromFormValue)]
+#[serde(rename_all = "snake_case")]
+pub enum TalkSort {
+    Newest,
+    Oldest,
+    #[form(value = "highest_score")]
+    HighestScore,
+    #[form(value = "lowest_score")]
+    LowestScore,
+}
+
+impl TalkSort {
+    /// The value identifying this order in a query string.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TalkSort::Newest => "newest",
+            TalkSort::Oldest => "oldest",
+            TalkSort::HighestScore => "highest_score",
+            TalkSort::LowestScore => "lowest_score",
+        }
+    }
+}
+
+impl Default for TalkSort {
+    fn default() -> TalkSort {
+        TalkSort::Newest
+    }
+}
+
+/// Restricts a list of talks to those matching all of the given criteria.
+#[derive(Default, Serialize)]
+pub struct TalkFilter {
+    /// The event the talks were submitted to
+    pub event: Option<Event>,
+    /// Words that must all appear in the title or description. Words match
+    /// any word they are a prefix of.
+    pub query: Option<String>,
+    pub status: Option<TalkStatus>,
+    pub tag: Option<String>,
+    /// Part of the login or name of the presenter. Only program chairs may
+    /// filter by presenter, since reviewers don't get to know who presents
+    /// which talk, and only organizers may do so for events under blind
+    /// review.
+    pub presenter: Option<String>,
+    pub sort: TalkSort,
+}
+
+/// The number of talks shown per page of a talk list.
+pub const PAGE_SIZE: i64 = 50;
+
+/// Turns free text into an FTS5 query that matches rows containing every
+/// word, or a word starting with it. Each word is quoted, so that FTS5 syntax
+/// in the input is searched for rather than interpreted.
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Escapes `%`, `_` and the escape character `\` itself, so that `text` is
+/// matched literally by a `LIKE` pattern with `ESCAPE '\'`.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+impl TalkFilter {
+    /// Builds a query for all talks matching the filter, ignoring the sort
+    /// order.
+    fn query<'a>(&self, visibility: &PresenterVisibility) -> talks::BoxedQuery<'a, Sqlite> {
+        let mut query = talks::table.into_boxed();
+
+        if let Some(ref event) = self.event {
+            query = query.filter(talks::event.eq(event.id));
+        }
+
+        if let Some(fts) = self.query.as_ref().and_then(|query| fts_query(query)) {
+            // Comparing an FTS5 table to a value is the same as `MATCH`ing it.
+            let matches = talks_fts::table
+                .select(talks_fts::rowid)
+                .filter(sql::<Text>("talks_fts").eq(fts));
+            query = query.filter(talks::id.eq_any(matches));
+        }
+        if let Some(status) = self.status {
+            query = query.filter(talks::status.eq(status as i32));
+        }
+        if let Some(tag) = self.tag.as_ref().and_then(|tag| Tags::new(Some(tag)).0.pop()) {
+            // Tags are stored comma-separated, without spaces.
+            query = query.filter(
+                sql::<Text>("',' || talks.tags || ','")
+                    .like(format!("%,{},%", escape_like(&tag)))
+                    .escape('\\'),
+            );
+        }
+        if let Some(presenter) = self.presenter.as_ref().filter(|_| visibility.program_chair) {
+            let presenters = users::table.select(users::id).filter(
+                users::login
+                    .eq(presenter.clone())
+                    .or(users::name.like(format!("%{}%", escape_like(presenter))).escape('\\')),
+            );
+            query = query.filter(talks::presenter.eq_any(presenters));
+            // Otherwise the results would reveal who presents talks under
+            // blind review.
+            if !visibility.organizer {
+                query = query.filter(talks::event.ne_all(visibility.blind_events.clone()));
+            }
+        }
+
+        query
+    }
+}
+
+/// Matches the talks that `user` presents, either as the primary speaker or
+/// as a confirmed co-speaker. Presenters own their talks: they can see, edit
+/// and withdraw them.
+fn presented_by(user: &User) -> Box<BoxableExpression<talks::table, Sqlite, SqlType = Bool>> {
+    let co_presented = talk_speakers::table
+        .select(talk_speakers::talk)
+        .filter(talk_speakers::user.eq(user.id).and(talk_speakers::confirmed.eq(true)));
+    Box::new(talks::presenter.eq(user.id).or(talks::id.eq_any(co_presented)))
 }
 
 impl Talk {
     /// Retrieve all talks, regardless of presenter
-    pub fn get_all(conn: &DbConn, _admin: &Admin) -> Result<Vec<Talk>, Error> {
+    pub fn get_all(conn: &DbConn, _reviewer: &Reviewer) -> Result<Vec<Talk>, Error> {
         talks::table.load(&conn.0)
     }
 
-    /// Retrieve all talks that `user` is presenting
+    /// Retrieve the talks matching `filter`, `PAGE_SIZE` at a time. Pages
+    /// start at 1. Also returns the number of matching talks in total.
+    pub fn search(
+        conn: &DbConn,
+        _reviewer: &Reviewer,
+        visibility: &PresenterVisibility,
+        filter: &TalkFilter,
+        page: i64,
+    ) -> Result<(Vec<Talk>, i64), Error> {
+        let total = filter.query(visibility).count().get_result(&conn.0)?;
+
+        let average_score =
+            sql::<Nullable<Double>>("(SELECT AVG(score) FROM reviews WHERE reviews.talk = talks.id)");
+        let query = filter.query(visibility);
+        let query = match filter.sort {
+            TalkSort::Newest => query.order((talks::submitted_at.desc(), talks::id.desc())),
+            TalkSort::Oldest => query.order((talks::submitted_at, talks::id)),
+            TalkSort::HighestScore => query.order((average_score.desc(), talks::id)),
+            // Unreviewed talks have no score, which SQLite sorts first.
+            TalkSort::LowestScore => query.order((average_score.clone().is_null(), average_score.asc(), talks::id)),
+        };
+        let talks = query
+            .limit(PAGE_SIZE)
+            .offset((page.max(1) - 1) * PAGE_SIZE)
+            .load(&conn.0)?;
+
+        Ok((talks, total))
+    }
+
+    /// Retrieve all talks that `user` is presenting, including those they
+    /// co-present
     pub fn get_for_user(conn: &DbConn, user: &User) -> Result<Vec<Talk>, Error> {
         talks::table
-            .filter(talks::presenter.eq(user.id))
+            .filter(presented_by(user))
             .load(&conn.0)
     }
 
-    /// Retrieves a single talk by `id`. The user must either be the presenter
-    /// of the given talk, or an admin.
+    /// Retrieve the talks that `user` is presenting at `event`
+    pub fn get_for_user_in(conn: &DbConn, user: &User, event: &Event) -> Result<Vec<Talk>, Error> {
+        talks::table
+            .filter(presented_by(user).and(talks::event.eq(event.id)))
+            .load(&conn.0)
+    }
+
+    /// Retrieves a single talk by `id`. The user must either be a presenter
+    /// of the given talk, or a reviewer.
     pub fn get_one(conn: &DbConn, user: &User, id: i32) -> Result<Option<Talk>, Error> {
         talks::table
-            .filter(talks::id.eq(id).and(talks::presenter.eq(user.id).or(user.is_admin)))
+            .filter(talks::id.eq(id).and(presented_by(user).or(user.roles.reviewer)))
             .get_result(&conn.0)
             .optional()
     }
 
-    /// Inserts a new talk. The talk will be `Pending` initially and presented by `user`.
-    pub fn new(conn: &DbConn, user: &User, title: &str, description: &str) -> Result<(), Error> {
-        diesel::insert_into(talks::table)
-            .values(NewTalk {
-                presenter: user.id,
-                title,
-                status: TalkStatus::Pending,
-                description,
-            })
-            .execute(&conn.0)?;
-        Ok(())
+    /// Returns whether `user` presents the talk with the given `id`, either
+    /// as its primary speaker or as a confirmed co-speaker.
+    pub fn is_presented_by(conn: &DbConn, user: &User, id: i32) -> Result<bool, Error> {
+        diesel::select(exists(talks::table.filter(talks::id.eq(id).and(presented_by(user)))))
+            .get_result(&conn.0)
+    }
+
+    /// Inserts a new talk submitted to `event`. The talk will be `Pending` initially and
+    /// presented by `user`. Returns the ID of the new talk.
+    pub fn new(conn: &DbConn, user: &User, event: &Event, details: &TalkDetails) -> Result<i32, Error> {
+        Talk::insert(conn, user, user, event, details, TalkStatus::Pending, None)
+    }
+
+    /// Submits a copy of one of `user`'s talks from another event to `event`.
+    /// The copy starts out `Pending`, like any new talk, and is presented by
+    /// `user` alone. Each talk can be resubmitted to each event once.
+    pub fn resubmit(conn: &DbConn, user: &User, id: i32, event: &Event) -> Result<Resubmission, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let talk: Option<Talk> = talks::table
+                .filter(
+                    talks::id.eq(id)
+                        .and(presented_by(user))
+                        .and(talks::event.ne(event.id)),
+                )
+                .first(&conn.0)
+                .optional()?;
+
+            let talk = match talk {
+                Some(talk) => talk,
+                None => return Ok(Resubmission::NotFound),
+            };
+            let copies: i64 = talks::table
+                .filter(talks::resubmitted_from.eq(id).and(talks::event.eq(event.id)))
+                .count()
+                .get_result(&conn.0)?;
+            if copies > 0 {
+                return Ok(Resubmission::AlreadySubmitted);
+            }
+
+            let details = TalkDetails {
+                title: talk.title,
+                description: talk.description,
+                format: talk.format,
+                duration: talk.duration,
+                level: talk.level,
+                outline: talk.outline,
+                notes: talk.notes,
+                tags: talk.tags,
+            };
+            let note = format!("Resubmitted from talk {}", id);
+            let copy = Talk::insert(conn, user, user, event, &details, TalkStatus::Pending, Some(&note))?;
+            diesel::update(talks::table.filter(talks::id.eq(copy)))
+                .set(talks::resubmitted_from.eq(id))
+                .execute(&conn.0)?;
+            Ok(Resubmission::Resubmitted(copy))
+        })
+    }
+
+    /// Inserts a talk imported from elsewhere, presented by `presenter` and
+    /// with the given `status`. `actor` is recorded as having submitted it to
+    /// `event`. Returns the ID of the new talk.
+    pub fn import(
+        conn: &DbConn,
+        actor: &User,
+        presenter: &User,
+        event: &Event,
+        details: &TalkDetails,
+        status: TalkStatus,
+    ) -> Result<i32, Error> {
+        Talk::insert(conn, actor, presenter, event, details, status, Some("Imported"))
+    }
+
+    fn insert(
+        conn: &DbConn,
+        actor: &User,
+        presenter: &User,
+        event: &Event,
+        details: &TalkDetails,
+        status: TalkStatus,
+        note: Option<&str>,
+    ) -> Result<i32, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            diesel::insert_into(talks::table)
+                .values(NewTalk {
+                    presenter: presenter.id,
+                    title: &details.title,
+                    status,
+                    description: &details.description,
+                    format: details.format,
+                    duration: details.duration,
+                    level: details.level,
+                    outline: &details.outline,
+                    notes: &details.notes,
+                    tags: &details.tags,
+                    submitted_at: Utc::now().naive_utc(),
+                    event: event.id,
+                })
+                .execute(&conn.0)?;
+
+            let id = diesel::select(last_insert_rowid).get_result(&conn.0)?;
+            TalkEvent::record(conn, id, actor, None, status, note)?;
+            Ok(id)
+        })
+    }
+
+    /// Retrieve the ID of the talk titled `title` that `presenter` submitted
+    /// to `event`, if there is one
+    pub fn find_by_title(
+        conn: &DbConn,
+        presenter: &User,
+        event: &Event,
+        title: &str,
+    ) -> Result<Option<i32>, Error> {
+        talks::table
+            .filter(
+                talks::presenter.eq(presenter.id)
+                    .and(talks::event.eq(event.id))
+                    .and(talks::title.eq(title)),
+            )
+            .select(talks::id)
+            .first(&conn.0)
+            .optional()
+    }
+
+    /// Replaces the details of the talk with the given `id`. Only presenters
+    /// can edit a talk, and only while it is editable. Returns whether a talk
+    /// was updated.
+    pub fn update(
+        conn: &DbConn,
+        user: &User,
+        id: i32,
+        details: &TalkDetails,
+    ) -> Result<bool, Error> {
+        let updated = diesel::update(talks::table.filter(
+            talks::id.eq(id)
+                .and(presented_by(user))
+                .and(talks::status.eq(TalkStatus::Pending as i32)),
+        ))
+        .set(details)
+        .execute(&conn.0)?;
+
+        Ok(updated > 0)
     }
 
-    /// Sets the status of the talk with the given `id` to `new_status`
+    /// Withdraws the talk with the given `id`. Only presenters can withdraw
+    /// a talk, and only while it is withdrawable. Returns whether a talk was
+    /// withdrawn.
+    pub fn withdraw(conn: &DbConn, user: &User, id: i32) -> Result<bool, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let withdrawable = vec![TalkStatus::Pending as i32, TalkStatus::Approved as i32];
+            let old_status: Option<TalkStatus> = talks::table
+                .filter(
+                    talks::id.eq(id)
+                        .and(presented_by(user))
+                        .and(talks::status.eq_any(withdrawable)),
+                )
+                .select(talks::status)
+                .first(&conn.0)
+                .optional()?;
+
+            let old_status = match old_status {
+                Some(old_status) => old_status,
+                None => return Ok(false),
+            };
+
+            diesel::update(talks::table.filter(talks::id.eq(id)))
+                .set(talks::status.eq(TalkStatus::Withdrawn as i32))
+                .execute(&conn.0)?;
+            TalkEvent::record(conn, id, user, Some(old_status), TalkStatus::Withdrawn, None)?;
+            Ok(true)
+        })
+    }
+
+    /// Sets the status of the talk with the given `id` to `new_status`, and
+    /// records the change in the talk's history along with `note`. Setting
+    /// the status the talk already has changes nothing.
     pub fn set_status(
         conn: &DbConn,
-        _admin: &Admin,
+        chair: &ProgramChair,
         id: i32,
-        new_status: TalkStatus
-    ) -> Result<(), Error> {
-        diesel::update(talks::table.filter(talks::id.eq(id)))
-            .set(talks::status.eq(new_status as i32))
-            .execute(&conn.0)?;
+        new_status: TalkStatus,
+        note: Option<&str>,
+    ) -> Result<StatusChange, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let old_status: Option<TalkStatus> = talks::table
+                .filter(talks::id.eq(id))
+                .select(talks::status)
+                .first(&conn.0)
+                .optional()?;
+
+            let old_status = match old_status {
+                Some(old_status) if old_status == new_status => return Ok(StatusChange::Unchanged),
+                Some(old_status) => old_status,
+                None => return Ok(StatusChange::NotFound),
+            };
 
-        Ok(())
+            diesel::update(talks::table.filter(talks::id.eq(id)))
+                .set(talks::status.eq(new_status as i32))
+                .execute(&conn.0)?;
+            TalkEvent::record(conn, id, &chair.0, Some(old_status), new_status, note)?;
+            Ok(StatusChange::Changed)
+        })
     }
 }
@@ -0,0 +1,1767 @@
+use super::*;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use diesel::{sql_query, SqliteConnection};
+use rocket::config::{Config, Environment, Table};
+use rocket::local::{Client, LocalResponse};
+use rocket::http::{Accept, ContentType, Header, Method, Status};
+use reqwest::Url;
+use serde_json;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::convert::TryFrom;
+use std::cell::Cell;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{env, fs, process, thread};
+
+/// A minimal OAuth provider speaking just enough HTTP for the GitHub flow.
+///
+/// The authorization code decides the outcome of the token exchange: the code
+/// `fail` is rejected, and any other code is exchanged for an access token
+/// belonging to the user whose login is the code. The user `hidden` keeps
+/// their email address private.
+struct MockProvider {
+    url: String,
+    token_requests: Arc<AtomicUsize>,
+}
+
+impl MockProvider {
+    fn start() -> MockProvider {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock provider");
+        let url = format!("http://{}", listener.local_addr().expect("local address"));
+        let token_requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = token_requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    handle_request(stream, &counter);
+                }
+            }
+        });
+
+        MockProvider { url, token_requests }
+    }
+
+    fn token_requests(&self) -> usize {
+        self.token_requests.load(Ordering::SeqCst)
+    }
+}
+
+/// The numeric ID of the mock account with the given `login`.
+fn account_id(login: &str) -> u32 {
+    login.bytes().fold(7, |id: u32, byte| id.wrapping_mul(31).wrapping_add(byte as u32))
+}
+
+fn handle_request(mut stream: TcpStream, token_requests: &AtomicUsize) {
+    let mut reader = BufReader::new(stream.try_clone().expect("clone stream"));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).expect("request line");
+    let path = request_line.split_whitespace().nth(1).unwrap_or("").to_string();
+
+    let mut content_length = 0;
+    let mut authorization = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("header line");
+        let line = line.trim_right();
+        if line.is_empty() {
+            break;
+        }
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").to_lowercase();
+        let value = parts.next().unwrap_or("").trim().to_string();
+        match name.as_str() {
+            "content-length" => content_length = value.parse().unwrap_or(0),
+            "authorization" => authorization = value,
+            _ => {}
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).expect("request body");
+    let body = String::from_utf8_lossy(&body);
+
+    let (status, response) = match path.as_str() {
+        "/token" => {
+            token_requests.fetch_add(1, Ordering::SeqCst);
+            let code = body
+                .split('&')
+                .filter_map(|pair| {
+                    let mut parts = pair.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some("code"), Some(code)) => Some(code.to_string()),
+                        _ => None,
+                    }
+                })
+                .next()
+                .unwrap_or_default();
+            if code == "fail" {
+                ("401 Unauthorized", json!({ "error": "bad_verification_code" }))
+            } else {
+                ("200 OK", json!({ "access_token": format!("access-{}", code) }))
+            }
+        }
+        "/user" if authorization.starts_with("token access-") => {
+            let login = &authorization["token access-".len()..];
+            let email = if login == "hidden" { None } else { Some(format!("{}@example.com", login)) };
+            ("200 OK", json!({
+                "id": account_id(login),
+                "login": login,
+                "name": format!("{} (mock)", login),
+                "email": email,
+            }))
+        }
+        "/user/emails" if authorization.starts_with("token access-") => {
+            let login = &authorization["token access-".len()..];
+            ("200 OK", json!([
+                { "email": format!("{}@old.example.com", login), "primary": false, "verified": true },
+                { "email": format!("{}@users.example.com", login), "primary": true, "verified": true },
+            ]))
+        }
+        "/user" => ("401 Unauthorized", json!({ "message": "Bad credentials" })),
+        _ => ("404 Not Found", json!({ "message": "Not Found" })),
+    };
+
+    let response = response.to_string();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    );
+}
+
+/// A database file, and directories that emails and avatars are written to,
+/// which are removed when the test is over.
+struct TestDb(String);
+
+impl TestDb {
+    fn new(name: &str) -> TestDb {
+        let path = env::temp_dir().join(format!("cfp-test-{}-{}.db", process::id(), name));
+        let db = TestDb(path.to_string_lossy().into_owned());
+        db.clean_up();
+        db
+    }
+
+    fn outbox(&self) -> String {
+        format!("{}.outbox", self.0)
+    }
+
+    fn avatars(&self) -> String {
+        format!("{}.avatars", self.0)
+    }
+
+    /// Returns the emails sent so far, oldest first.
+    fn emails(&self) -> Vec<String> {
+        let mut paths: Vec<_> = match fs::read_dir(self.outbox()) {
+            Ok(entries) => entries.map(|entry| entry.expect("outbox entry").path()).collect(),
+            Err(_) => return vec![],
+        };
+        paths.sort();
+        paths.iter().map(|path| fs::read_to_string(path).expect("readable email")).collect()
+    }
+
+    fn clean_up(&self) {
+        let _ = fs::remove_file(&self.0);
+        let _ = fs::remove_dir_all(self.outbox());
+        let _ = fs::remove_dir_all(self.avatars());
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        self.clean_up();
+    }
+}
+
+/// Builds the configuration of the application, using `db` and logging in
+/// through `provider`, which is configured as a GitHub provider named `mock`.
+fn config(provider: &MockProvider, db: &TestDb) -> Config {
+    let mut database = Table::new();
+    database.insert("url".into(), db.0.as_str().into());
+    let mut databases = Table::new();
+    databases.insert("cfp".into(), database.into());
+
+    let mut mock = Table::new();
+    mock.insert("kind".into(), "github".into());
+    mock.insert("client_id".into(), "client".into());
+    mock.insert("client_secret".into(), "secret".into());
+    mock.insert("authorize_url".into(), format!("{}/authorize", provider.url).into());
+    mock.insert("token_url".into(), format!("{}/token", provider.url).into());
+    mock.insert("user_url".into(), format!("{}/user", provider.url).into());
+    mock.insert("emails_url".into(), format!("{}/user/emails", provider.url).into());
+    let mut oauth = Table::new();
+    oauth.insert("mock".into(), mock.into());
+
+    let mut mail = Table::new();
+    mail.insert("backend".into(), "file".into());
+    mail.insert("dir".into(), db.outbox().into());
+
+    Config::build(Environment::Development)
+        .extra("databases", databases)
+        .extra("oauth", oauth)
+        .extra("mail", mail)
+        .extra("avatar_dir", db.avatars())
+        .finalize()
+        .expect("valid configuration")
+}
+
+/// Builds a client for the application, using `db` and logging in through
+/// `provider`.
+fn client(provider: &MockProvider, db: &TestDb) -> Client {
+    Client::new(build(rocket::custom(config(provider, db)))).expect("valid rocket instance")
+}
+
+/// Starts the login flow, returning the state passed to the provider.
+fn start_login(client: &Client) -> String {
+    let res = client.get("/login/mock").dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    let location = res.headers().get_one("Location").expect("redirect location");
+    let url = Url::parse(location).expect("valid authorize URL");
+    assert_eq!(url.path(), "/authorize");
+    url.query_pairs()
+        .find(|&(ref key, _)| key == "state")
+        .map(|(_, state)| state.into_owned())
+        .expect("state parameter")
+}
+
+fn callback<'c>(client: &'c Client, code: &str, state: &str) -> LocalResponse<'c> {
+    client.get(format!("/callback/mock?code={}&state={}", code, state)).dispatch()
+}
+
+/// Logs in as `login` through the mock provider.
+fn log_in(provider: &MockProvider, db: &TestDb, login: &str) -> Client {
+    let client = client(provider, db);
+    let state = start_login(&client);
+    assert_eq!(callback(&client, login, &state).status(), Status::SeeOther);
+    client
+}
+
+/// Returns the logged-in user as JSON, if there is one.
+fn me(client: &Client) -> Option<serde_json::Value> {
+    let mut res = client.get("/api/v1/me").dispatch();
+    if res.status() != Status::Ok {
+        assert_eq!(res.status(), Status::Unauthorized);
+        return None;
+    }
+    Some(serde_json::from_str(&res.body_string()?).expect("valid JSON"))
+}
+
+/// Returns the CSRF token of `client`, as embedded in its forms. The login
+/// form is shown to everyone, whether logged in or not.
+fn csrf_token(client: &Client) -> String {
+    let page = client.get("/login").dispatch().body_string().expect("login page");
+    let field = r#"name="csrf_token" value=""#;
+    let start = page.find(field).expect("a CSRF token") + field.len();
+    page[start..].chars().take_while(|&c| c != '"').collect()
+}
+
+/// Prepends the CSRF token of `client` to the form `body`.
+fn form(client: &Client, body: &str) -> String {
+    let token = format!("csrf_token={}", csrf_token(client));
+    if body.is_empty() { token } else { format!("{}&{}", token, body) }
+}
+
+/// Returns the header carrying the CSRF token of `client`, for requests
+/// without a form.
+fn csrf_header(client: &Client) -> Header<'static> {
+    Header::new("X-CSRF-Token", csrf_token(client))
+}
+
+#[test]
+fn first_user_becomes_admin() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("first_user_becomes_admin");
+
+    let alice = client(&provider, &db);
+    let state = start_login(&alice);
+    let res = callback(&alice, "alice", &state);
+    assert_eq!(res.status(), Status::SeeOther);
+    assert_eq!(res.headers().get_one("Location"), Some("/"));
+
+    let user = me(&alice).expect("alice is logged in");
+    assert_eq!(user["login"], "alice");
+    assert_eq!(user["email"], "alice@example.com");
+    assert_eq!(user["roles"]["organizer"], true);
+
+    let bob = client(&provider, &db);
+    let state = start_login(&bob);
+    assert_eq!(callback(&bob, "bob", &state).status(), Status::SeeOther);
+
+    let user = me(&bob).expect("bob is logged in");
+    assert_eq!(user["login"], "bob");
+    assert_eq!(user["roles"]["organizer"], false);
+    assert_eq!(user["roles"]["speaker"], true);
+
+    // Logging in again yields the same account, which is still the admin.
+    let alice = client(&provider, &db);
+    let state = start_login(&alice);
+    assert_eq!(callback(&alice, "alice", &state).status(), Status::SeeOther);
+
+    let user = me(&alice).expect("alice is logged in");
+    assert_eq!(user["login"], "alice");
+    assert_eq!(user["roles"]["organizer"], true);
+}
+
+#[test]
+fn state_mismatch() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("state_mismatch");
+
+    // Without a login in progress, there is no state to compare against.
+    let client = client(&provider, &db);
+    assert_eq!(callback(&client, "alice", "forged").status(), Status::BadRequest);
+
+    // With a login in progress, the state must match.
+    let state = start_login(&client);
+    assert_ne!(state, "forged");
+    assert_eq!(callback(&client, "alice", "forged").status(), Status::BadRequest);
+
+    // The code is never exchanged, and nobody is logged in.
+    assert_eq!(provider.token_requests(), 0);
+    assert!(me(&client).is_none());
+}
+
+#[test]
+fn token_exchange_failure() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("token_exchange_failure");
+
+    let client = client(&provider, &db);
+    let state = start_login(&client);
+    assert_eq!(callback(&client, "fail", &state).status(), Status::BadGateway);
+    assert_eq!(provider.token_requests(), 1);
+    assert!(me(&client).is_none());
+
+    // The state was used up; a successful exchange requires a new login.
+    let state = start_login(&client);
+    assert_eq!(callback(&client, "alice", &state).status(), Status::SeeOther);
+    assert_eq!(provider.token_requests(), 2);
+    assert!(me(&client).is_some());
+}
+
+#[test]
+fn invalid_provider_urls_are_refused_at_launch() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("invalid_provider_urls_are_refused_at_launch");
+
+    for &(key, url) in &[("authorize_url", "not a URL"), ("token_url", "/token"), ("user_url", "ftp://example.com/")] {
+        let mut config = config(&provider, &db);
+        let mut mock = config.get_table("oauth").unwrap()["mock"].as_table().unwrap().clone();
+        mock.insert(key.into(), url.into());
+        let mut oauth = Table::new();
+        oauth.insert("mock".into(), mock.into());
+        config.set_extra("oauth", oauth);
+        assert!(Client::new(build(rocket::custom(config))).is_err(), "{} = {} was accepted", key, url);
+    }
+}
+
+#[test]
+fn submissions_are_refused_outside_the_cfp_window() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("submissions_are_refused_outside_the_cfp_window");
+    let mut config = config(&provider, &db);
+    config.set_extra("cfp_closes", "2018-01-01T00:00:00Z");
+
+    // The deadline is kept by the server, so everything below goes through
+    // the same client.
+    let alice = Client::new(build(rocket::custom(config))).expect("valid rocket instance");
+    let state = start_login(&alice);
+    assert_eq!(callback(&alice, "alice", &state).status(), Status::SeeOther);
+    let send = |method: Method, path: &str, body: &str| {
+        alice.req(method, path).header(ContentType::Form).body(form(&alice, body)).dispatch().status()
+    };
+    assert_eq!(send(Method::Post, "/events", "slug=rustfest&name=RustFest"), Status::SeeOther);
+
+    let talk = "title=Rocket&description=Web+apps&format=talk&duration=30&level=beginner&outline=&notes=&tags=";
+    let submit_api = |query: &str| {
+        let body = json!({
+            "title": "Rocket",
+            "description": "Web apps",
+            "format": "Talk",
+            "duration": 30,
+            "level": "Beginner",
+            "tags": [],
+        });
+        alice.post(format!("/api/v1/talks{}", query))
+            .header(ContentType::JSON)
+            .body(body.to_string())
+            .dispatch()
+            .status()
+    };
+    assert_eq!(send(Method::Post, "/talks", talk), Status::Forbidden);
+    assert_eq!(send(Method::Post, "/e/rustfest/talks", talk), Status::Forbidden);
+    assert_eq!(submit_api(""), Status::Forbidden);
+    assert_eq!(submit_api("?event=rustfest"), Status::Forbidden);
+    let talks = alice.get("/api/v1/talks").dispatch().body_string().unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&talks).unwrap().as_array().unwrap().len(), 0);
+
+    // Moving the deadline reopens the CFP of every event without a window of
+    // its own.
+    assert_eq!(send(Method::Put, "/admin/deadline", "closes=2100-01-01T00%3A00"), Status::SeeOther);
+    assert_eq!(send(Method::Post, "/talks", talk), Status::SeeOther);
+    assert_eq!(send(Method::Post, "/e/rustfest/talks", talk), Status::SeeOther);
+    assert_eq!(submit_api(""), Status::Created);
+    assert_eq!(submit_api("?event=rustfest"), Status::Created);
+
+    let settings = "name=RustFest&cfp_opens=&cfp_closes=2018-01-01T00%3A00";
+    assert_eq!(send(Method::Put, "/e/rustfest/admin", settings), Status::SeeOther);
+    assert_eq!(send(Method::Post, "/e/rustfest/talks", talk), Status::Forbidden);
+    assert_eq!(submit_api("?event=rustfest"), Status::Forbidden);
+    assert_eq!(send(Method::Post, "/talks", talk), Status::SeeOther);
+}
+
+#[test]
+fn reviewers_score_but_do_not_decide() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("reviewers_score_but_do_not_decide");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let carol = log_in(&provider, &db, "carol");
+
+    let mut res = bob.post("/api/v1/talks")
+        .header(ContentType::JSON)
+        .body(r#"{ "title": "Rocket", "description": "Web apps", "format": "Talk", "duration": 30, "level": "Beginner" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let talk: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    let talk_id = talk["id"].as_i64().unwrap();
+
+    // Carol can't see the talk until she is a reviewer.
+    let talk_page = format!("/talks/{}", talk_id);
+    assert_eq!(carol.get(talk_page.clone()).header(Accept::HTML).dispatch().status(), Status::NotFound);
+
+    let carol_id = me(&carol).unwrap()["id"].as_i64().unwrap();
+    let res = alice.post("/admin/roles")
+        .header(ContentType::Form)
+        .body(form(&alice, &format!("user={}&role=reviewer", carol_id)))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+
+    // Reviewers can score the talk, but don't see who submitted it...
+    let mut res = carol.get(talk_page.clone()).header(Accept::HTML).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert!(!res.body_string().unwrap().contains("bob@example.com"));
+
+    let res = carol.put(format!("/talks/{}/review", talk_id))
+        .header(ContentType::Form)
+        .body(form(&carol, "score=4&comments=Nice"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+
+    // ...and can't decide on it.
+    let res = carol.put(format!("/api/v1/talks/{}/status", talk_id))
+        .header(ContentType::JSON)
+        .body(r#"{ "status": "Approved" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Forbidden);
+
+    // Nobody scores their own talk.
+    let own_id = submit_talk(&carol, "Diesel", "Databases", &[]);
+    let res = carol.put(format!("/talks/{}/review", own_id))
+        .header(ContentType::Form)
+        .body(form(&carol, "score=5&comments=Great"))
+        .dispatch();
+    assert_eq!(res.status(), Status::Forbidden);
+
+    // Organizers can do both.
+    let mut res = alice.get(talk_page).header(Accept::HTML).dispatch();
+    assert!(res.body_string().unwrap().contains("bob@example.com"));
+
+    let res = alice.put(format!("/api/v1/talks/{}/status", talk_id))
+        .header(ContentType::JSON)
+        .body(r#"{ "status": "Approved" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+}
+
+#[test]
+fn audit_log_is_filtered_by_actor_and_date() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("audit_log_is_filtered_by_actor_and_date");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let talk_id = submit_talk(&bob, "Rocket", "Web apps", &[]);
+
+    let res = alice.put(format!("/api/v1/talks/{}/status", talk_id))
+        .header(ContentType::JSON)
+        .body(r#"{ "status": "Approved" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let bob_id = me(&bob).unwrap()["id"].as_i64().unwrap();
+    let res = alice.post("/admin/roles")
+        .header(ContentType::Form)
+        .body(form(&alice, &format!("user={}&role=reviewer", bob_id)))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    let res = alice.put("/admin/deadline")
+        .header(ContentType::Form)
+        .body(form(&alice, "closes=2030-01-01T18%3A00"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+
+    let audit = |query: &str| {
+        let mut res = alice.get(format!("/admin/audit{}", query)).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        res.body_string().unwrap()
+    };
+
+    let log = audit("");
+    assert!(log.contains("Rocket"));
+    assert!(log.contains("granted Reviewer to bob"));
+    assert!(log.contains("moved the CFP deadline to 2030-01-01 18:00:00 UTC"));
+
+    // Bob only submitted his talk.
+    let log = audit("?actor=bob");
+    assert!(log.contains("submitted"));
+    assert!(!log.contains("granted Reviewer"));
+    assert!(!log.contains("Approved"));
+
+    let today = chrono::Utc::now().naive_utc().date();
+    let log = audit(&format!("?since={}&until={}", today, today));
+    assert!(log.contains("granted Reviewer to bob"));
+    let log = audit(&format!("?since={}", today.succ()));
+    assert_eq!(log.matches("No matching events.").count(), 2);
+    let log = audit(&format!("?until={}", today.pred()));
+    assert_eq!(log.matches("No matching events.").count(), 2);
+
+    assert_eq!(alice.get("/admin/audit?since=yesterday").dispatch().status(), Status::BadRequest);
+    assert_eq!(bob.get("/admin/audit").dispatch().status(), Status::NotFound);
+}
+
+#[test]
+fn private_emails_are_looked_up() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("private_emails_are_looked_up");
+
+    let client = log_in(&provider, &db, "hidden");
+    let user = me(&client).expect("hidden is logged in");
+    assert_eq!(user["email"], "hidden@users.example.com");
+    assert_eq!(user["email_confirmed"], false);
+
+    let res = client.put("/email")
+        .header(ContentType::Form)
+        .body(form(&client, "email=hidden%40example.org"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+
+    let user = me(&client).expect("hidden is logged in");
+    assert_eq!(user["email"], "hidden@example.org");
+    assert_eq!(user["email_confirmed"], true);
+}
+
+#[test]
+fn speakers_are_emailed() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("speakers_are_emailed");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let carol = log_in(&provider, &db, "carol");
+    confirm_email(&bob);
+
+    let mut res = bob.post("/api/v1/talks")
+        .header(ContentType::JSON)
+        .body(r#"{ "title": "Rocket", "description": "Web apps", "format": "Talk", "duration": 30, "level": "Beginner" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let talk: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+
+    // Carol hasn't confirmed her address, so she isn't emailed.
+    submit_talk(&carol, "Diesel", "Databases", &[]);
+
+    let emails = db.emails();
+    assert_eq!(emails.len(), 1);
+    assert!(emails[0].contains("To: bob@example.com"));
+    assert!(emails[0].contains(r#"Subject: We received your proposal "Rocket""#));
+
+    let res = alice.put(format!("/api/v1/talks/{}/status", talk["id"]))
+        .header(ContentType::JSON)
+        .body(r#"{ "status": "Rejected" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    let emails = db.emails();
+    assert_eq!(emails.len(), 2);
+    assert!(emails[1].contains("To: bob@example.com"));
+    assert!(emails[1].contains(r#"Subject: Your proposal "Rocket" was not accepted"#));
+    assert!(emails[1].contains(&format!("/talks/{}", talk["id"])));
+
+    // Deciding the same way again doesn't repeat the email.
+    let res = alice.put(format!("/api/v1/talks/{}/status", talk["id"]))
+        .header(ContentType::JSON)
+        .body(r#"{ "status": "Rejected" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(db.emails().len(), 2);
+}
+
+/// Confirms the email address the provider told about, so that the user is
+/// emailed.
+fn confirm_email(client: &Client) {
+    let email = me(client).expect("logged in")["email"].as_str().unwrap().to_string();
+    let res = client.put("/email")
+        .header(ContentType::Form)
+        .body(form(client, &format!("email={}", email.replace('@', "%40"))))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+}
+
+/// Submits a talk through the API and returns its ID.
+fn submit_talk(client: &Client, title: &str, description: &str, tags: &[&str]) -> i64 {
+    let body = json!({
+        "title": title,
+        "description": description,
+        "format": "Talk",
+        "duration": 30,
+        "level": "Beginner",
+        "tags": tags,
+    });
+    let mut res = client.post("/api/v1/talks")
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let talk: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    talk["id"].as_i64().unwrap()
+}
+
+#[test]
+fn admin_search_and_pagination() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("admin_search_and_pagination");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+
+    let rocket = submit_talk(&bob, "Rocket on nightly", "Web apps with Rust", &["web"]);
+    submit_talk(&bob, "Async Rust", "Futures and tokio", &["async", "web"]);
+    for i in 0..talk::PAGE_SIZE {
+        submit_talk(&alice, &format!("Filler {}", i), "Nothing to see", &[]);
+    }
+
+    let search = |query: &str| {
+        let mut res = alice.get(format!("/admin?{}", query)).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        res.body_string().unwrap()
+    };
+
+    let page = search("q=rock");
+    assert!(page.contains("Rocket on nightly") && !page.contains("Async Rust"));
+    let page = search("q=tokio+futures");
+    assert!(!page.contains("Rocket on nightly") && page.contains("Async Rust"));
+    let page = search("q=%22unbalanced");
+    assert!(page.contains("0 talks found."));
+
+    let page = search("tag=async");
+    assert!(!page.contains("Rocket on nightly") && page.contains("Async Rust"));
+    let page = search("tag=web&presenter=bob");
+    assert!(page.contains("2 talks found."));
+
+    let res = alice.put(format!("/api/v1/talks/{}/status", rocket))
+        .header(ContentType::JSON)
+        .body(r#"{ "status": "Approved" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let page = search("status=approved");
+    assert!(page.contains("1 talk found.") && page.contains("Rocket on nightly"));
+
+    // The newest talks come first, so the first talk ends up on the last page.
+    let page = search("");
+    assert!(page.contains("52 talks found.") && page.contains("Page 1 of 2"));
+    assert!(!page.contains("Rocket on nightly") && page.contains("sort=newest&amp;page=2"));
+    let page = search("page=2");
+    assert!(page.contains("Page 2 of 2") && page.contains("Rocket on nightly"));
+    let page = search("sort=oldest");
+    assert!(page.contains("Rocket on nightly"));
+
+    // Wildcards are searched for literally.
+    assert!(search("presenter=%25").contains("0 talks found."));
+    assert!(search("tag=w_b").contains("0 talks found."));
+
+    // Unreviewed talks come last, however the talks are sorted by score.
+    let late = submit_talk(&bob, "Late talk", "Submitted last", &[]);
+    let res = alice.put(format!("/talks/{}/review", late))
+        .header(ContentType::Form)
+        .body(form(&alice, "score=2&comments=Meh"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    assert!(search("sort=lowest_score").contains("Late talk"));
+    assert!(search("sort=highest_score").contains("Late talk"));
+}
+
+#[test]
+fn organizers_export_talks() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("organizers_export_talks");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+
+    submit_talk(&bob, "Rocket, on nightly", "Web apps with \"Rust\"", &["web"]);
+    submit_talk(&bob, "Async Rust", "Futures and tokio", &[]);
+    submit_talk(&bob, "=1+2", "-3", &[]);
+
+    // Speakers can't export.
+    assert_eq!(bob.get("/admin/export.csv").dispatch().status(), Status::NotFound);
+
+    let mut res = alice.get("/admin/export.csv").dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.content_type(), Some(ContentType::CSV));
+    let csv = res.body_string().unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("id,title,description,status"));
+    assert!(lines[1].contains(r#""Rocket, on nightly","Web apps with ""Rust""",Pending"#));
+    assert!(lines[1].contains(",bob,bob (mock),0,,"));
+    // Spreadsheets show cells that look like formulas as text.
+    assert!(lines[3].contains(",'=1+2,'-3,Pending"));
+
+    let mut res = alice.get("/admin/export.json").dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let talks: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    assert_eq!(talks.as_array().unwrap().len(), 3);
+    assert_eq!(talks[1]["title"], "Async Rust");
+    assert_eq!(talks[2]["title"], "=1+2");
+    assert_eq!(talks[1]["presenter_login"], "bob");
+    assert_eq!(talks[1]["reviews"]["count"], 0);
+}
+
+#[test]
+fn organizers_import_talks() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("organizers_import_talks");
+
+    let alice = log_in(&provider, &db, "alice");
+    let csv = format!(
+        "github_id,github_login,name,email,title,description,format,duration,status\n\
+         {bob},bob,Bob,bob@example.com,Rocket,Web apps,talk,30,approved\n\
+         {bob},bob,Bob,,Diesel,Databases,workshop,90,\n\
+         {carol},,Carol,,,Nothing,talk,30,pending\n",
+        bob = account_id("bob"),
+        carol = account_id("carol"),
+    );
+    let import = |dry_run: bool| {
+        let mut res = alice.post(format!("/admin/import?dry_run={}", dry_run))
+            .header(ContentType::CSV)
+            .body(csv.as_str())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        serde_json::from_str::<serde_json::Value>(&res.body_string().unwrap()).unwrap()
+    };
+
+    // Row 3 has an empty status, and row 4 has no title.
+    let report = import(true);
+    assert_eq!((report["imported"].as_u64(), report["invalid"].as_u64()), (Some(1), Some(2)));
+    assert_eq!(report["rows"][1]["errors"][0], "Unknown status: ''.");
+    assert_eq!(report["rows"][2]["errors"][0], "The title must not be empty.");
+
+    // A dry run doesn't change anything.
+    let export = |client: &Client| client.get("/admin/export.json").dispatch().body_string().unwrap();
+    assert_eq!(export(&alice), "[\n]\n");
+
+    let report = import(false);
+    assert_eq!(report["rows"][0]["outcome"], "imported");
+    let talks: serde_json::Value = serde_json::from_str(&export(&alice)).unwrap();
+    assert_eq!(talks[0]["title"], "Rocket");
+    assert_eq!(talks[0]["status"], "Approved");
+    assert_eq!(talks[0]["presenter_login"], "bob");
+
+    // Importing again skips the talk, and Bob finds it when he logs in.
+    let report = import(false);
+    assert_eq!((report["imported"].as_u64(), report["skipped"].as_u64()), (Some(0), Some(1)));
+    // Only the import that added a talk is in the audit log.
+    let log = alice.get("/admin/audit").dispatch().body_string().unwrap();
+    assert_eq!(log.matches("imported 1 talk").count(), 1);
+
+    let bob = log_in(&provider, &db, "bob");
+    let mut res = bob.get("/api/v1/talks").dispatch();
+    let talks: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    assert_eq!(talks.as_array().unwrap().len(), 1);
+    assert_eq!(talks[0]["title"], "Rocket");
+
+    // Logins can change, so they don't identify presenters.
+    let mut res = alice.post("/admin/import?dry_run=true")
+        .header(ContentType::CSV)
+        .body("github_id,name,title,description\nbob,Bob,Serde,Serialization\n")
+        .dispatch();
+    let report: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    assert_eq!(report["rows"][0]["errors"][0], "The GitHub account ID must be a number, not 'bob'.");
+
+    // Pasted imports aren't held to the much smaller limit of other forms.
+    let mut data = String::from("github_id,name,title,description%0A");
+    for i in 0..1000 {
+        data.push_str(&format!("{},Bob,Talk+number+{},A+talk+to+fill+up+the+form%0A", account_id("bob"), i));
+    }
+    assert!(data.len() > 32 * 1024);
+    let mut res = alice.post("/admin/import")
+        .header(ContentType::Form)
+        .body(form(&alice, &format!("format=csv&data={}&dry_run=on", data)))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert!(res.body_string().unwrap().contains("1000 imported, 0 skipped, 0 invalid."));
+}
+
+#[test]
+fn organizers_schedule_approved_talks() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("organizers_schedule_approved_talks");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let carol = log_in(&provider, &db, "carol");
+
+    let rocket = submit_talk(&bob, "Rocket", "Web apps", &[]);
+    let diesel = submit_talk(&bob, "Diesel", "Databases", &[]);
+    let serde = submit_talk(&carol, "Serde, in depth", "Serialization", &[]);
+    let pending = submit_talk(&carol, "Tera", "Templates", &[]);
+    for &id in &[rocket, diesel, serde] {
+        let res = alice.put(format!("/api/v1/talks/{}/status", id))
+            .header(ContentType::JSON)
+            .body(r#"{ "status": "Approved" }"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    }
+
+    let post = |path: &str, body: String| {
+        alice.post(path).header(ContentType::Form).body(form(&alice, &body)).dispatch().status()
+    };
+    assert_eq!(post("/e/cfp/admin/schedule/rooms", "name=Main".into()), Status::SeeOther);
+    assert_eq!(post("/e/cfp/admin/schedule/rooms", "name=Side".into()), Status::SeeOther);
+    assert_eq!(post("/e/cfp/admin/schedule/rooms", "name=Main".into()), Status::Conflict);
+    for &(starts_at, ends_at) in &[("10:00", "10:30"), ("10:15", "10:45"), ("11:00", "11:30")] {
+        let body = format!("starts_at=2018-10-20T{}&ends_at=2018-10-20T{}", starts_at, ends_at);
+        assert_eq!(post("/e/cfp/admin/schedule/slots", body), Status::SeeOther);
+    }
+    let (main, side) = (1, 2);
+    let schedule = |talk: i64, room: i32, slot: i32| {
+        post("/e/cfp/admin/schedule", format!("talk={}&room={}&slot={}", talk, room, slot))
+    };
+
+    assert_eq!(schedule(pending, main, 1), Status::UnprocessableEntity);
+    assert_eq!(schedule(rocket, main, 1), Status::SeeOther);
+    // Bob can't be in two rooms at once...
+    assert_eq!(schedule(diesel, side, 2), Status::UnprocessableEntity);
+    // ...and talks in the same room can't overlap.
+    assert_eq!(schedule(serde, main, 2), Status::UnprocessableEntity);
+    assert_eq!(schedule(serde, side, 2), Status::SeeOther);
+    assert_eq!(schedule(diesel, main, 3), Status::SeeOther);
+
+    // The schedule is public.
+    let anonymous = client(&provider, &db);
+    let mut res = anonymous.get("/schedule").dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let page = res.body_string().unwrap();
+    assert!(page.contains("Rocket") && page.contains("Diesel") && !page.contains("Tera"));
+
+    let mut res = anonymous.get("/schedule.ics").dispatch();
+    assert_eq!(res.content_type(), Some(ContentType::Calendar));
+    let ics = res.body_string().unwrap();
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 3);
+    assert!(ics.contains("DTSTART:20181020T101500Z\r\nDTEND:20181020T104500Z\r\nSUMMARY:Serde\\, in depth\r\nLOCATION:Side\r\n"));
+
+    // Withdrawn talks disappear from the public schedule.
+    let res = bob.delete(format!("/talks/{}", diesel)).header(csrf_header(&bob)).dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    let ics = anonymous.get("/schedule.ics").dispatch().body_string().unwrap();
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+
+    // Rooms and slots can only be removed while nothing is scheduled in them.
+    let delete = |path: &str| alice.delete(path).header(csrf_header(&alice)).dispatch().status();
+    assert_eq!(delete("/e/cfp/admin/schedule/rooms/1"), Status::Conflict);
+    assert_eq!(delete("/e/cfp/admin/schedule/slots/1"), Status::Conflict);
+    assert_eq!(delete("/e/cfp/admin/schedule/rooms/99"), Status::NotFound);
+    assert_eq!(delete("/e/cfp/admin/schedule/slots/99"), Status::NotFound);
+    assert_eq!(post("/e/cfp/admin/schedule/rooms", "name=Spare".into()), Status::SeeOther);
+    assert_eq!(delete("/e/cfp/admin/schedule/rooms/3"), Status::SeeOther);
+
+    // Co-speakers can't be in two rooms at once either.
+    let dave = log_in(&provider, &db, "dave");
+    for &(client, talk) in &[(&bob, rocket), (&carol, serde)] {
+        let res = client.post(format!("/talks/{}/speakers", talk))
+            .header(ContentType::Form)
+            .body(form(client, "login=dave"))
+            .dispatch();
+        assert_eq!(res.status(), Status::SeeOther);
+        let res = dave.post(format!("/invitations/{}", talk))
+            .header(ContentType::Form)
+            .body(form(&dave, ""))
+            .dispatch();
+        assert_eq!(res.status(), Status::SeeOther);
+    }
+    let page = anonymous.get("/schedule").dispatch().body_string().unwrap();
+    assert!(page.contains("bob (mock) and dave (mock)"));
+    assert_eq!(schedule(serde, side, 2), Status::UnprocessableEntity);
+    let page = alice.get("/e/cfp/admin/schedule").dispatch().body_string().unwrap();
+    assert!(page.contains("dave (mock) can"));
+    let res = alice.get("/admin/schedule").dispatch();
+    assert_eq!(res.headers().get_one("Location"), Some("/e/cfp/admin/schedule"));
+
+    // Every event has its own rooms, slots and schedule.
+    assert_eq!(post("/events", "slug=rustfest&name=RustFest".into()), Status::SeeOther);
+    let body = json!({
+        "title": "Tokio",
+        "description": "Async I/O",
+        "format": "Talk",
+        "duration": 30,
+        "level": "Beginner",
+        "tags": [],
+    });
+    let mut res = bob.post("/api/v1/talks?event=rustfest")
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let talk: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    let tokio = talk["id"].as_i64().unwrap();
+    let res = alice.put(format!("/api/v1/talks/{}/status", tokio))
+        .header(ContentType::JSON)
+        .body(r#"{ "status": "Approved" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let page = alice.get("/e/rustfest/admin/schedule").dispatch().body_string().unwrap();
+    assert!(page.contains("Tokio") && !page.contains("Rocket"));
+
+    assert_eq!(schedule(tokio, side, 3), Status::UnprocessableEntity);
+    let placement = |room: i32, slot: i32| format!("talk={}&room={}&slot={}", tokio, room, slot);
+    assert_eq!(post("/e/rustfest/admin/schedule", placement(side, 3)), Status::NotFound);
+    assert_eq!(delete("/e/rustfest/admin/schedule/rooms/1"), Status::NotFound);
+    assert_eq!(post("/e/rustfest/admin/schedule/rooms", "name=Main".into()), Status::SeeOther);
+    let body = "starts_at=2018-10-20T10:00&ends_at=2018-10-20T10:30".to_string();
+    assert_eq!(post("/e/rustfest/admin/schedule/slots", body), Status::SeeOther);
+    // The new room and slot take the next free ids.
+    assert_eq!(post("/e/rustfest/admin/schedule", placement(3, 4)), Status::SeeOther);
+
+    let page = anonymous.get("/e/rustfest/schedule").dispatch().body_string().unwrap();
+    assert!(page.contains("Tokio") && !page.contains("Rocket"));
+    let ics = anonymous.get("/e/rustfest/schedule.ics").dispatch().body_string().unwrap();
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+    let page = anonymous.get("/schedule").dispatch().body_string().unwrap();
+    assert!(page.contains("Rocket") && !page.contains("Tokio"));
+
+    let log = alice.get("/admin/audit").dispatch().body_string().unwrap();
+    for action in &[
+        "added room Main to event cfp",
+        "added room Main to event rustfest",
+        "removed room Spare from event cfp",
+        "added the slot from 2018-10-20 10:00:00 until 2018-10-20 10:30:00 to event cfp",
+        "of event cfp in Main at 2018-10-20 10:00:00",
+        "of event rustfest in Main at 2018-10-20 10:00:00",
+    ] {
+        assert!(log.contains(action), "{} is missing from the audit log", action);
+    }
+}
+
+#[test]
+fn talks_are_submitted_to_events() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("talks_are_submitted_to_events");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let carol = log_in(&provider, &db, "carol");
+
+    let post = |client: &Client, path: &str, body: &str| {
+        client.post(path).header(ContentType::Form).body(form(client, body)).dispatch().status()
+    };
+    assert_eq!(post(&alice, "/events", "slug=rustfest-2018&name=RustFest+2018"), Status::SeeOther);
+    assert_eq!(post(&alice, "/events", "slug=rustfest-2018&name=Again"), Status::Conflict);
+    assert_eq!(post(&alice, "/events", "slug=Not+a+slug&name=Nope"), Status::UnprocessableEntity);
+    assert_eq!(post(&bob, "/events", "slug=bobconf&name=BobConf"), Status::NotFound);
+
+    // Talks submitted without an event go to the default one.
+    let rocket = submit_talk(&bob, "Rocket", "Web apps", &[]);
+    let talk = "title=Diesel&description=Databases&format=talk&duration=30&level=beginner&outline=&notes=&tags=";
+    assert_eq!(post(&bob, "/e/rustfest-2018/talks", talk), Status::SeeOther);
+    assert_eq!(post(&bob, "/e/nowhere/talks", talk), Status::NotFound);
+
+    // Bob can resubmit his talk to the new event, but only once.
+    let body = format!("talk={}", rocket);
+    assert_eq!(post(&bob, "/e/rustfest-2018/resubmissions", &body), Status::SeeOther);
+    assert_eq!(post(&bob, "/e/rustfest-2018/resubmissions", &body), Status::Conflict);
+    assert_eq!(post(&bob, "/e/cfp/resubmissions", &body), Status::NotFound);
+    assert_eq!(post(&carol, "/e/rustfest-2018/resubmissions", &body), Status::NotFound);
+
+    let page = bob.get("/e/rustfest-2018/talks").dispatch().body_string().unwrap();
+    assert!(page.contains("Diesel") && page.contains("Rocket"));
+    assert!(!page.contains("Submit a previous talk"));
+    let page = alice.get("/admin?event=rustfest-2018").dispatch().body_string().unwrap();
+    assert!(page.contains("2 talks found."));
+    let page = alice.get("/admin?event=cfp").dispatch().body_string().unwrap();
+    assert!(page.contains("1 talk found."));
+
+    // Carol can manage the new event once she administers it, and closing its
+    // CFP doesn't affect the default event.
+    assert_eq!(carol.get("/e/rustfest-2018/admin").dispatch().status(), Status::Forbidden);
+    assert_eq!(post(&alice, "/e/rustfest-2018/admin/admins", "login=carol"), Status::SeeOther);
+    let res = carol.put("/e/rustfest-2018/admin")
+        .header(ContentType::Form)
+        .body(form(&carol, "name=RustFest+2018&cfp_opens=&cfp_closes=2018-01-01T00%3A00"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    assert_eq!(carol.get("/e/cfp/admin").dispatch().status(), Status::Forbidden);
+
+    assert_eq!(post(&bob, "/e/rustfest-2018/talks", talk), Status::Forbidden);
+    assert_eq!(post(&bob, "/talks", talk), Status::SeeOther);
+
+    let carol_id = me(&carol).unwrap()["id"].as_i64().unwrap();
+    let remove = |user: i64| {
+        alice.delete("/e/rustfest-2018/admin/admins")
+            .header(ContentType::Form)
+            .body(form(&alice, &format!("user={}", user)))
+            .dispatch()
+            .status()
+    };
+    assert_eq!(remove(carol_id), Status::SeeOther);
+    assert_eq!(remove(carol_id), Status::NotFound);
+    assert_eq!(remove(99), Status::NotFound);
+
+    // Organizers see all of it in the audit log.
+    let log = alice.get("/admin/audit").dispatch().body_string().unwrap();
+    for action in &[
+        "created event rustfest-2018",
+        "made carol an administrator of event rustfest-2018",
+        "updated the settings of event rustfest-2018",
+        "removed carol from the administrators of event rustfest-2018",
+    ] {
+        assert!(log.contains(action), "{} is missing from the audit log", action);
+    }
+}
+
+#[test]
+fn speakers_edit_and_withdraw_their_talks() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("speakers_edit_and_withdraw_their_talks");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let carol = log_in(&provider, &db, "carol");
+
+    let rocket = submit_talk(&bob, "Rocket", "Web apps", &[]);
+    let diesel = submit_talk(&bob, "Diesel", "Databases", &[]);
+    let edit = |client: &Client, id: i64, title: &str| {
+        let body = format!(
+            "title={}&description=Web+apps&format=talk&duration=30&level=beginner&outline=&notes=&tags=",
+            title
+        );
+        client.put(format!("/talks/{}", id))
+            .header(ContentType::Form)
+            .body(form(client, &body))
+            .dispatch()
+            .status()
+    };
+    let withdraw = |client: &Client, id: i64| {
+        client.delete(format!("/talks/{}", id)).header(csrf_header(client)).dispatch().status()
+    };
+    let talk = |id: i64| {
+        let mut res = bob.get(format!("/api/v1/talks/{}", id)).dispatch();
+        serde_json::from_str::<serde_json::Value>(&res.body_string().unwrap()).unwrap()
+    };
+    let set_status = |id: i64, status: &str| {
+        let res = alice.put(format!("/api/v1/talks/{}/status", id))
+            .header(ContentType::JSON)
+            .body(format!(r#"{{ "status": "{}" }}"#, status))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    };
+
+    // Pending talks can be edited by their speaker.
+    assert_eq!(edit(&bob, rocket, "Rocket+on+stable"), Status::SeeOther);
+    assert_eq!(talk(rocket)["title"], "Rocket on stable");
+    assert_eq!(edit(&bob, rocket, ""), Status::UnprocessableEntity);
+    assert_eq!(talk(rocket)["title"], "Rocket on stable");
+
+    // Nobody else edits or withdraws them, not even reviewers, who can see
+    // them.
+    let carol_id = me(&carol).unwrap()["id"].as_i64().unwrap();
+    assert_eq!(edit(&carol, rocket, "Mine"), Status::NotFound);
+    assert_eq!(withdraw(&carol, rocket), Status::NotFound);
+    let res = alice.post("/admin/roles")
+        .header(ContentType::Form)
+        .body(form(&alice, &format!("user={}&role=reviewer", carol_id)))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    assert_eq!(edit(&carol, rocket, "Mine"), Status::Forbidden);
+    assert_eq!(withdraw(&carol, rocket), Status::Forbidden);
+    assert_eq!(talk(rocket)["status"], "Pending");
+
+    // Once a decision was made, talks are locked. Approved talks can still be
+    // withdrawn, once.
+    set_status(rocket, "Approved");
+    set_status(diesel, "Rejected");
+    assert_eq!(edit(&bob, rocket, "Rocket+0.4"), Status::Conflict);
+    assert_eq!(edit(&bob, diesel, "Diesel+1.3"), Status::Conflict);
+    assert_eq!(withdraw(&bob, diesel), Status::Conflict);
+    assert_eq!(withdraw(&bob, rocket), Status::SeeOther);
+    assert_eq!(talk(rocket)["status"], "Withdrawn");
+    assert_eq!(withdraw(&bob, rocket), Status::Conflict);
+    assert_eq!(edit(&bob, rocket, "Rocket+0.4"), Status::Conflict);
+    assert_eq!(talk(rocket)["title"], "Rocket on stable");
+}
+
+#[test]
+fn speakers_invite_co_speakers() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("speakers_invite_co_speakers");
+
+    let _alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let carol = log_in(&provider, &db, "carol");
+    let dave = log_in(&provider, &db, "dave");
+    confirm_email(&bob);
+    confirm_email(&carol);
+
+    let talk = submit_talk(&bob, "Rocket", "Web apps", &[]);
+    let speakers = format!("/talks/{}/speakers", talk);
+    let talk_page = format!("/talks/{}", talk);
+    let post = |client: &Client, path: &str, body: &str| {
+        client.post(path).header(ContentType::Form).body(form(client, body)).dispatch().status()
+    };
+
+    assert_eq!(post(&bob, &speakers, "login=carol"), Status::SeeOther);
+    assert_eq!(post(&bob, &speakers, "login=carol"), Status::Conflict);
+    assert_eq!(post(&bob, &speakers, "login=bob"), Status::Conflict);
+    assert_eq!(post(&bob, &speakers, "login=nobody"), Status::NotFound);
+    assert_eq!(post(&dave, &speakers, "login=dave"), Status::NotFound);
+    assert!(db.emails()[1].contains(r#"Subject: bob (mock) invited you to co-present "Rocket""#));
+
+    // Carol only gets to see the talk once she accepts.
+    let page = carol.get("/").dispatch().body_string().unwrap();
+    assert!(page.contains(r#"invited you to co-present "Rocket"."#));
+    assert_eq!(carol.get(talk_page.clone()).header(Accept::HTML).dispatch().status(), Status::NotFound);
+    assert_eq!(post(&carol, &format!("/invitations/{}", talk), ""), Status::SeeOther);
+    assert_eq!(post(&carol, &format!("/invitations/{}", talk), ""), Status::NotFound);
+
+    // Co-speakers own the talk, but only the primary speaker invites others.
+    let mut res = carol.get("/api/v1/talks").dispatch();
+    let talks: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    assert_eq!(talks[0]["title"], "Rocket");
+    let edit = "title=Rocket+and+Diesel&description=Web+apps&format=talk&duration=30&level=beginner&outline=&notes=&tags=";
+    let res = carol.put(talk_page.clone()).header(ContentType::Form).body(form(&carol, edit)).dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    assert_eq!(post(&carol, &speakers, "login=dave"), Status::Forbidden);
+
+    // Co-speakers can step down.
+    let carol_id = me(&carol).unwrap()["id"].as_i64().unwrap();
+    let res = carol.delete(speakers.clone())
+        .header(ContentType::Form)
+        .body(form(&carol, &format!("user={}", carol_id)))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    assert_eq!(carol.get(talk_page).header(Accept::HTML).dispatch().status(), Status::NotFound);
+}
+
+/// Uploads `image` as the current user's avatar, returning the status.
+fn upload_avatar(client: &Client, image: &[u8]) -> Status {
+    let mut body = format!("--BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+        {}\r\n\
+        --BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"avatar\"; filename=\"me\"\r\n\
+        Content-Type: application/octet-stream\r\n\r\n", csrf_token(client))
+        .into_bytes();
+    body.extend_from_slice(image);
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+    client.post("/profile/avatar")
+        .header(ContentType::with_params("multipart", "form-data", ("boundary", "BOUNDARY")))
+        .body(body)
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn speakers_edit_their_profile() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("speakers_edit_their_profile");
+
+    let bob = log_in(&provider, &db, "bob");
+    let update = |body: &str| {
+        bob.put("/profile").header(ContentType::Form).body(form(&bob, body)).dispatch()
+    };
+
+    let mut res = update("bio=Rustacean&pronouns=he%2Fhim&company=&website=ftp%3A%2F%2Fbob&twitter=%40bob&github=bob");
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    assert!(res.body_string().unwrap().contains("The website must start with http:// or https://."));
+    let res = update("bio=Rustacean&pronouns=he%2Fhim&company=&website=https%3A%2F%2Fbob.example.com&twitter=%40bob&github=bob");
+    assert_eq!(res.status(), Status::SeeOther);
+
+    let page = bob.get("/profile").dispatch().body_string().unwrap();
+    assert!(page.contains("Rustacean") && page.contains(r#"name="twitter" value="bob""#));
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    assert_eq!(upload_avatar(&bob, b"GIF89a, not a supported image"), Status::UnsupportedMediaType);
+    assert_eq!(upload_avatar(&bob, &vec![0xff; 3 * 1024 * 1024]), Status::PayloadTooLarge);
+    assert_eq!(upload_avatar(&bob, png), Status::SeeOther);
+
+    // The avatar is served from the avatar directory, and replaced by the
+    // next upload.
+    let page = bob.get("/profile").dispatch().body_string().unwrap();
+    let start = page.find("/avatars/").expect("avatar is shown");
+    let url = &page[start..page[start..].find('"').unwrap() + start];
+    let mut res = bob.get(url).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.body_bytes().unwrap(), png.to_vec());
+
+    assert_eq!(upload_avatar(&bob, b"\xff\xd8\xff\xe0 a JPEG"), Status::SeeOther);
+    assert_eq!(bob.get(url).dispatch().status(), Status::NotFound);
+    assert_eq!(fs::read_dir(db.avatars()).unwrap().count(), 1);
+}
+
+#[test]
+fn blind_review_hides_presenters() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("blind_review_hides_presenters");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let carol = log_in(&provider, &db, "carol");
+
+    let talk_id = submit_talk(&bob, "Rocket", "Web apps", &[]);
+    let carol_id = me(&carol).unwrap()["id"].as_i64().unwrap();
+    let res = alice.post("/admin/roles")
+        .header(ContentType::Form)
+        .body(form(&alice, &format!("user={}&role=program_chair", carol_id)))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+
+    let talk_path = format!("/talks/{}", talk_id);
+    let api_talk = |client: &Client| -> serde_json::Value {
+        let mut res = client.get(format!("/api/v1/talks/{}", talk_id)).dispatch();
+        serde_json::from_str(&res.body_string().unwrap()).unwrap()
+    };
+    let talk_page = |client: &Client| client.get(talk_path.clone()).header(Accept::HTML).dispatch().body_string().unwrap();
+    let page = |client: &Client, path: &str| client.get(path.to_string()).dispatch().body_string().unwrap();
+
+    // Program chairs know who presents talks...
+    assert!(talk_page(&carol).contains("bob@example.com"));
+    assert!(api_talk(&carol)["presenter"].is_number());
+
+    let res = alice.put("/e/cfp/admin")
+        .header(ContentType::Form)
+        .body(form(&alice, "name=Call+for+Papers&cfp_opens=&cfp_closes=&blind_review=on"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+
+    // ...unless the event is under blind review, which only organizers see
+    // through.
+    assert!(!talk_page(&carol).contains("bob@example.com"));
+    assert!(api_talk(&carol).get("presenter").is_none());
+    assert!(page(&carol, "/admin?presenter=bob").contains("0 talks found."));
+
+    assert!(talk_page(&alice).contains("bob@example.com"));
+    assert!(api_talk(&alice)["presenter"].is_number());
+    assert!(page(&alice, "/admin?presenter=bob").contains("1 talk found."));
+
+    // Presenters always see their own talks in full.
+    assert!(api_talk(&bob)["presenter"].is_number());
+}
+
+#[test]
+fn api_tokens_authenticate_until_revoked() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("api_tokens_authenticate_until_revoked");
+
+    let bob = log_in(&provider, &db, "bob");
+    let mut res = bob.post("/tokens")
+        .header(ContentType::Form)
+        .body(form(&bob, "name=CI"))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let page = res.body_string().unwrap();
+    let start = page.find("<code>").unwrap() + "<code>".len();
+    let token = page[start..start + page[start..].find("</code>").unwrap()].to_string();
+
+    // Clients without a session authenticate with the token instead...
+    let api = client(&provider, &db);
+    let me_with = |token: &str| {
+        let header = Header::new("Authorization", format!("Bearer {}", token));
+        let mut res = api.get("/api/v1/me").header(header).dispatch();
+        (res.status(), res.body_string())
+    };
+    let (status, body) = me_with(&token);
+    assert_eq!(status, Status::Ok);
+    assert!(body.unwrap().contains(r#""login":"bob""#));
+    assert_eq!(me_with("wrong").0, Status::Unauthorized);
+    let page = bob.get("/tokens").dispatch().body_string().unwrap();
+    assert!(page.contains("CI") && !page.contains(&token));
+
+    // ...until it is revoked.
+    let start = page.find(r#"action="/tokens/"#).unwrap() + r#"action=""#.len();
+    let path = page[start..start + page[start..].find('"').unwrap()].to_string();
+    assert_eq!(bob.delete(path).header(csrf_header(&bob)).dispatch().status(), Status::SeeOther);
+    assert_eq!(me_with(&token).0, Status::Unauthorized);
+}
+
+#[test]
+fn sessions_can_be_revoked() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("sessions_can_be_revoked");
+
+    let _alice = log_in(&provider, &db, "alice");
+    let laptop = log_in(&provider, &db, "bob");
+    let phone = log_in(&provider, &db, "bob");
+    let carol = log_in(&provider, &db, "carol");
+
+    // Bob sees both of his sessions, and can revoke the other one.
+    let page = laptop.get("/sessions").dispatch().body_string().unwrap();
+    assert!(page.contains("This browser"));
+    let start = page.find("action=\"/sessions/").expect("a revocable session") + "action=\"/sessions/".len();
+    let id: String = page[start..].chars().take_while(|c| c.is_ascii_digit()).collect();
+    let session = format!("/sessions/{}", id);
+
+    let revoke = |client: &Client, path: &str| {
+        client.delete(path.to_string()).header(csrf_header(client)).dispatch().status()
+    };
+    assert_eq!(revoke(&carol, &session), Status::NotFound);
+    assert!(me(&phone).is_some());
+    assert_eq!(revoke(&laptop, &session), Status::SeeOther);
+    assert!(me(&phone).is_none());
+    assert!(me(&laptop).is_some());
+    assert_eq!(revoke(&laptop, &session), Status::NotFound);
+
+    // Logging out everywhere ends every session, including the current one,
+    // and the CSRF token.
+    let tablet = log_in(&provider, &db, "bob");
+    let token = csrf_token(&laptop);
+    assert_eq!(revoke(&laptop, "/sessions"), Status::SeeOther);
+    assert_ne!(csrf_token(&laptop), token);
+    assert!(me(&laptop).is_none());
+    assert!(me(&tablet).is_none());
+    assert!(me(&carol).is_some());
+
+    // Logging out takes a form, so that a link can't log anyone out. It ends
+    // the current session, and its CSRF token.
+    assert_eq!(carol.get("/logout").dispatch().status(), Status::Ok);
+    assert!(me(&carol).is_some());
+    let res = carol.post("/logout").header(ContentType::Form).dispatch();
+    assert_eq!(res.status(), Status::Forbidden);
+    assert!(me(&carol).is_some());
+    let token = csrf_token(&carol);
+    let res = carol.post("/logout").header(ContentType::Form).body(form(&carol, "")).dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    assert!(me(&carol).is_none());
+    assert_ne!(csrf_token(&carol), token);
+}
+
+#[test]
+fn forms_require_csrf_token() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("forms_require_csrf_token");
+
+    let bob = log_in(&provider, &db, "bob");
+    let set_email = |body: &str| bob.put("/email").header(ContentType::Form).body(body.to_string()).dispatch();
+
+    // Forms from elsewhere don't carry the token, or carry the wrong one...
+    let mut res = set_email("email=forged%40example.org");
+    assert_eq!(res.status(), Status::Forbidden);
+    assert!(res.body_string().unwrap().contains("sent from another site"));
+    let res = set_email("csrf_token=forged&email=forged%40example.org");
+    assert_eq!(res.status(), Status::Forbidden);
+    let res = bob.delete("/sessions").dispatch();
+    assert_eq!(res.status(), Status::Forbidden);
+    assert_eq!(me(&bob).unwrap()["email"], "bob@example.com");
+
+    // ...while the forms of this site do.
+    let res = set_email(&form(&bob, "email=bob%40example.org"));
+    assert_eq!(res.status(), Status::SeeOther);
+
+    // Forms can't send JSON, so the API needs no token.
+    let res = bob.post("/api/v1/talks")
+        .header(ContentType::JSON)
+        .body(r#"{ "title": "Rocket", "description": "Web apps", "format": "Talk", "duration": 30, "level": "Beginner" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+}
+
+/// The CSRF fairing only looks for the token at the start of a form's body,
+/// so every form that posts must send it first, after `_method` if any.
+#[test]
+fn forms_send_the_csrf_token_first() {
+    for entry in fs::read_dir("templates").expect("templates directory") {
+        let path = entry.expect("template").path();
+        if !path.is_file() {
+            continue;
+        }
+        let template = fs::read_to_string(&path).expect("readable template");
+        for form in template.split("<form").skip(1) {
+            let form = &form[..form.find("</form>").expect("closed form")];
+            if !form[..form.find('>').unwrap()].contains(r#"method="post""#) {
+                continue;
+            }
+            let fields: Vec<&str> = form
+                .split(r#"name=""#)
+                .skip(1)
+                .map(|rest| &rest[..rest.find('"').unwrap()])
+                .collect();
+            let first = if fields.first() == Some(&"_method") { fields.get(1) } else { fields.first() };
+            assert_eq!(first, Some(&"csrf_token"), "form fields in {}: {:?}", path.display(), fields);
+        }
+    }
+}
+
+#[test]
+fn errors_are_rendered_as_pages() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("errors_are_rendered_as_pages");
+
+    let bob = log_in(&provider, &db, "bob");
+    let talk = submit_talk(&bob, "Rocket", "Web apps", &[]);
+
+    // Error pages keep the navigation of the logged-in user, while the API
+    // gets JSON.
+    let mut res = bob.get("/talks/999").header(Accept::HTML).dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+    let page = res.body_string().unwrap();
+    assert!(page.contains("404: Not Found") && page.contains("/logout"));
+    let mut res = bob.get("/api/v1/talks/999").dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+    assert!(res.body_string().unwrap().contains(r#""code":404"#));
+
+    // Invalid values in the database are errors, rather than panics.
+    assert!(TalkStatus::try_from(7).is_err());
+    let conn = SqliteConnection::establish(&db.0).expect("test database");
+    sql_query("UPDATE talks SET status = 7").execute(&conn).unwrap();
+    let res = bob.get(format!("/talks/{}", talk)).header(Accept::HTML).dispatch();
+    assert_eq!(res.status(), Status::InternalServerError);
+    assert_eq!(bob.get("/").dispatch().status(), Status::InternalServerError);
+}
+
+#[test]
+fn admins_manage_users() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("admins_manage_users");
+
+    let alice = log_in(&provider, &db, "alice");
+    let bob = log_in(&provider, &db, "bob");
+    let robert = log_in(&provider, &db, "robert");
+    let carol = log_in(&provider, &db, "carol");
+    let dave = log_in(&provider, &db, "dave");
+    let decided = submit_talk(&bob, "Rocket", "Web apps", &[]);
+    submit_talk(&robert, "Diesel", "Databases", &[]);
+    let pending = submit_talk(&dave, "Tera", "Templates", &[]);
+
+    let id = |client: &Client| me(client).unwrap()["id"].as_i64().unwrap();
+    let (alice_id, bob_id, robert_id, carol_id, dave_id) = (id(&alice), id(&bob), id(&robert), id(&carol), id(&dave));
+    let manage = |client: &Client, method: Method, path: String| {
+        client.req(method, path).header(csrf_header(client)).dispatch().status()
+    };
+
+    // Admins see every user, with the number of talks they present.
+    assert_eq!(carol.get("/admin/users").dispatch().status(), Status::NotFound);
+    let page = alice.get("/admin/users").dispatch().body_string().unwrap();
+    assert!(page.contains("(robert)") && page.contains("<td>1</td>"));
+
+    // Admins promote and demote others, but can't demote themselves.
+    let admin = |id: i64| format!("/admin/users/{}/admin", id);
+    assert_eq!(manage(&alice, Method::Put, admin(carol_id)), Status::SeeOther);
+    assert_eq!(carol.get("/admin/users").dispatch().status(), Status::Ok);
+    assert_eq!(manage(&carol, Method::Delete, admin(carol_id)), Status::Conflict);
+    assert_eq!(manage(&alice, Method::Delete, admin(carol_id)), Status::SeeOther);
+    assert_eq!(carol.get("/admin/users").dispatch().status(), Status::NotFound);
+
+    // Disabled users are logged out, and can't log in again until enabled.
+    let disabled = |id: i64| format!("/admin/users/{}/disabled", id);
+    assert_eq!(manage(&alice, Method::Put, disabled(alice_id)), Status::Conflict);
+    assert_eq!(manage(&alice, Method::Put, disabled(bob_id)), Status::SeeOther);
+    assert!(me(&bob).is_none());
+    let browser = client(&provider, &db);
+    let state = start_login(&browser);
+    assert_eq!(callback(&browser, "bob", &state).status(), Status::Forbidden);
+    assert_eq!(manage(&alice, Method::Delete, disabled(bob_id)), Status::SeeOther);
+    let bob = log_in(&provider, &db, "bob");
+
+    // Merging moves the duplicate's talks, and deletes the duplicate.
+    let merge = |target: &str| {
+        alice.post(format!("/admin/users/{}/merge", robert_id))
+            .header(ContentType::Form)
+            .body(form(&alice, &format!("target={}", target)))
+            .dispatch()
+            .status()
+    };
+    assert_eq!(merge("nobody"), Status::UnprocessableEntity);
+    assert_eq!(merge("robert"), Status::Conflict);
+    assert_eq!(merge("bob"), Status::SeeOther);
+    assert!(me(&robert).is_none());
+    let index = bob.get("/").dispatch().body_string().unwrap();
+    assert!(index.contains("Rocket") && index.contains("Diesel"));
+
+    // Users are deleted along with their pending talks, but users whose talks
+    // were decided on, or who reviewed them, are kept.
+    let res = alice.post("/admin/roles")
+        .header(ContentType::Form)
+        .body(form(&alice, &format!("user={}&role=reviewer", carol_id)))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    let res = carol.put(format!("/talks/{}/review", decided))
+        .header(ContentType::Form)
+        .body(form(&carol, "score=4&comments=Nice"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    let res = alice.put(format!("/api/v1/talks/{}/status", decided))
+        .header(ContentType::JSON)
+        .body(r#"{ "status": "Approved" }"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let user = |id: i64| format!("/admin/users/{}", id);
+    assert_eq!(manage(&alice, Method::Delete, user(bob_id)), Status::Conflict);
+    assert_eq!(manage(&alice, Method::Delete, user(carol_id)), Status::Conflict);
+    assert_eq!(manage(&alice, Method::Delete, user(alice_id)), Status::Conflict);
+    assert_eq!(manage(&alice, Method::Delete, user(dave_id)), Status::SeeOther);
+    assert!(me(&dave).is_none());
+    let res = alice.get(format!("/talks/{}", pending)).header(Accept::HTML).dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+    assert_eq!(manage(&alice, Method::Delete, user(dave_id)), Status::NotFound);
+
+    // All of it is recorded in the audit log.
+    let log = alice.get("/admin/audit?actor=alice").dispatch().body_string().unwrap();
+    for action in &[
+        "made carol an admin",
+        "revoked admin from carol",
+        "disabled bob",
+        "enabled bob",
+        "merged robert into bob",
+        "deleted dave",
+    ] {
+        assert!(log.contains(action), "missing '{}'", action);
+    }
+}
+
+#[test]
+fn admins_are_created_from_the_command_line() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("admins_are_created_from_the_command_line");
+    let mut config = config(&provider, &db);
+    config.set_extra("auto_admin", false);
+    let cli = |command: &str, args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        cli::run(build(rocket::custom(config.clone())), command, &args)
+    };
+
+    // Without auto-admin, the first user to log in is just a speaker...
+    let alice = Client::new(build(rocket::custom(config.clone()))).expect("valid rocket instance");
+    let state = start_login(&alice);
+    assert_eq!(callback(&alice, "alice", &state).status(), Status::SeeOther);
+    assert_eq!(me(&alice).unwrap()["roles"]["organizer"], false);
+
+    // ...until an admin is created from the command line.
+    assert_eq!(cli("create-admin", &["alice"]), 0);
+    assert_eq!(me(&alice).unwrap()["roles"]["organizer"], true);
+    assert_eq!(cli("create-admin", &["zoe"]), 0);
+    assert_eq!(cli("create-admin", &["not a login"]), 1);
+    assert_eq!(cli("create-admin", &[]), 2);
+    assert_eq!(cli("list-users", &[]), 0);
+    assert_eq!(cli("migrate", &[]), 0);
+    assert_eq!(cli("reset-password", &["zoe"]), 0);
+    assert_eq!(cli("reset-password", &["nobody"]), 1);
+
+    // Without a database, commands fail rather than panic.
+    let mut database = Table::new();
+    database.insert("url".into(), "/nonexistent/cfp.sqlite".into());
+    let mut databases = Table::new();
+    databases.insert("cfp".into(), database.into());
+    let mut broken = config.clone();
+    broken.set_extra("databases", databases);
+    let broken_cli = |command: &str| cli::run(build(rocket::custom(broken.clone())), command, &[]);
+    assert_eq!(broken_cli("migrate"), 1);
+    assert_eq!(broken_cli("list-users"), 1);
+
+    // Program chairs decide on talks from the command line too.
+    let bob = log_in(&provider, &db, "bob");
+    let id = submit_talk(&bob, "Rocket", "Web apps", &[]).to_string();
+    let talk = id.as_str();
+    assert_eq!(cli("set-status", &[talk, "approved", "--as", "bob"]), 1);
+    assert_eq!(cli("set-status", &[talk, "maybe", "--as", "alice"]), 1);
+    assert_eq!(cli("set-status", &[talk, "approved"]), 2);
+    assert_eq!(cli("set-status", &[talk, "approved", "--as", "alice"]), 0);
+    let mut res = bob.get(format!("/api/v1/talks/{}", talk)).dispatch();
+    let json: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    assert_eq!(json["status"], "Approved");
+}
+
+#[test]
+fn invalid_submissions_are_rendered_with_errors() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("invalid_submissions_are_rendered_with_errors");
+
+    let bob = log_in(&provider, &db, "bob");
+    let submit = |body: &str| {
+        bob.post("/talks")
+            .header(ContentType::Form)
+            .body(form(&bob, body))
+            .dispatch()
+    };
+
+    // Invalid submissions are refused, and the form is filled back in.
+    let mut res = submit("title=Rocket&description=Web+apps&format=lightning&duration=45&level=beginner&outline=&notes=&tags=");
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    let page = res.body_string().unwrap();
+    assert!(page.contains("must last between 5 and 10 minutes"));
+    assert!(page.contains(r#"value="Rocket""#));
+    let mut res = submit("title=Rocket&description=Web+apps&format=keynote&duration=30&level=beginner&outline=&notes=&tags=");
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    assert!(res.body_string().unwrap().contains("Unknown talk format"));
+    assert!(me(&bob).is_some());
+    assert_eq!(bob.get("/api/v1/talks").dispatch().body_string().unwrap(), "[]");
+
+    // Valid ones keep every detail, with the tags normalized.
+    let res = submit("title=Rocket&description=Web+apps&format=workshop&duration=90&level=advanced&outline=Intro&notes=Needs+a+projector&tags=Rust,+web,+rust");
+    assert_eq!(res.status(), Status::SeeOther);
+    let mut res = bob.get("/api/v1/talks").dispatch();
+    let talks: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    assert_eq!(talks[0]["format"], "Workshop");
+    assert_eq!(talks[0]["duration"], 90);
+    assert_eq!(talks[0]["level"], "Advanced");
+    assert_eq!(talks[0]["notes"], "Needs a projector");
+    assert_eq!(talks[0]["tags"], json!(["rust", "web"]));
+}
+
+#[test]
+fn talks_are_served_as_html_or_json() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("talks_are_served_as_html_or_json");
+
+    let bob = log_in(&provider, &db, "bob");
+    let talk = format!("/talks/{}", submit_talk(&bob, "Rocket", "Web apps", &["rust"]));
+
+    let mut res = bob.get(talk.clone()).header(Accept::HTML).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.content_type(), Some(ContentType::HTML));
+    assert!(res.body_string().unwrap().contains("<h1>Rocket</h1>"));
+
+    let mut res = bob.get(talk.clone()).header(Accept::JSON).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.content_type(), Some(ContentType::JSON));
+    let json: serde_json::Value = serde_json::from_str(&res.body_string().unwrap()).unwrap();
+    assert_eq!(json["title"], "Rocket");
+    assert_eq!(json["tags"], json!(["rust"]));
+
+    // Errors are negotiated the same way.
+    let mut res = bob.get("/talks/999").header(Accept::JSON).dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+    assert!(res.body_string().unwrap().contains(r#""code":404"#));
+    let anonymous = client(&provider, &db);
+    let res = anonymous.get("/api/v1/talks").dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    assert_eq!(res.content_type(), Some(ContentType::JSON));
+}
+
+#[test]
+fn github_identities_are_keyed_on_the_account_id() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("github_identities_are_keyed_on_the_account_id");
+
+    let alice = log_in(&provider, &db, "alice");
+    let id = me(&alice).unwrap()["id"].clone();
+    let conn = SqliteConnection::establish(&db.0).expect("test database");
+    let subjects = || -> Vec<String> {
+        sql_query("SELECT subject FROM user_identities ORDER BY id")
+            .load::<Subject>(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|row| row.subject)
+            .collect()
+    };
+    assert_eq!(subjects(), vec![account_id("alice").to_string()]);
+
+    // Identities stored under the login before are moved to the account ID
+    // when their owner logs in.
+    sql_query("UPDATE user_identities SET subject = 'login:alice'").execute(&conn).unwrap();
+    let alice = log_in(&provider, &db, "alice");
+    assert_eq!(me(&alice).unwrap()["id"], id);
+    assert_eq!(subjects(), vec![account_id("alice").to_string()]);
+}
+
+#[derive(QueryableByName)]
+struct Subject {
+    #[sql_type = "Text"]
+    subject: String,
+}
+
+#[test]
+fn local_logins_are_throttled_and_passwords_reset() {
+    let provider = MockProvider::start();
+    let db = TestDb::new("local_logins_are_throttled_and_passwords_reset");
+
+    let laptop = client(&provider, &db);
+    let res = laptop.post("/register")
+        .header(ContentType::Form)
+        .body(form(&laptop, "login=alice&name=Alice&email=alice%40example.com&password=correct+horse&password_confirm=correct+horse"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    assert_eq!(me(&laptop).unwrap()["login"], "alice");
+
+    // The address given when registering gets nothing until it is confirmed.
+    assert_eq!(res.headers().get_one("Location"), Some("/email"));
+    assert_eq!(me(&laptop).unwrap()["email_confirmed"], false);
+    let res = laptop.post("/password/reset")
+        .header(ContentType::Form)
+        .body(form(&laptop, "login=alice"))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert!(db.emails().is_empty());
+    confirm_email(&laptop);
+
+    // Every attempt claims another address in `X-Real-IP`, which must not
+    // get around the throttling.
+    let attempts = Cell::new(0);
+    let log_in_from = |client: &Client, ip: &str, password: &str| {
+        attempts.set(attempts.get() + 1);
+        let mut res = client.post("/login")
+            .remote(SocketAddr::new(ip.parse().unwrap(), 8000))
+            .header(ContentType::Form)
+            .header(Header::new("X-Real-IP", format!("192.0.2.{}", attempts.get())))
+            .body(form(client, &format!("login=alice&password={}", password)))
+            .dispatch();
+        let status = res.status();
+        (status, res.body_string().unwrap_or_default())
+    };
+
+    // Too many wrong passwords throttle the client they came from...
+    let attacker = client(&provider, &db);
+    for _ in 0..5 {
+        let (status, page) = log_in_from(&attacker, "10.0.0.1", "wrong");
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert!(page.contains("Invalid login or password."));
+    }
+    let (status, page) = log_in_from(&attacker, "10.0.0.1", "correct+horse");
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert!(page.contains("Too many failed logins."));
+
+    // ...but don't lock the account for everyone else.
+    let phone = client(&provider, &db);
+    assert_eq!(log_in_from(&phone, "10.0.0.2", "correct+horse").0, Status::SeeOther);
+    assert!(me(&phone).is_some());
+
+    // Resetting the password ends every other session.
+    let res = attacker.post("/password/reset")
+        .header(ContentType::Form)
+        .body(form(&attacker, "login=alice"))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let email = db.emails().pop().expect("a reset email");
+    let start = email.find("/password/reset/").expect("a reset link") + "/password/reset/".len();
+    let token: String = email[start..].chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+    let reset = format!("/password/reset/{}", token);
+
+    let tablet = client(&provider, &db);
+    assert_eq!(tablet.get(reset.clone()).dispatch().status(), Status::Ok);
+    let res = tablet.post(reset.clone())
+        .header(ContentType::Form)
+        .body(form(&tablet, "password=battery+staple&password_confirm=battery+staple"))
+        .dispatch();
+    assert_eq!(res.status(), Status::SeeOther);
+    assert!(me(&tablet).is_some());
+    assert!(me(&laptop).is_none());
+    assert!(me(&phone).is_none());
+    assert_eq!(tablet.get(reset).dispatch().status(), Status::NotFound);
+
+    let other = client(&provider, &db);
+    assert_eq!(log_in_from(&other, "10.0.0.3", "correct+horse").0, Status::UnprocessableEntity);
+    assert_eq!(log_in_from(&other, "10.0.0.3", "battery+staple").0, Status::SeeOther);
+}
@@ -0,0 +1,170 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel;
+use diesel::prelude::*;
+use diesel::result::Error;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::request::LenientForm;
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+use sha2::{Digest, Sha256};
+
+use csrf::CsrfToken;
+use error::CfpResult;
+use schema::api_tokens;
+use user::User;
+use DbConn;
+
+/// The prefix of every API token, making tokens easy to recognize (e.g. when
+/// scanning for leaked secrets).
+const TOKEN_PREFIX: &str = "cfp_";
+
+/// Generates a new random API token.
+fn generate_token() -> String {
+    let random: String = thread_rng().sample_iter(&Alphanumeric).take(40).collect();
+    format!("{}{}", TOKEN_PREFIX, random)
+}
+
+/// Hashes a token for storage. Tokens are long random strings, so a fast
+/// unsalted hash is sufficient; only the hash is ever stored.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// API token data retrieved from the database. The token itself is not
+/// stored, only its hash.
+#[derive(Queryable, Serialize)]
+pub struct ApiToken {
+    pub id: i32,
+    pub user: i32,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+/// New API token data to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "api_tokens"]
+struct NewApiToken<'a> {
+    pub user: i32,
+    pub name: &'a str,
+    pub token_hash: &'a str,
+    pub created_at: NaiveDateTime,
+}
+
+impl ApiToken {
+    /// Creates a new token named `name` for `user`. Returns the token in
+    /// plain text; this is the only time it is available.
+    pub fn create(conn: &DbConn, user: &User, name: &str) -> Result<String, Error> {
+        let token = generate_token();
+        diesel::insert_into(api_tokens::table)
+            .values(NewApiToken {
+                user: user.id,
+                name,
+                token_hash: &hash_token(&token),
+                created_at: Utc::now().naive_utc(),
+            })
+            .execute(&conn.0)?;
+
+        Ok(token)
+    }
+
+    /// Retrieve all tokens belonging to `user`
+    pub fn get_for_user(conn: &DbConn, user: &User) -> Result<Vec<ApiToken>, Error> {
+        api_tokens::table
+            .filter(api_tokens::user.eq(user.id))
+            .order(api_tokens::created_at.desc())
+            .load(&conn.0)
+    }
+
+    /// Revokes the token with the given `id`, which must belong to `user`.
+    /// Returns whether a token was revoked.
+    pub fn revoke(conn: &DbConn, user: &User, id: i32) -> Result<bool, Error> {
+        let deleted = diesel::delete(
+            api_tokens::table.filter(api_tokens::id.eq(id).and(api_tokens::user.eq(user.id))),
+        )
+        .execute(&conn.0)?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Looks up the user owning `token`, and records that the token was used.
+    pub fn authenticate(conn: &DbConn, token: &str) -> Result<Option<User>, Error> {
+        let hash = hash_token(token);
+        let id: Option<i32> = api_tokens::table
+            .filter(api_tokens::token_hash.eq(&hash))
+            .select(api_tokens::user)
+            .first(&conn.0)
+            .optional()?;
+
+        match id {
+            Some(id) => {
+                diesel::update(api_tokens::table.filter(api_tokens::token_hash.eq(&hash)))
+                    .set(api_tokens::last_used_at.eq(Utc::now().naive_utc()))
+                    .execute(&conn.0)?;
+                User::get(conn, id).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Renders the token management page. `new_token` is shown once, right after
+/// it was created.
+fn render_tokens(
+    conn: &DbConn,
+    user: &User,
+    csrf: &CsrfToken,
+    new_token: Option<&str>,
+) -> CfpResult<Template> {
+    let tokens = ApiToken::get_for_user(conn, user)?;
+    Ok(Template::render("tokens", json!({
+        "user": user,
+        "tokens": tokens,
+        "new_token": new_token,
+        "csrf_token": csrf,
+    })))
+}
+
+/// Lists the current user's API tokens.
+#[get("/tokens")]
+fn list_tokens(conn: DbConn, user: User, csrf: CsrfToken) -> CfpResult<Template> {
+    render_tokens(&conn, &user, &csrf, None)
+}
+
+/// A struct containing the name of a new token.
+#[derive(FromForm)]
+struct NewToken {
+    name: String,
+}
+
+/// Mints a new API token and shows it to the user.
+#[post("/tokens", data = "<token>")]
+fn create_token(token: LenientForm<NewToken>, conn: DbConn, user: User, csrf: CsrfToken) -> CfpResult<Template> {
+    let name = token.name.trim();
+    if name.is_empty() {
+        return Err(Status::UnprocessableEntity.into());
+    }
+    let token = ApiToken::create(&conn, &user, name)?;
+    render_tokens(&conn, &user, &csrf, Some(&token))
+}
+
+/// Revokes one of the current user's API tokens.
+#[delete("/tokens/<id>")]
+fn revoke_token(id: i32, conn: DbConn, user: User) -> CfpResult<Redirect> {
+    if !ApiToken::revoke(&conn, &user, id)? {
+        return Err(Status::NotFound.into());
+    }
+    Ok(Redirect::to("/tokens"))
+}
+
+/// Returns a fairing that mounts the API token management routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("API Tokens", |rocket| {
+        Ok(rocket.mount("/", routes![list_tokens, create_token, revoke_token]))
+    })
+}
@@ -1,72 +1,440 @@
+use std::collections::HashMap;
+
 use diesel;
+use diesel::dsl::sql;
 use diesel::prelude::*;
 use diesel::result::Error;
+use diesel::sql_types::BigInt;
 
-use schema::users;
+use auth::AutoAdmin;
+use role::{Admin, Role, Roles};
+use schema::{
+    admin_events, api_tokens, event_admins, local_credentials, password_resets, profiles, reviews, schedule_entries, sessions,
+    talk_events, talk_speakers, talks, user_identities, user_roles, users,
+};
+use talk::TalkStatus;
 use DbConn;
 
-/// User data retrieved from the database
-#[derive(Debug, Clone, Queryable, Serialize)]
+/// User data retrieved from the database, together with the user's roles
+#[derive(Debug, Clone, Serialize)]
 pub struct User {
     pub id: i32,
-    pub github_id: String,
+    pub login: String,
     pub email: String,
     pub name: String,
-    pub is_admin: bool,
+    /// Whether the user has confirmed that `email` is where they want to be
+    /// notified.
+    pub email_confirmed: bool,
+    /// Whether an organizer disabled the account. Disabled users can't log
+    /// in, and are refused by the `User` request guard.
+    pub disabled: bool,
+    pub roles: Roles,
 }
 
+/// The columns of a row of the `users` table
+type UserRow = (i32, String, String, String, bool, bool);
+
 /// New user data to be inserted into the database
 #[derive(Insertable)]
 #[table_name = "users"]
 pub struct NewUser<'a> {
-    pub github_id: &'a str,
+    pub login: &'a str,
     pub email: &'a str,
     pub name: &'a str,
-    pub is_admin: bool,
+    pub email_confirmed: bool,
+}
+
+/// The identity of a user at an OAuth provider, as reported by the provider.
+#[derive(Debug)]
+pub struct Identity {
+    /// A stable identifier of the user at the provider.
+    pub subject: String,
+    /// The identifier the provider's identities used to be stored under, if
+    /// it changed. Identities still stored under it are moved to `subject`.
+    pub legacy_subject: Option<String>,
+    pub login: String,
+    pub name: String,
+    pub email: Option<String>,
+}
+
+/// A provider identity linked to a user, retrieved from the database
+#[derive(Debug, Queryable, Serialize)]
+pub struct UserIdentity {
+    pub id: i32,
+    pub user: i32,
+    pub provider: String,
+    pub subject: String,
+}
+
+/// New identity data to be inserted into the database
+#[derive(Insertable)]
+#[table_name = "user_identities"]
+struct NewUserIdentity<'a> {
+    pub user: i32,
+    pub provider: &'a str,
+    pub subject: &'a str,
+}
+
+/// The outcome of deleting a user.
+#[derive(Debug, PartialEq)]
+pub enum DeleteOutcome {
+    /// The user and their pending talks were deleted.
+    Deleted,
+    /// There is no such user.
+    NotFound,
+    /// The user presents talks that were already decided on, reviewed them,
+    /// changed the status of other talks, or took admin actions, and is kept
+    /// for the record.
+    HasHistory,
 }
 
 impl User {
-    /// Get a user, or create it if it does not exist.
+    /// Get the user with the given identity at `provider`, or create it if
+    /// it does not exist.
     ///
-    /// Existence is determined by the `github_login`.
-    /// `email` and `name` are only set for "new" users.
+    /// `login`, `email` and `name` are only set for "new" users. If the login
+    /// is already taken by another user, a suffix is added to make it unique.
+    /// Existing users without an email address get the one reported by the
+    /// provider, if any. New users are made admins according to `auto_admin`.
     pub fn get_or_create(
         conn: &DbConn,
-        github_login: &str,
-        name: &str,
-        is_admin: bool,
+        provider: &str,
+        identity: &Identity,
+        auto_admin: &AutoAdmin,
     ) -> Result<i32, Error> {
-        let existing_user = users::table
-            .filter(users::github_id.eq(github_login))
-            .first(&conn.0)
-            .optional()?;
+        conn.0.transaction::<_, Error, _>(|| {
+            let mut existing_user = User::identity_owner(conn, provider, &identity.subject)?;
+            if let (None, Some(legacy)) = (existing_user, identity.legacy_subject.as_ref()) {
+                existing_user = User::identity_owner(conn, provider, legacy)?;
+                if existing_user.is_some() {
+                    diesel::update(user_identities::table
+                        .filter(user_identities::provider.eq(provider))
+                        .filter(user_identities::subject.eq(legacy)))
+                        .set(user_identities::subject.eq(&identity.subject))
+                        .execute(&conn.0)?;
+                }
+            }
 
-        if let Some(User { id, .. }) = existing_user {
-            return Ok(id);
-        }
+            if let Some(id) = existing_user {
+                if let Some(ref email) = identity.email {
+                    diesel::update(users::table.filter(users::id.eq(id).and(users::email.eq(""))))
+                        .set(users::email.eq(email))
+                        .execute(&conn.0)?;
+                }
+                return Ok(id);
+            }
+
+            let login = User::unique_login(conn, &identity.login, provider)?;
+            let id = User::create(conn, &NewUser {
+                login: &login,
+                email: identity.email.as_ref().map_or("", String::as_str),
+                name: &identity.name,
+                email_confirmed: false,
+            }, false)?;
+            auto_admin.apply(conn, id)?;
+
+            diesel::insert_into(user_identities::table)
+                .values(NewUserIdentity { user: id, provider, subject: &identity.subject })
+                .execute(&conn.0)?;
 
+            Ok(id)
+        })
+    }
+
+    /// Looks up the user the identity `subject` at `provider` is linked to.
+    fn identity_owner(conn: &DbConn, provider: &str, subject: &str) -> Result<Option<i32>, Error> {
+        user_identities::table
+            .filter(user_identities::provider.eq(provider))
+            .filter(user_identities::subject.eq(subject))
+            .select(user_identities::user)
+            .first(&conn.0)
+            .optional()
+    }
+
+    /// Inserts a new user, who is a speaker and, if `is_admin` is set, an
+    /// organizer. Returns the ID of the new user.
+    pub fn create(conn: &DbConn, user: &NewUser, is_admin: bool) -> Result<i32, Error> {
         diesel::insert_into(users::table)
-            .values(NewUser {
-                github_id: github_login,
-                email: "",
-                name,
-                is_admin,
-            })
+            .values(user)
             .execute(&conn.0)?;
 
-        users::table
-            .filter(users::github_id.eq(github_login))
+        let id = users::table
+            .filter(users::login.eq(user.login))
             .select(users::id)
+            .first(&conn.0)?;
+
+        Role::grant_initial(conn, id, is_admin)?;
+
+        Ok(id)
+    }
+
+    /// Completes a row of the `users` table with the user's roles.
+    fn from_row(conn: &DbConn, (id, login, email, name, email_confirmed, disabled): UserRow) -> Result<User, Error> {
+        let roles = Roles::from_granted(&Role::get_granted(conn, id)?);
+        Ok(User { id, login, email, name, email_confirmed, disabled, roles })
+    }
+
+    /// Sets `user`'s email address to `email`, and marks it as confirmed.
+    pub fn confirm_email(conn: &DbConn, user: &User, email: &str) -> Result<(), Error> {
+        diesel::update(users::table.filter(users::id.eq(user.id)))
+            .set((users::email.eq(email), users::email_confirmed.eq(true)))
+            .execute(&conn.0)?;
+
+        Ok(())
+    }
+
+    /// Get a given user by `login`, if it exists.
+    pub fn get_by_login(conn: &DbConn, login: &str) -> Result<Option<User>, Error> {
+        let row: Option<UserRow> = users::table
+            .filter(users::login.eq(login))
             .first(&conn.0)
+            .optional()?;
+
+        match row {
+            Some(row) => User::from_row(conn, row).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `login` if no user has it yet, and otherwise `login` suffixed
+    /// with the provider name and, if necessary, a number.
+    fn unique_login(conn: &DbConn, login: &str, provider: &str) -> Result<String, Error> {
+        let candidates = ::std::iter::once(login.to_string())
+            .chain(::std::iter::once(format!("{}-{}", login, provider)))
+            .chain((2..).map(|n| format!("{}-{}-{}", login, provider, n)));
+
+        for candidate in candidates {
+            let taken: i64 = users::table
+                .filter(users::login.eq(&candidate))
+                .count()
+                .get_result(&conn.0)?;
+            if taken == 0 {
+                return Ok(candidate);
+            }
+        }
+
+        unreachable!("ran out of login candidates")
+    }
+
+    /// Links the identity at `provider` to `user`. Returns `false` if the
+    /// identity is already linked to a different user.
+    pub fn link_identity(
+        conn: &DbConn,
+        user: &User,
+        provider: &str,
+        identity: &Identity,
+    ) -> Result<bool, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            match User::identity_owner(conn, provider, &identity.subject)? {
+                Some(owner) => Ok(owner == user.id),
+                None => {
+                    diesel::insert_into(user_identities::table)
+                        .values(NewUserIdentity { user: user.id, provider, subject: &identity.subject })
+                        .execute(&conn.0)?;
+                    Ok(true)
+                }
+            }
+        })
+    }
+
+    /// Retrieve all provider identities linked to `user`
+    pub fn get_identities(conn: &DbConn, user: &User) -> Result<Vec<UserIdentity>, Error> {
+        user_identities::table
+            .filter(user_identities::user.eq(user.id))
+            .order(user_identities::provider)
+            .load(&conn.0)
     }
 
     /// Get a given user by `id`. Returns an error when the user does not exist
     pub fn get(conn: &DbConn, id: i32) -> Result<User, Error> {
-        users::table.filter(users::id.eq(id)).first(&conn.0)
+        let row = users::table.filter(users::id.eq(id)).first(&conn.0)?;
+        User::from_row(conn, row)
+    }
+
+    /// Retrieve all users, ordered by login
+    pub fn get_all(conn: &DbConn) -> Result<Vec<User>, Error> {
+        let rows: Vec<UserRow> = users::table.order(users::login).load(&conn.0)?;
+        rows.into_iter().map(|row| User::from_row(conn, row)).collect()
     }
 
     /// Return the number of existing users.
     pub fn count(conn: &DbConn) -> Result<i64, Error> {
         users::table.count().get_result(&conn.0)
     }
+
+    /// Counts the talks each user presents, keyed by user ID. Users without
+    /// talks are missing from the map.
+    pub fn get_talk_counts(conn: &DbConn, _admin: &Admin) -> Result<HashMap<i32, i64>, Error> {
+        let counts: Vec<(i32, i64)> = talks::table
+            .group_by(talks::presenter)
+            .select((talks::presenter, sql::<BigInt>("COUNT(*)")))
+            .load(&conn.0)?;
+        Ok(counts.into_iter().collect())
+    }
+
+    /// Disables or re-enables the user with the given `id`. Disabling a user
+    /// also ends their sessions. Returns whether the user exists.
+    pub fn set_disabled(conn: &DbConn, _admin: &Admin, id: i32, disabled: bool) -> Result<bool, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let updated = diesel::update(users::table.filter(users::id.eq(id)))
+                .set(users::disabled.eq(disabled))
+                .execute(&conn.0)?;
+            if disabled {
+                diesel::delete(sessions::table.filter(sessions::user.eq(id))).execute(&conn.0)?;
+            }
+            Ok(updated > 0)
+        })
+    }
+
+    /// Merges the duplicate account `source` into `target`, which takes over
+    /// the talks, reviews, history, roles, event administration, linked
+    /// identities and API tokens of `source`. Where only one of them can be
+    /// kept, such as two reviews of the same talk, a password or a profile,
+    /// `target` keeps its own. `source` is deleted afterwards.
+    pub fn merge(conn: &DbConn, admin: &Admin, source: &User, target: &User) -> Result<(), Error> {
+        let (from, to) = (source.id, target.id);
+        conn.0.transaction::<_, Error, _>(|| {
+            diesel::update(talks::table.filter(talks::presenter.eq(from)))
+                .set(talks::presenter.eq(to))
+                .execute(&conn.0)?;
+            diesel::update(talk_events::table.filter(talk_events::actor.eq(from)))
+                .set(talk_events::actor.eq(to))
+                .execute(&conn.0)?;
+            diesel::update(admin_events::table.filter(admin_events::actor.eq(from)))
+                .set(admin_events::actor.eq(to))
+                .execute(&conn.0)?;
+
+            let reviewed: Vec<i32> = reviews::table
+                .filter(reviews::reviewer.eq(to))
+                .select(reviews::talk)
+                .load(&conn.0)?;
+            diesel::update(reviews::table.filter(reviews::reviewer.eq(from).and(reviews::talk.ne_all(reviewed))))
+                .set(reviews::reviewer.eq(to))
+                .execute(&conn.0)?;
+
+            // `target` no longer needs to be invited to talks it now presents.
+            let presented: Vec<i32> = talks::table
+                .filter(talks::presenter.eq(to))
+                .select(talks::id)
+                .load(&conn.0)?;
+            diesel::delete(talk_speakers::table.filter(talk_speakers::user.eq(to).and(talk_speakers::talk.eq_any(&presented))))
+                .execute(&conn.0)?;
+            let co_presented: Vec<i32> = talk_speakers::table
+                .filter(talk_speakers::user.eq(to))
+                .select(talk_speakers::talk)
+                .load(&conn.0)?;
+            diesel::update(talk_speakers::table.filter(
+                talk_speakers::user.eq(from)
+                    .and(talk_speakers::talk.ne_all(co_presented))
+                    .and(talk_speakers::talk.ne_all(&presented)),
+            ))
+            .set(talk_speakers::user.eq(to))
+            .execute(&conn.0)?;
+
+            let administered: Vec<i32> = event_admins::table
+                .filter(event_admins::user.eq(to))
+                .select(event_admins::event)
+                .load(&conn.0)?;
+            diesel::update(event_admins::table.filter(
+                event_admins::user.eq(from).and(event_admins::event.ne_all(administered)),
+            ))
+            .set(event_admins::user.eq(to))
+            .execute(&conn.0)?;
+
+            for role in Role::get_granted(conn, from)? {
+                Role::grant(conn, admin, to, role)?;
+            }
+            diesel::update(user_identities::table.filter(user_identities::user.eq(from)))
+                .set(user_identities::user.eq(to))
+                .execute(&conn.0)?;
+            diesel::update(api_tokens::table.filter(api_tokens::user.eq(from)))
+                .set(api_tokens::user.eq(to))
+                .execute(&conn.0)?;
+
+            let has_password: i64 = local_credentials::table.find(to).count().get_result(&conn.0)?;
+            if has_password == 0 {
+                diesel::update(local_credentials::table.filter(local_credentials::user.eq(from)))
+                    .set(local_credentials::user.eq(to))
+                    .execute(&conn.0)?;
+            }
+            let has_profile: i64 = profiles::table.find(to).count().get_result(&conn.0)?;
+            if has_profile == 0 {
+                diesel::update(profiles::table.filter(profiles::user.eq(from)))
+                    .set(profiles::user.eq(to))
+                    .execute(&conn.0)?;
+            }
+
+            User::delete_account(conn, from)
+        })
+    }
+
+    /// Deletes the user with the given `id` along with their pending talks,
+    /// their reviews of pending talks, and everything else that only concerns
+    /// them. Users who present or reviewed talks that were already decided
+    /// on, who changed the status of other talks, or who appear in the audit
+    /// log as admins, can't be deleted; they can be disabled instead.
+    pub fn delete(conn: &DbConn, _admin: &Admin, id: i32) -> Result<DeleteOutcome, Error> {
+        conn.0.transaction::<_, Error, _>(|| {
+            let exists: i64 = users::table.find(id).count().get_result(&conn.0)?;
+            if exists == 0 {
+                return Ok(DeleteOutcome::NotFound);
+            }
+
+            let pending: Vec<i32> = talks::table
+                .filter(talks::presenter.eq(id).and(talks::status.eq(TalkStatus::Pending as i32)))
+                .select(talks::id)
+                .load(&conn.0)?;
+            let decided: i64 = talks::table
+                .filter(talks::presenter.eq(id).and(talks::status.ne(TalkStatus::Pending as i32)))
+                .count()
+                .get_result(&conn.0)?;
+            let decided_reviews: i64 = reviews::table
+                .inner_join(talks::table)
+                .filter(reviews::reviewer.eq(id).and(talks::status.ne(TalkStatus::Pending as i32)))
+                .count()
+                .get_result(&conn.0)?;
+            let status_changes: i64 = talk_events::table
+                .filter(talk_events::actor.eq(id).and(talk_events::talk.ne_all(&pending)))
+                .count()
+                .get_result(&conn.0)?;
+            let admin_actions: i64 = admin_events::table
+                .filter(admin_events::actor.eq(id))
+                .count()
+                .get_result(&conn.0)?;
+            if decided > 0 || decided_reviews > 0 || status_changes > 0 || admin_actions > 0 {
+                return Ok(DeleteOutcome::HasHistory);
+            }
+
+            diesel::delete(reviews::table.filter(reviews::talk.eq_any(&pending))).execute(&conn.0)?;
+            diesel::delete(talk_events::table.filter(talk_events::talk.eq_any(&pending))).execute(&conn.0)?;
+            diesel::delete(talk_speakers::table.filter(talk_speakers::talk.eq_any(&pending))).execute(&conn.0)?;
+            diesel::delete(schedule_entries::table.filter(schedule_entries::talk.eq_any(&pending))).execute(&conn.0)?;
+            // Copies of the talks resubmitted to other events stay.
+            diesel::update(talks::table.filter(talks::resubmitted_from.eq_any(&pending)))
+                .set(talks::resubmitted_from.eq(None::<i32>))
+                .execute(&conn.0)?;
+            diesel::delete(talks::table.filter(talks::id.eq_any(&pending))).execute(&conn.0)?;
+
+            User::delete_account(conn, id)?;
+            Ok(DeleteOutcome::Deleted)
+        })
+    }
+
+    /// Deletes the user with the given `id`, and every row that belongs to
+    /// them alone. Their talks and status changes must have been dealt with.
+    fn delete_account(conn: &DbConn, id: i32) -> Result<(), Error> {
+        diesel::delete(reviews::table.filter(reviews::reviewer.eq(id))).execute(&conn.0)?;
+        diesel::delete(talk_speakers::table.filter(talk_speakers::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(event_admins::table.filter(event_admins::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(user_roles::table.filter(user_roles::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(user_identities::table.filter(user_identities::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(api_tokens::table.filter(api_tokens::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(local_credentials::table.filter(local_credentials::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(password_resets::table.filter(password_resets::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(profiles::table.filter(profiles::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(sessions::table.filter(sessions::user.eq(id))).execute(&conn.0)?;
+        diesel::delete(users::table.filter(users::id.eq(id))).execute(&conn.0)?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,197 @@
+use diesel::result::Error;
+use diesel::Connection;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::request::{LenientForm, State};
+use rocket::response::Redirect;
+use rocket_contrib::templates::Template;
+
+use audit::AdminEvent;
+use csrf::CsrfToken;
+use error::{CfpError, CfpResult};
+use profile::{remove_avatar_file, AvatarDir, Profile};
+use role::{Admin, Role};
+use user::{DeleteOutcome, User};
+use DbConn;
+
+/// Retrieves the user with the given `id`, failing with a `404 Not Found`
+/// if there is none.
+fn find_user(conn: &DbConn, id: i32) -> CfpResult<User> {
+    match User::get(conn, id) {
+        Ok(user) => Ok(user),
+        Err(Error::NotFound) => Err(Status::NotFound.into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Lists all users, along with the number of talks they present.
+#[get("/admin/users")]
+fn list_users(conn: DbConn, admin: Admin, csrf: CsrfToken) -> CfpResult<Template> {
+    let talk_counts = User::get_talk_counts(&conn, &admin)?;
+    let users: Vec<_> = User::get_all(&conn)?
+        .into_iter()
+        .map(|user| {
+            let talks = talk_counts.get(&user.id).cloned().unwrap_or(0);
+            json!({ "user": user, "talks": talks })
+        })
+        .collect();
+    Ok(Template::render("users", json!({
+        "user": admin.0,
+        "users": users,
+        "csrf_token": csrf,
+    })))
+}
+
+/// Makes a user an admin, i.e. grants them the organizer role.
+#[put("/admin/users/<id>/admin")]
+fn promote_user(id: i32, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let user = find_user(&conn, id)?;
+    conn.0.transaction::<_, Error, _>(|| {
+        Role::grant(&conn, &admin, user.id, Role::Organizer)?;
+        AdminEvent::record(&conn, &admin.0, &format!("made {} an admin", user.login))
+    })?;
+    info!("{} made {} an admin", admin.0.login, user.login);
+    Ok(Redirect::to("/admin/users"))
+}
+
+/// Revokes the organizer role from a user. Admins can't demote themselves,
+/// so that there is always at least one admin left.
+#[delete("/admin/users/<id>/admin")]
+fn demote_user(id: i32, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    if id == admin.0.id {
+        return Err(Status::Conflict.into());
+    }
+    let user = find_user(&conn, id)?;
+    let revoked = conn.0.transaction::<_, Error, _>(|| {
+        if !Role::revoke(&conn, &admin, id, Role::Organizer)? {
+            return Ok(false);
+        }
+        AdminEvent::record(&conn, &admin.0, &format!("revoked admin from {}", user.login))?;
+        Ok(true)
+    })?;
+    if !revoked {
+        return Err(Status::NotFound.into());
+    }
+    info!("{} revoked admin from user {}", admin.0.login, id);
+    Ok(Redirect::to("/admin/users"))
+}
+
+/// Disables a user's account, logging them out everywhere. Admins can't
+/// disable themselves.
+#[put("/admin/users/<id>/disabled")]
+fn disable_user(id: i32, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    if id == admin.0.id {
+        return Err(Status::Conflict.into());
+    }
+    let user = find_user(&conn, id)?;
+    conn.0.transaction::<_, Error, _>(|| {
+        User::set_disabled(&conn, &admin, id, true)?;
+        AdminEvent::record(&conn, &admin.0, &format!("disabled {}", user.login))
+    })?;
+    info!("{} disabled user {}", admin.0.login, id);
+    Ok(Redirect::to("/admin/users"))
+}
+
+/// Enables a disabled account again.
+#[delete("/admin/users/<id>/disabled")]
+fn enable_user(id: i32, conn: DbConn, admin: Admin) -> CfpResult<Redirect> {
+    let user = find_user(&conn, id)?;
+    conn.0.transaction::<_, Error, _>(|| {
+        User::set_disabled(&conn, &admin, id, false)?;
+        AdminEvent::record(&conn, &admin.0, &format!("enabled {}", user.login))
+    })?;
+    info!("{} enabled user {}", admin.0.login, id);
+    Ok(Redirect::to("/admin/users"))
+}
+
+/// A struct containing the login of the account to merge a user into.
+#[derive(FromForm)]
+struct MergeTarget {
+    target: String,
+}
+
+/// Merges a duplicate account into the account with the given login, which
+/// takes over its talks, reviews and roles. The duplicate is deleted, so
+/// admins can't merge their own account away.
+#[post("/admin/users/<id>/merge", data = "<merge>")]
+fn merge_user(
+    id: i32,
+    merge: LenientForm<MergeTarget>,
+    conn: DbConn,
+    admin: Admin,
+    dir: State<AvatarDir>,
+) -> CfpResult<Redirect> {
+    let source = find_user(&conn, id)?;
+    let target = User::get_by_login(&conn, merge.target.trim())?
+        .ok_or_else(|| CfpError::new(Status::UnprocessableEntity, format!("There is no user {}.", merge.target.trim())))?;
+    if source.id == target.id {
+        return Err(CfpError::new(Status::Conflict, "An account can't be merged into itself."));
+    }
+    if source.id == admin.0.id {
+        return Err(CfpError::new(Status::Conflict, "You can't merge your own account into another one."));
+    }
+
+    let avatars = vec![Profile::get(&conn, &source)?.avatar, Profile::get(&conn, &target)?.avatar];
+    conn.0.transaction::<_, Error, _>(|| {
+        User::merge(&conn, &admin, &source, &target)?;
+        AdminEvent::record(&conn, &admin.0, &format!("merged {} into {}", source.login, target.login))
+    })?;
+    let kept = Profile::get(&conn, &target)?.avatar;
+    for avatar in avatars.into_iter().flatten().filter(|avatar| Some(avatar) != kept.as_ref()) {
+        remove_avatar_file(&dir, &avatar);
+    }
+
+    info!("{} merged {} into {}", admin.0.login, source.login, target.login);
+    Ok(Redirect::to("/admin/users"))
+}
+
+/// Deletes a user along with their pending talks. Users whose talks were
+/// already decided on are kept for the record, and can only be disabled.
+#[delete("/admin/users/<id>")]
+fn delete_user(id: i32, conn: DbConn, admin: Admin, dir: State<AvatarDir>) -> CfpResult<Redirect> {
+    if id == admin.0.id {
+        return Err(Status::Conflict.into());
+    }
+    let user = find_user(&conn, id)?;
+    let avatar = Profile::get(&conn, &user)?.avatar;
+    let outcome = conn.0.transaction::<_, Error, _>(|| {
+        let outcome = User::delete(&conn, &admin, id)?;
+        if outcome == DeleteOutcome::Deleted {
+            AdminEvent::record(&conn, &admin.0, &format!("deleted {}", user.login))?;
+        }
+        Ok(outcome)
+    })?;
+    match outcome {
+        DeleteOutcome::Deleted => {
+            if let Some(avatar) = avatar {
+                remove_avatar_file(&dir, &avatar);
+            }
+            info!("{} deleted user {}", admin.0.login, user.login);
+            Ok(Redirect::to("/admin/users"))
+        }
+        DeleteOutcome::NotFound => Err(Status::NotFound.into()),
+        DeleteOutcome::HasHistory => Err(CfpError::new(
+            Status::Conflict,
+            format!(
+                "{} presents or reviewed talks that were already decided on, changed the status of \
+                 other talks, or administered the CFP. Disable the account instead.",
+                user.login
+            ),
+        )),
+    }
+}
+
+/// Returns a fairing that mounts the user management routes.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("User Management", |rocket| {
+        Ok(rocket.mount("/", routes![
+            list_users,
+            promote_user,
+            demote_user,
+            disable_user,
+            enable_user,
+            merge_user,
+            delete_user,
+        ]))
+    })
+}
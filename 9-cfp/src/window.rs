@@ -0,0 +1,177 @@
+use std::sync::RwLock;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use rocket::config::{self, Config, ConfigError};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::RawStr;
+use rocket::request::{FromFormValue, LenientForm, State};
+use rocket::response::Redirect;
+
+use audit::AdminEvent;
+use error::CfpResult;
+use event::Event;
+use role::Admin;
+use DbConn;
+
+/// Parses a timestamp either in RFC 3339 format or in the format submitted by
+/// an HTML `datetime-local` input (`2018-08-24T18:00`, or with seconds when the
+/// input was filled in with them), which is taken as UTC.
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .map(|naive| DateTime::from_utc(naive, Utc))
+}
+
+/// Reads an optional timestamp from the configuration parameter `name`.
+fn get_timestamp(config: &Config, name: &str) -> config::Result<Option<DateTime<Utc>>> {
+    match config.get_str(name) {
+        Ok(value) => parse_timestamp(value)
+            .map(Some)
+            .ok_or_else(|| ConfigError::BadType(name.into(), "an RFC 3339 timestamp", "string", None)),
+        Err(ConfigError::Missing(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Formats `remaining` as a human-readable countdown, e.g. "3 days, 4 hours".
+fn format_countdown(remaining: Duration) -> String {
+    let days = remaining.num_days();
+    let hours = remaining.num_hours() % 24;
+    let minutes = remaining.num_minutes() % 60;
+    match (days, hours) {
+        (0, 0) => format!("{} minutes", minutes),
+        (0, _) => format!("{} hours, {} minutes", hours, minutes),
+        _ => format!("{} days, {} hours", days, hours),
+    }
+}
+
+/// The call-for-papers submission window. Either end may be left open. The
+/// closing time can be extended by an administrator while the server runs.
+///
+/// The configured window applies to every event that doesn't set its own.
+pub struct CfpWindow {
+    opens: Option<DateTime<Utc>>,
+    closes: RwLock<Option<DateTime<Utc>>>,
+}
+
+/// A snapshot of the submission window, suitable for rendering in templates.
+#[derive(Serialize)]
+pub struct WindowStatus {
+    pub opens: Option<DateTime<Utc>>,
+    pub closes: Option<DateTime<Utc>>,
+    pub is_open: bool,
+    pub countdown: Option<String>,
+}
+
+impl CfpWindow {
+    /// Reads the `cfp_opens` and `cfp_closes` configuration parameters.
+    pub fn from_config(config: &Config) -> config::Result<CfpWindow> {
+        let opens = get_timestamp(config, "cfp_opens")?;
+        let closes = get_timestamp(config, "cfp_closes")?;
+        Ok(CfpWindow { opens, closes: RwLock::new(closes) })
+    }
+
+    /// Returns the current closing time of the window.
+    pub fn closes(&self) -> Option<DateTime<Utc>> {
+        *self.closes.read().expect("CFP window lock")
+    }
+
+    /// Moves the closing time of the window to `closes`.
+    pub fn set_closes(&self, closes: DateTime<Utc>) {
+        *self.closes.write().expect("CFP window lock") = Some(closes);
+    }
+
+    /// Returns whether submissions are accepted at time `now`.
+    pub fn is_open_at(&self, now: DateTime<Utc>) -> bool {
+        self.opens.map_or(true, |opens| opens <= now)
+            && self.closes().map_or(true, |closes| now < closes)
+    }
+
+    /// Returns whether `event` accepts submissions at the current time, by its
+    /// own window.
+    pub fn is_open_for(&self, event: &Event) -> bool {
+        self.for_event(event).is_open_at(Utc::now())
+    }
+
+    /// Returns the window of `event`: its own opening and closing times where
+    /// it sets them, and this window's otherwise.
+    pub fn for_event(&self, event: &Event) -> CfpWindow {
+        let utc = |timestamp: NaiveDateTime| DateTime::from_utc(timestamp, Utc);
+        CfpWindow {
+            opens: event.cfp_opens.map(utc).or(self.opens),
+            closes: RwLock::new(event.cfp_closes.map(utc).or_else(|| self.closes())),
+        }
+    }
+
+    /// Returns the state of the window at the current time.
+    pub fn status(&self) -> WindowStatus {
+        let now = Utc::now();
+        let closes = self.closes();
+        let is_open = self.is_open_at(now);
+        let countdown = match (self.opens, closes) {
+            (Some(opens), _) if now < opens => Some(format_countdown(opens - now)),
+            (_, Some(closes)) if is_open => Some(format_countdown(closes - now)),
+            _ => None,
+        };
+
+        WindowStatus { opens: self.opens, closes, is_open, countdown }
+    }
+}
+
+/// A timestamp submitted through a web form.
+pub struct FormTimestamp(pub DateTime<Utc>);
+
+impl<'v> FromFormValue<'v> for FormTimestamp {
+    type Error = &'v RawStr;
+
+    fn from_form_value(value: &'v RawStr) -> Result<Self, Self::Error> {
+        let decoded = value.url_decode().map_err(|_| value)?;
+        parse_timestamp(&decoded).map(FormTimestamp).ok_or(value)
+    }
+}
+
+/// A struct containing the new closing time of the submission window.
+#[derive(FromForm)]
+struct NewDeadline {
+    closes: FormTimestamp,
+}
+
+/// Lets an administrator extend (or shorten) the submission deadline. The
+/// change only lasts until the server is restarted; update `cfp_closes` in
+/// `Rocket.toml` to make it permanent.
+#[put("/admin/deadline", data = "<deadline>")]
+fn set_deadline(
+    deadline: LenientForm<NewDeadline>,
+    window: State<CfpWindow>,
+    conn: DbConn,
+    admin: Admin,
+) -> CfpResult<Redirect> {
+    AdminEvent::record(&conn, &admin.0, &format!("moved the CFP deadline to {}", deadline.closes.0))?;
+    info!("{} moved the CFP deadline to {}", admin.0.login, deadline.closes.0);
+    window.set_closes(deadline.closes.0);
+    Ok(Redirect::to("/admin"))
+}
+
+/// Returns a fairing that sets up the submission window:
+///
+/// * Reads the window configuration.
+/// * Mounts the deadline override route.
+pub fn fairing() -> impl Fairing {
+    AdHoc::on_attach("CFP Window", |rocket| {
+        match CfpWindow::from_config(rocket.config()) {
+            Ok(window) => Ok(rocket
+                .manage(window)
+                .mount("/", routes![set_deadline])
+            ),
+            Err(e) => {
+                error!("Invalid CFP window configuration: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}